@@ -0,0 +1,70 @@
+//! Builds the firmware modules that don't depend on the AVR for the host, so that their unit
+//! tests run with `cargo test`. The modules are included from the firmware's source tree at the
+//! same module paths as in the firmware, so their `crate::` paths resolve the same way.
+//!
+//! The crate is `no_std` like the firmware, so the modules build against the same `core` APIs.
+//! Hardware dependent modules that the included modules use, such as the `millis()` clock and
+//! the console log, are replaced by mocks, and so are modules that need the firmware's fork of
+//! `ufmt`. The fork adds `f32` formatting, so the `uDisplay` implementations that format `f32`
+//! values are only built for the AVR, and the imports they use are unused on the host.
+#![no_std]
+#![allow(dead_code, unused_imports)]
+
+#[path = "../../src/drv8833"]
+pub mod drv8833 {
+    pub mod motor_controller;
+}
+
+#[path = "../../src/l298n"]
+pub mod l298n {
+    pub mod motor_controller;
+}
+
+#[path = "../../src/model"]
+pub mod model {
+    pub mod bang_bang_controller;
+    pub mod battery;
+    pub mod controller;
+    pub mod lead_lag_controller;
+    pub mod motor_calibration;
+    pub mod motor_characterization;
+    pub mod motor_output_limiter;
+    pub mod motor_power;
+    pub mod pid_controller;
+    pub mod relay_autotune;
+    pub mod stall_timer;
+}
+
+#[path = "../../src/motor_driver.rs"]
+pub mod motor_driver;
+
+#[path = "../../src/shell"]
+pub mod shell {
+    pub mod parser;
+}
+
+#[path = "../../src/system"]
+pub mod system {
+    #[path = "../../host-tests/src/mock_log.rs"]
+    pub mod log;
+    pub mod log_level;
+    #[path = "../../host-tests/src/mock_millis.rs"]
+    pub mod millis;
+    pub mod ring_buffer;
+    pub mod run_files;
+    pub mod scheduler;
+    pub mod time;
+}
+
+#[path = "../../src/tb6612fng"]
+pub mod tb6612fng {
+    pub mod motor_controller;
+}
+
+#[path = "../../src/telemetry"]
+pub mod telemetry {
+    pub mod binary;
+    pub mod recorder;
+    #[path = "../../host-tests/src/mock_telemetry_row.rs"]
+    pub mod telemetry_row;
+}
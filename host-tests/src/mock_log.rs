@@ -0,0 +1,37 @@
+//! Stands in for the firmware's `system::log` module on the host, where there is no console. The
+//! logging macros discard their messages. The arguments are still evaluated by reference, so the
+//! code that logs builds the same way, but they aren't formatted.
+pub use super::log_level::LogLevel;
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $message:literal $(, $arg:expr)* $(,)?) => {{
+        let _: $crate::system::log::LogLevel = $level;
+        $(let _ = &$arg;)*
+    }};
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Error, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Warn, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Info, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Debug, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Trace, $($t)*) };
+}
@@ -0,0 +1,41 @@
+//! Stands in for the firmware's `system::millis` module on the host. The clock only moves when a
+//! test sets or advances it. Each test thread has its own clock, so tests that run in parallel
+//! don't disturb each other.
+extern crate std;
+
+use core::cell::Cell;
+
+std::thread_local! {
+    static MILLIS: Cell<u32> = const { Cell::new(0) };
+    static MICROS: Cell<u32> = const { Cell::new(0) };
+}
+
+pub fn millis() -> u32 {
+    MILLIS.with(|millis| millis.get())
+}
+
+pub fn micros() -> u32 {
+    MICROS.with(|micros| micros.get())
+}
+
+/// Sets the clock to `millis` milliseconds. The microsecond clock is set to the same time, which
+/// wraps around much sooner.
+pub fn set_millis(millis: u32) {
+    MILLIS.with(|clock| clock.set(millis));
+    MICROS.with(|clock| clock.set(millis.wrapping_mul(1000)));
+}
+
+/// Advances the clock by `millis` milliseconds, wrapping around like the firmware's clock.
+pub fn advance_millis(millis: u32) {
+    advance_micros(millis.wrapping_mul(1000));
+}
+
+/// Advances the clock by `micros` microseconds. The millisecond clock advances by the whole
+/// milliseconds that pass.
+pub fn advance_micros(micros: u32) {
+    let old_micros = self::micros();
+    let new_micros = old_micros.wrapping_add(micros);
+    MICROS.with(|clock| clock.set(new_micros));
+    let millis_passed = ((old_micros % 1000) as u64 + micros as u64) / 1000;
+    MILLIS.with(|clock| clock.set(clock.get().wrapping_add(millis_passed as u32)));
+}
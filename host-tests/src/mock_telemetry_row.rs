@@ -0,0 +1,13 @@
+//! Stands in for the firmware's `telemetry::telemetry_row` module on the host. The firmware's
+//! row fields are displayed with the `f32` support of its `ufmt` fork, which the host build
+//! doesn't have, so only the binary record part of the `TelemetryRow` trait is kept.
+
+/// A row of a telemetry table that can be written as a binary record.
+pub trait TelemetryRow {
+    /// The size of the row's binary record in bytes.
+    const BINARY_SIZE: usize;
+
+    /// Writes the row's fields to `buf` as little-endian values in column order and returns the
+    /// number of bytes written, which is `BINARY_SIZE`.
+    fn write_binary(&self, buf: &mut [u8]) -> usize;
+}
@@ -0,0 +1,181 @@
+use embedded_hal::PwmPin;
+
+use crate::motor_driver::{DualMotorDriver, MotorChannel, MotorPins};
+
+/// The two PWM inputs of one DRV8833 motor channel.
+pub struct PwmInputPins<IN1, IN2> {
+    in1: IN1,
+    in2: IN2,
+}
+
+impl<IN1, IN2> PwmInputPins<IN1, IN2>
+where
+    IN1: PwmPin<Duty = u8>,
+    IN2: PwmPin<Duty = u8>,
+{
+    pub fn new(in1: IN1, in2: IN2) -> Self {
+        Self { in1, in2 }
+    }
+}
+
+impl<IN1, IN2> MotorPins for PwmInputPins<IN1, IN2>
+where
+    IN1: PwmPin<Duty = u8>,
+    IN2: PwmPin<Duty = u8>,
+{
+    /// Both inputs low lets the motor coast.
+    fn drive(&mut self, output: i16) {
+        let duty = output.unsigned_abs() as u8;
+        if output > 0 {
+            release_input(&mut self.in2);
+            self.in1.set_duty(duty);
+            self.in1.enable();
+        } else if output < 0 {
+            release_input(&mut self.in1);
+            self.in2.set_duty(duty);
+            self.in2.enable();
+        } else {
+            release_input(&mut self.in1);
+            release_input(&mut self.in2);
+        }
+    }
+
+    /// The DRV8833 brakes a motor when both of its inputs are high.
+    fn brake(&mut self) {
+        self.in1.set_duty(self.in1.get_max_duty());
+        self.in2.set_duty(self.in2.get_max_duty());
+        self.in1.enable();
+        self.in2.enable();
+    }
+
+    /// Returns the duty applied to whichever of the inputs is being driven.
+    fn get_duty(&self) -> u8 {
+        self.in1.get_duty().max(self.in2.get_duty())
+    }
+
+    fn get_max_duty(&self) -> u8 {
+        self.in1.get_max_duty()
+    }
+}
+
+/// Holds an input low. The duty is zeroed too so that `get_duty` doesn't report it.
+fn release_input<IN: PwmPin<Duty = u8>>(input: &mut IN) {
+    input.set_duty(0);
+    input.disable();
+}
+
+/// Controls two motors through a DRV8833 dual H-bridge. Each motor has two PWM inputs and
+/// there is no enable pin. The motor is driven with fast decay: the PWM is applied to the input of
+/// the driven direction while the other input is held low.
+pub struct MotorController<AIN1, AIN2, BIN1, BIN2> {
+    channel_a: MotorChannel<PwmInputPins<AIN1, AIN2>>,
+    channel_b: MotorChannel<PwmInputPins<BIN1, BIN2>>,
+}
+
+#[allow(dead_code)]
+impl<AIN1, AIN2, BIN1, BIN2> MotorController<AIN1, AIN2, BIN1, BIN2>
+where
+    AIN1: PwmPin<Duty = u8>,
+    AIN2: PwmPin<Duty = u8>,
+    BIN1: PwmPin<Duty = u8>,
+    BIN2: PwmPin<Duty = u8>,
+{
+    pub fn new(ain1: AIN1, ain2: AIN2, bin1: BIN1, bin2: BIN2) -> Self {
+        Self {
+            channel_a: MotorChannel::new(PwmInputPins::new(ain1, ain2)),
+            channel_b: MotorChannel::new(PwmInputPins::new(bin1, bin2)),
+        }
+    }
+}
+
+impl<AIN1, AIN2, BIN1, BIN2> DualMotorDriver for MotorController<AIN1, AIN2, BIN1, BIN2>
+where
+    AIN1: PwmPin<Duty = u8>,
+    AIN2: PwmPin<Duty = u8>,
+    BIN1: PwmPin<Duty = u8>,
+    BIN2: PwmPin<Duty = u8>,
+{
+    type PinsA = PwmInputPins<AIN1, AIN2>;
+    type PinsB = PwmInputPins<BIN1, BIN2>;
+
+    fn channel_a(&self) -> &MotorChannel<Self::PinsA> {
+        &self.channel_a
+    }
+
+    fn channel_a_mut(&mut self) -> &mut MotorChannel<Self::PinsA> {
+        &mut self.channel_a
+    }
+
+    fn channel_b(&self) -> &MotorChannel<Self::PinsB> {
+        &self.channel_b
+    }
+
+    fn channel_b_mut(&mut self) -> &mut MotorChannel<Self::PinsB> {
+        &mut self.channel_b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motor_driver::mock::{MockPwmPin, PinTransaction as T};
+    use crate::system::time::Instant;
+
+    #[test]
+    fn drives_one_input_and_holds_the_other_low() {
+        let mut ain1 = MockPwmPin::new(&[
+            T::get_max_duty(255),
+            T::set_duty(80),
+            T::enable(),
+            T::get_duty(80),
+            T::set_duty(40),
+            T::enable(),
+            T::set_duty(0),
+            T::disable(),
+            T::get_duty(0),
+            T::set_duty(0),
+            T::disable(),
+        ]);
+        let mut ain2 = MockPwmPin::new(&[
+            T::set_duty(0),
+            T::disable(),
+            T::get_duty(0),
+            T::set_duty(0),
+            T::disable(),
+            T::set_duty(40),
+            T::enable(),
+            T::get_duty(40),
+            T::set_duty(0),
+            T::disable(),
+        ]);
+        let mut bin1 = MockPwmPin::new(&[]);
+        let mut bin2 = MockPwmPin::new(&[]);
+        let mut motors =
+            MotorController::new(ain1.clone(), ain2.clone(), bin1.clone(), bin2.clone());
+        motors.set_speed_a(80);
+        assert_eq!(motors.get_duty_a(), 80);
+        motors.set_duty_a(40);
+        motors.reverse_a();
+        assert_eq!(motors.get_duty_a(), 40);
+        motors.stop_a();
+
+        for pin in [&mut ain1, &mut ain2, &mut bin1, &mut bin2] {
+            pin.done();
+        }
+    }
+
+    #[test]
+    fn brakes_with_both_inputs_high() {
+        let mut ain1 = MockPwmPin::new(&[]);
+        let mut ain2 = MockPwmPin::new(&[]);
+        let mut bin1 = MockPwmPin::new(&[T::get_max_duty(255), T::set_duty(255), T::enable()]);
+        let mut bin2 = MockPwmPin::new(&[T::get_max_duty(255), T::set_duty(255), T::enable()]);
+        let mut motors =
+            MotorController::new(ain1.clone(), ain2.clone(), bin1.clone(), bin2.clone());
+        motors.brake_b(Instant::from_millis(0));
+
+        for pin in [&mut ain1, &mut ain2, &mut bin1, &mut bin2] {
+            pin.done();
+        }
+    }
+}
@@ -1,125 +1,154 @@
 use embedded_hal::digital::v2::OutputPin;
 use embedded_hal::PwmPin;
 
+use crate::motor_driver::{DirectionPwmPins, DualMotorDriver, MotorChannel};
+
+/// Controls two motors through an L298N dual H-bridge. Each motor has two direction inputs and
+/// a PWM enable pin.
 pub struct MotorController<INA1, INA2, INB1, INB2, ENA, ENB> {
-    ina1: INA1,
-    ina2: INA2,
-    inb1: INB1,
-    inb2: INB2,
-    ena: ENA,
-    enb: ENB,
+    channel_a: MotorChannel<DirectionPwmPins<INA1, INA2, ENA>>,
+    channel_b: MotorChannel<DirectionPwmPins<INB1, INB2, ENB>>,
 }
 
-#[allow(dead_code)]
 impl<INA1, INA2, INB1, INB2, ENA, ENB> MotorController<INA1, INA2, INB1, INB2, ENA, ENB>
 where
     INA1: OutputPin,
     INA2: OutputPin,
     INB1: OutputPin,
     INB2: OutputPin,
-    ENA: PwmPin,
-    ENB: PwmPin,
+    ENA: PwmPin<Duty = u8>,
+    ENB: PwmPin<Duty = u8>,
 {
-    pub fn new(ina1: INA1, ina2: INA2, inb1: INB1, inb2: INB2, ena: ENA, enb: ENB) -> Self
-    where
-        INA1: OutputPin,
-        INA2: OutputPin,
-        INB1: OutputPin,
-        INB2: OutputPin,
-        ENA: PwmPin,
-        ENB: PwmPin,
-    {
+    pub fn new(ina1: INA1, ina2: INA2, inb1: INB1, inb2: INB2, ena: ENA, enb: ENB) -> Self {
         Self {
-            ina1,
-            ina2,
-            inb1,
-            inb2,
-            ena,
-            enb,
+            channel_a: MotorChannel::new(DirectionPwmPins::new(ina1, ina2, ena)),
+            channel_b: MotorChannel::new(DirectionPwmPins::new(inb1, inb2, enb)),
         }
     }
+}
 
-    pub fn set_duty(&mut self, duty_a: ENA::Duty, duty_b: ENB::Duty) {
-        self.ena.set_duty(duty_a);
-        self.enb.set_duty(duty_b);
-    }
-
-    pub fn set_duty_a(&mut self, duty: ENA::Duty) {
-        self.ena.set_duty(duty);
-    }
-
-    pub fn set_duty_b(&mut self, duty: ENB::Duty) {
-        self.enb.set_duty(duty);
-    }
-
-    pub fn get_duty_a(&self) -> ENA::Duty {
-        self.ena.get_duty()
-    }
-
-    pub fn get_duty_b(&self) -> ENB::Duty {
-        self.enb.get_duty()
-    }
-
-    pub fn forward(&mut self) {
-        self.ina1.set_high().ok();
-        self.ina2.set_low().ok();
-        self.inb1.set_high().ok();
-        self.inb2.set_low().ok();
-        self.ena.enable();
-        self.enb.enable();
-    }
-
-    pub fn forward_a(&mut self) {
-        self.ina1.set_high().ok();
-        self.ina2.set_low().ok();
-        self.ena.enable();
-    }
-
-    pub fn forward_b(&mut self) {
-        self.inb1.set_high().ok();
-        self.inb2.set_low().ok();
-        self.enb.enable();
-    }
+impl<INA1, INA2, INB1, INB2, ENA, ENB> DualMotorDriver
+    for MotorController<INA1, INA2, INB1, INB2, ENA, ENB>
+where
+    INA1: OutputPin,
+    INA2: OutputPin,
+    INB1: OutputPin,
+    INB2: OutputPin,
+    ENA: PwmPin<Duty = u8>,
+    ENB: PwmPin<Duty = u8>,
+{
+    type PinsA = DirectionPwmPins<INA1, INA2, ENA>;
+    type PinsB = DirectionPwmPins<INB1, INB2, ENB>;
 
-    pub fn reverse(&mut self) {
-        self.ina1.set_low().ok();
-        self.ina2.set_high().ok();
-        self.inb1.set_low().ok();
-        self.inb2.set_high().ok();
-        self.ena.enable();
-        self.enb.enable();
+    fn channel_a(&self) -> &MotorChannel<Self::PinsA> {
+        &self.channel_a
     }
 
-    pub fn reverse_a(&mut self) {
-        self.ina1.set_low().ok();
-        self.ina2.set_high().ok();
-        self.ena.enable();
+    fn channel_a_mut(&mut self) -> &mut MotorChannel<Self::PinsA> {
+        &mut self.channel_a
     }
 
-    pub fn reverse_b(&mut self) {
-        self.inb1.set_low().ok();
-        self.inb2.set_high().ok();
-        self.enb.enable();
+    fn channel_b(&self) -> &MotorChannel<Self::PinsB> {
+        &self.channel_b
     }
 
-    pub fn stop(&mut self) {
-        self.ena.disable();
-        self.enb.disable();
-        self.ina1.set_low().ok();
-        self.ina2.set_low().ok();
-        self.inb1.set_low().ok();
-        self.inb2.set_low().ok();
+    fn channel_b_mut(&mut self) -> &mut MotorChannel<Self::PinsB> {
+        &mut self.channel_b
     }
+}
 
-    pub fn stop_a(&mut self) {
-        self.ena.disable();
-        self.ina1.set_low().ok();
-        self.ina2.set_low().ok();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motor_driver::mock::{MockOutputPin, MockPwmPin, PinTransaction as T, State};
+    use crate::system::time::Instant;
+
+    #[test]
+    fn drives_both_motors_through_their_inputs_and_enable_pins() {
+        // set_duty applies the duty while the motors coast, then forward drives them
+        let mut ina1 =
+            MockOutputPin::new(&[T::set(State::Low), T::set(State::High), T::set(State::High)]);
+        let mut ina2 =
+            MockOutputPin::new(&[T::set(State::Low), T::set(State::Low), T::set(State::High)]);
+        let mut inb1 =
+            MockOutputPin::new(&[T::set(State::Low), T::set(State::Low), T::set(State::High)]);
+        let mut inb2 =
+            MockOutputPin::new(&[T::set(State::Low), T::set(State::High), T::set(State::High)]);
+        let mut ena = MockPwmPin::new(&[
+            T::set_duty(0),
+            T::disable(),
+            T::set_duty(120),
+            T::enable(),
+            T::get_duty(120),
+            T::get_max_duty(255),
+            T::set_duty(255),
+            T::enable(),
+        ]);
+        let mut enb = MockPwmPin::new(&[
+            T::set_duty(0),
+            T::disable(),
+            T::set_duty(90),
+            T::enable(),
+            T::get_duty(90),
+            T::get_max_duty(255),
+            T::set_duty(255),
+            T::enable(),
+        ]);
+        let mut motors = MotorController::new(
+            ina1.clone(),
+            ina2.clone(),
+            inb1.clone(),
+            inb2.clone(),
+            ena.clone(),
+            enb.clone(),
+        );
+
+        motors.set_duty(120, 90);
+        motors.forward_a();
+        motors.reverse_b();
+        assert_eq!(motors.get_duty_a(), 120);
+        assert_eq!(motors.get_duty_b(), 90);
+        motors.brake(Instant::from_millis(100));
+
+        for pin in [&mut ina1, &mut ina2, &mut inb1, &mut inb2] {
+            pin.done();
+        }
+        ena.done();
+        enb.done();
     }
 
-    pub fn stop_b(&mut self) {
-        self.enb.disable();
-        self.inb1.set_low().ok();
-        self.inb2.set_low().ok();
+    #[test]
+    fn update_ramps_a_slew_rate_limited_motor() {
+        let mut in1 = MockOutputPin::new(&[T::set(State::High), T::set(State::High)]);
+        let mut in2 = MockOutputPin::new(&[T::set(State::Low), T::set(State::Low)]);
+        let mut ena = MockPwmPin::new(&[
+            T::get_max_duty(255),
+            T::set_duty(5),
+            T::enable(),
+            T::set_duty(55),
+            T::enable(),
+        ]);
+        let mut inb1 = MockOutputPin::new(&[]);
+        let mut inb2 = MockOutputPin::new(&[]);
+        let mut enb = MockPwmPin::new(&[]);
+        let mut motors = MotorController::new(
+            in1.clone(),
+            in2.clone(),
+            inb1.clone(),
+            inb2.clone(),
+            ena.clone(),
+            enb.clone(),
+        );
+        motors.set_slew_rate_limit(5);
+        motors.update(Instant::from_millis(1000));
+        motors.set_speed_a(200);
+        // motor B isn't limiting, so the update doesn't touch its pins
+        motors.update(Instant::from_millis(1010));
+
+        for pin in [&mut in1, &mut in2, &mut inb1, &mut inb2] {
+            pin.done();
+        }
+        ena.done();
+        enb.done();
     }
 }
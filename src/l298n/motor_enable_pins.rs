@@ -1,7 +1,11 @@
-use arduino_hal::hal::port::{PH3, PH4, PH5};
+use arduino_hal::hal::port::{
+    PB4, PB5, PB6, PB7, PE3, PE4, PE5, PG5, PH3, PH4, PH5, PH6, PL3, PL4, PL5,
+};
 use arduino_hal::port::mode::PwmOutput;
 use arduino_hal::port::Pin;
-use arduino_hal::simple_pwm::Timer4Pwm;
+use arduino_hal::simple_pwm::{
+    Prescaler, Timer0Pwm, Timer1Pwm, Timer2Pwm, Timer3Pwm, Timer4Pwm, Timer5Pwm,
+};
 use embedded_hal::PwmPin;
 
 /// This is just glue code so that we can use the abstract PwmPin trait in the l298n struct
@@ -12,74 +16,91 @@ impl<PIN, TIMER> MotorEnablePin<PIN, TIMER> {
     }
 }
 
-impl PwmPin for MotorEnablePin<PH3, Timer4Pwm> {
-    type Duty = u8;
-
-    fn disable(&mut self) {
-        self.0.disable();
-    }
-
-    fn enable(&mut self) {
-        self.0.enable();
-    }
-
-    fn get_duty(&self) -> Self::Duty {
-        self.0.get_duty()
-    }
-
-    fn get_max_duty(&self) -> Self::Duty {
-        self.0.get_max_duty()
-    }
-
-    fn set_duty(&mut self, duty: Self::Duty) {
-        self.0.set_duty(duty);
-    }
+/// Implements `PwmPin` for `MotorEnablePin` on each of the listed pins of a PWM timer.
+macro_rules! impl_motor_enable_pwm_pin {
+    ($($timer:ty => [$($pin:ty),+ $(,)?]),+ $(,)?) => {
+        $($(
+            impl PwmPin for MotorEnablePin<$pin, $timer> {
+                type Duty = u8;
+
+                fn disable(&mut self) {
+                    self.0.disable();
+                }
+
+                fn enable(&mut self) {
+                    self.0.enable();
+                }
+
+                fn get_duty(&self) -> Self::Duty {
+                    self.0.get_duty()
+                }
+
+                fn get_max_duty(&self) -> Self::Duty {
+                    self.0.get_max_duty()
+                }
+
+                fn set_duty(&mut self, duty: Self::Duty) {
+                    self.0.set_duty(duty);
+                }
+            }
+        )+)+
+    };
 }
 
-impl PwmPin for MotorEnablePin<PH4, Timer4Pwm> {
-    type Duty = u8;
-
-    fn disable(&mut self) {
-        self.0.disable();
-    }
-
-    fn enable(&mut self) {
-        self.0.enable();
-    }
-
-    fn get_duty(&self) -> Self::Duty {
-        self.0.get_duty()
-    }
-
-    fn get_max_duty(&self) -> Self::Duty {
-        self.0.get_max_duty()
-    }
-
-    fn set_duty(&mut self, duty: Self::Duty) {
-        self.0.set_duty(duty);
-    }
+// Every PWM capable pin of the ATmega2560 and the timer that drives it. Note that the firmware
+// uses TC0 for `millis()`, which reconfigures the timer, so the Timer0 pins can't be used for
+// the motors.
+impl_motor_enable_pwm_pin! {
+    Timer0Pwm => [PB7, PG5],
+    Timer1Pwm => [PB5, PB6, PB7],
+    Timer2Pwm => [PB4, PH6],
+    Timer3Pwm => [PE3, PE4, PE5],
+    Timer4Pwm => [PH3, PH4, PH5],
+    Timer5Pwm => [PL3, PL4, PL5],
 }
 
-impl PwmPin for MotorEnablePin<PH5, Timer4Pwm> {
-    type Duty = u8;
-
-    fn disable(&mut self) {
-        self.0.disable();
-    }
-
-    fn enable(&mut self) {
-        self.0.enable();
-    }
-
-    fn get_duty(&self) -> Self::Duty {
-        self.0.get_duty()
-    }
-
-    fn get_max_duty(&self) -> Self::Duty {
-        self.0.get_max_duty()
-    }
+/// The PWM frequencies available for the motor enable pins. The PWM timers run in 8-bit fast PWM
+/// mode off of the 16 MHz clock, so the frequency is 16 MHz / (256 * prescaler).
+///
+/// The lower frequencies make the motors whine audibly. `Hz62500` moves the whine out of the
+/// audible range, but switching losses in the motor driver increase with the frequency.
+#[allow(dead_code)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MotorPwmFrequency {
+    Hz61,
+    Hz244,
+    Hz976,
+    Hz7812,
+    /// Above the L298N's `L298N_MAX_PWM_FREQUENCY`, so only use it with a motor driver that
+    /// switches this fast, such as the TB6612FNG or the DRV8833.
+    Hz62500,
+}
 
-    fn set_duty(&mut self, duty: Self::Duty) {
-        self.0.set_duty(duty);
+/// The highest PWM frequency that the L298N is specified for, in hertz.
+pub const L298N_MAX_PWM_FREQUENCY: u32 = 40_000;
+
+#[allow(dead_code)]
+impl MotorPwmFrequency {
+    /// The timer prescaler that produces this frequency. Pass it to the timer's `new` function,
+    /// such as `Timer4Pwm::new(dp.TC4, frequency.prescaler())`.
+    pub fn prescaler(&self) -> Prescaler {
+        match self {
+            MotorPwmFrequency::Hz61 => Prescaler::Prescale1024,
+            MotorPwmFrequency::Hz244 => Prescaler::Prescale256,
+            MotorPwmFrequency::Hz976 => Prescaler::Prescale64,
+            MotorPwmFrequency::Hz7812 => Prescaler::Prescale8,
+            MotorPwmFrequency::Hz62500 => Prescaler::Direct,
+        }
+    }
+
+    /// The PWM frequency in hertz.
+    pub const fn hertz(&self) -> u32 {
+        match self {
+            MotorPwmFrequency::Hz61 => 61,
+            MotorPwmFrequency::Hz244 => 244,
+            MotorPwmFrequency::Hz976 => 976,
+            MotorPwmFrequency::Hz7812 => 7812,
+            MotorPwmFrequency::Hz62500 => 62500,
+        }
     }
 }
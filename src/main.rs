@@ -2,25 +2,70 @@
 #![no_main]
 #![feature(abi_avr_interrupt)]
 
+#[allow(dead_code)]
+mod drv8833;
 mod l298n;
 mod model;
+mod motor_driver;
 mod robot;
+mod shell;
 mod system;
+#[allow(dead_code)]
+mod tb6612fng;
 mod telemetry;
 
 use arduino_hal::{
     pac::Peripherals,
-    simple_pwm::{IntoPwmPin, Prescaler, Timer4Pwm},
+    simple_pwm::{IntoPwmPin, Timer4Pwm},
 };
 use panic_halt as _;
 
 use robot::Robot;
+use shell::CommandShell;
 use system::{
-    millis::{millis, millis_init},
+    analog::put_adc,
+    battery_monitor::BatteryMonitor,
+    current_sensor::AdcCurrentSensor,
+    millis::millis_init,
+    scheduler::Scheduler,
+    sd_logger::{FixedTimeSource, SdLogger},
     serial_print::put_console,
+    time::Duration,
 };
+use telemetry::telemetry_format;
 
-use crate::l298n::motor_enable_pins::MotorEnablePin;
+use crate::l298n::{
+    motor_controller::MotorController,
+    motor_enable_pins::{MotorEnablePin, MotorPwmFrequency, L298N_MAX_PWM_FREQUENCY},
+};
+
+/// The PWM frequency of the motor enable pins.
+const MOTOR_PWM_FREQUENCY: MotorPwmFrequency = MotorPwmFrequency::Hz976;
+const _: () = assert!(
+    MOTOR_PWM_FREQUENCY.hertz() <= L298N_MAX_PWM_FREQUENCY,
+    "the motor PWM frequency is too high for the L298N"
+);
+/// The resistance of the shunt resistors on the L298N current sense pins, in ohms.
+const MOTOR_CURRENT_SHUNT_RESISTANCE: f32 = 0.5;
+/// The ratio of the battery voltage to the voltage at the battery monitor's analog input. The
+/// divider is a 20k resistor over a 10k resistor.
+const BATTERY_DIVIDER_RATIO: f32 = 3.0;
+/// How often the LED toggles.
+const LED_BLINK_PERIOD: Duration = Duration::from_secs(1);
+/// The LED blinks quickly while the battery is too low to move.
+const LOW_BATTERY_LED_BLINK_PERIOD: Duration = Duration::from_millis(150);
+/// The SD card must be initialized with a SPI clock of at most 400 kHz. 16 MHz / 64 = 250 kHz.
+const SD_CARD_INIT_SPI_CLOCK: arduino_hal::spi::SerialClockRate =
+    arduino_hal::spi::SerialClockRate::OscfOver64;
+/// Once initialized, the SD card runs at the fastest SPI clock the Mega has. 16 MHz / 2 = 8 MHz.
+const SD_CARD_SPI_CLOCK: arduino_hal::spi::SerialClockRate =
+    arduino_hal::spi::SerialClockRate::OscfOver2;
+
+/// The tasks that the main loop's scheduler runs.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum MainTask {
+    BlinkLed,
+}
 
 #[arduino_hal::entry]
 fn main() -> ! {
@@ -28,11 +73,12 @@ fn main() -> ! {
     let pins = arduino_hal::pins!(dp);
     let serial = arduino_hal::default_serial!(dp, pins, 57600);
     put_console(serial);
-    println!("Starting the Rust Robot! :D");
-    println!("Initializing millis");
+    println!("{}", F!("Starting the Rust Robot! :D"));
+    debug!("Initializing millis");
     millis_init(dp.TC0);
 
-    let timer4: Timer4Pwm = Timer4Pwm::new(dp.TC4, Prescaler::Prescale64);
+    let timer4: Timer4Pwm = Timer4Pwm::new(dp.TC4, MOTOR_PWM_FREQUENCY.prescaler());
+    println!("Motor PWM frequency = {} Hz", MOTOR_PWM_FREQUENCY.hertz());
 
     let i2c = arduino_hal::I2c::new(
         dp.TWI,
@@ -41,35 +87,124 @@ fn main() -> ! {
         50000,
     );
 
-    let mut robot = Robot::new(
+    // the motor current sense shunts are read on A0 (motor A) and A1 (motor B), and the battery
+    // voltage divider on A2
+    let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
+    let current_sensor = AdcCurrentSensor::new(
+        pins.a0.into_analog_input(&mut adc).into_channel(),
+        pins.a1.into_analog_input(&mut adc).into_channel(),
+        MOTOR_CURRENT_SHUNT_RESISTANCE,
+    );
+    let battery_channel = pins.a2.into_analog_input(&mut adc).into_channel();
+    put_adc(adc);
+    let battery_monitor = BatteryMonitor::new(battery_channel, BATTERY_DIVIDER_RATIO);
+
+    let motors = MotorController::new(
         pins.d4.into_output(),
         pins.d5.into_output(),
         pins.d2.into_output(),
         pins.d3.into_output(),
         MotorEnablePin::new(pins.d7.into_output().into_pwm(&timer4)),
         MotorEnablePin::new(pins.d6.into_output().into_pwm(&timer4)),
+    );
+
+    // the microSD module is on the hardware SPI pins, with its chip select on D53
+    let (spi, sd_card_cs) = arduino_hal::Spi::new(
+        dp.SPI,
+        pins.d52.into_output(),
+        pins.d51.into_output(),
+        pins.d50.into_pull_up_input(),
+        pins.d53.into_output(),
+        arduino_hal::spi::Settings {
+            clock: SD_CARD_INIT_SPI_CLOCK,
+            ..Default::default()
+        },
+    );
+    let sd_card = embedded_sdmmc::SdCard::new(spi, sd_card_cs, arduino_hal::Delay::new());
+    let mut telemetry_log = SdLogger::new(sd_card, FixedTimeSource, telemetry_format());
+    match telemetry_log.as_mut().and_then(|log| log.device()) {
+        // opening the volume initialized the card, so it can be clocked faster now
+        Some(sd_card) => sd_card.spi(|spi| {
+            let _ = nb::block!(spi.reconfigure(arduino_hal::spi::Settings {
+                clock: SD_CARD_SPI_CLOCK,
+                ..Default::default()
+            }));
+        }),
+        None => warn!("No SD card, telemetry is only logged to the console"),
+    }
+
+    let mut robot = Robot::new(
+        motors,
         pins.d26.into_floating_input(),
         &dp.EXINT.eicra,
         &dp.EXINT.eimsk,
         i2c, // takes ownership of i2c
+        arduino_hal::Eeprom::new(dp.EEPROM),
+        current_sensor,
+        battery_monitor,
+        telemetry_log,
     );
     let mut led = pins.d13.into_output();
     unsafe { avr_device::interrupt::enable() };
-    println!("Interrupts enabled");
+    debug!("Interrupts enabled");
 
     robot.reset_wheel_counters();
-    let mut led_blink_time = millis();
+    let mut shell = CommandShell::new();
+    println!("{}", F!("Type help for a list of commands"));
+    let mut scheduler: Scheduler<MainTask, 1> = Scheduler::new();
+    let _ = scheduler.add_periodic(MainTask::BlinkLed, LED_BLINK_PERIOD, 0);
     loop {
+        shell.poll(&mut robot);
         if robot.button_pressed() {
-            println!("Button pressed, testing movement");
-            led.set_high();
-            robot.straight(2000);
-            led.set_low();
-            led_blink_time = millis();
+            if robot.is_battery_low() {
+                println!(
+                    "Button pressed, but the battery is low ({} V), not moving",
+                    robot.battery_voltage()
+                );
+            } else {
+                led.set_high();
+                #[cfg(not(any(
+                    feature = "autotune",
+                    feature = "calibrate_motors",
+                    feature = "characterize_motors"
+                )))]
+                {
+                    println!("Button pressed, testing movement");
+                    if let Err(error) = robot.straight(2000) {
+                        println!("Movement failed: {}", error);
+                    }
+                }
+                #[cfg(feature = "autotune")]
+                {
+                    println!("Button pressed, auto-tuning control loops");
+                    robot.autotune_heading(true);
+                    robot.autotune_wheel_speed();
+                }
+                #[cfg(feature = "calibrate_motors")]
+                {
+                    println!("Button pressed, calibrating motors");
+                    robot.calibrate_motors();
+                }
+                #[cfg(feature = "characterize_motors")]
+                {
+                    println!("Button pressed, characterizing motors");
+                    robot.characterize_motors();
+                }
+                led.set_low();
+            }
+            scheduler.restart(MainTask::BlinkLed);
         }
-        if millis() - led_blink_time > 1000 {
-            led_blink_time = millis();
-            led.toggle();
+        let led_blink_period = if robot.is_battery_low() {
+            LOW_BATTERY_LED_BLINK_PERIOD
+        } else {
+            LED_BLINK_PERIOD
+        };
+        scheduler.set_period(MainTask::BlinkLed, led_blink_period);
+        while let Some(task) = scheduler.start_next() {
+            match task {
+                MainTask::BlinkLed => led.toggle(),
+            }
+            scheduler.finish(task);
         }
         robot.handle_loop();
     }
@@ -0,0 +1,109 @@
+use ufmt::{uDebug, uDisplay, uWrite, uwrite};
+
+use super::controller::Controller;
+use crate::system::time::Instant;
+
+/// A bang-bang controller with hysteresis. The control signal is either `+output_level` or
+/// `-output_level`. The controller switches to the positive output once the error rises above the
+/// hysteresis band and to the negative output once the error falls below it. Inside the band the
+/// previous output is held.
+#[derive(Default, Clone)]
+pub struct BangBangController {
+    pub output_level: f32,
+    pub hysteresis: f32,
+    pub setpoint: f32,
+    pub output: f32,
+}
+
+#[allow(dead_code)]
+impl BangBangController {
+    /// Create a new BangBangController.
+    /// `output_level` is the magnitude of the control signal.
+    /// `hysteresis` is the half width of the error band, in the same units as the measurement,
+    /// that the error must leave before the controller switches.
+    pub fn new(output_level: f32, hysteresis: f32) -> Self {
+        Self {
+            output_level,
+            hysteresis,
+            setpoint: 0.0,
+            output: 0.0,
+        }
+    }
+}
+
+impl Controller for BangBangController {
+    fn update(&mut self, measurement: f32, _measurement_time: Instant) -> f32 {
+        let error = self.setpoint - measurement;
+        if error > self.hysteresis {
+            self.output = self.output_level;
+        } else if error < -self.hysteresis {
+            self.output = -self.output_level;
+        }
+        self.output
+    }
+
+    fn reset(&mut self, _start_time: Instant) {
+        self.output = 0.0;
+    }
+
+    fn set_setpoint(&mut self, setpoint: f32) {
+        self.setpoint = setpoint;
+    }
+}
+
+// formatting f32 needs the firmware's fork of ufmt, which the host tests don't have
+#[cfg(target_arch = "avr")]
+impl uDebug for BangBangController {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uwrite!(
+            f,
+            "BangBangController<output_level: {}, hysteresis: {}, setpoint: {}>",
+            self.output_level,
+            self.hysteresis,
+            self.setpoint,
+        )
+    }
+}
+
+#[cfg(target_arch = "avr")]
+impl uDisplay for BangBangController {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uDebug::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_the_output_inside_the_hysteresis_band() {
+        let mut controller = BangBangController::new(10.0, 0.5);
+        controller.set_setpoint(1.0);
+        controller.reset(Instant::from_millis(0));
+        let mut update = |measurement| controller.update(measurement, Instant::from_millis(0));
+        // the output is off until the error first leaves the band
+        assert_eq!(update(0.7), 0.0);
+        assert_eq!(update(0.4), 10.0);
+        assert_eq!(update(1.0), 10.0);
+        assert_eq!(update(1.5), 10.0);
+        assert_eq!(update(1.6), -10.0);
+        assert_eq!(update(0.6), -10.0);
+        assert_eq!(update(0.4), 10.0);
+    }
+
+    #[test]
+    fn reset_turns_the_output_off() {
+        let mut controller = BangBangController::new(10.0, 0.5);
+        assert_eq!(controller.update(-1.0, Instant::from_millis(0)), 10.0);
+        controller.reset(Instant::from_millis(0));
+        assert_eq!(controller.output, 0.0);
+        assert_eq!(controller.update(0.0, Instant::from_millis(10)), 0.0);
+    }
+}
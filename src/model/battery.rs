@@ -0,0 +1,196 @@
+// weight of a new sample in the exponential moving average, sampled every 50 milliseconds
+const FILTER_ALPHA: f32 = 0.1;
+
+/// The battery pack's voltage at which the motor duty is not compensated.
+pub const NOMINAL_VOLTAGE: f32 = 9.0;
+/// Motion is refused below this voltage. The AA cells are nearly empty at 1.0 V per cell.
+const CUTOFF_VOLTAGE: f32 = 6.3;
+/// The voltage has to recover this far above the cutoff before motion is allowed again, since
+/// the voltage rebounds when the motors stop.
+const CUTOFF_HYSTERESIS: f32 = 0.3;
+/// The duty compensation factor is capped so that a sagging battery isn't drained even faster.
+const MAX_DUTY_COMPENSATION: f32 = 1.3;
+
+/// The discharge curve of the 6 AA alkaline cell pack as (voltage, state of charge in percent)
+/// points, in order of increasing voltage.
+const DISCHARGE_CURVE: [(f32, u8); 7] = [
+    (6.0, 0),
+    (6.3, 5),
+    (6.9, 20),
+    (7.5, 40),
+    (8.1, 60),
+    (8.7, 80),
+    (9.3, 100),
+];
+
+/// The state of the battery pack, estimated from its voltage samples.
+pub struct Battery {
+    voltage: f32,
+    below_cutoff: bool,
+}
+
+#[allow(dead_code)]
+impl Battery {
+    /// Starts from a first voltage sample, so the filter doesn't ramp up from zero.
+    pub fn new(voltage: f32) -> Self {
+        Self {
+            voltage,
+            below_cutoff: voltage < CUTOFF_VOLTAGE,
+        }
+    }
+
+    /// Adds a battery voltage sample in volts to the filtered voltage.
+    pub fn add_sample(&mut self, voltage: f32) {
+        self.voltage += FILTER_ALPHA * (voltage - self.voltage);
+        self.update_cutoff();
+    }
+
+    fn update_cutoff(&mut self) {
+        if self.below_cutoff {
+            self.below_cutoff = self.voltage < CUTOFF_VOLTAGE + CUTOFF_HYSTERESIS;
+        } else {
+            self.below_cutoff = self.voltage < CUTOFF_VOLTAGE;
+        }
+    }
+
+    /// The filtered battery voltage in volts.
+    pub fn voltage(&self) -> f32 {
+        self.voltage
+    }
+
+    /// The estimated state of charge in percent.
+    pub fn state_of_charge(&self) -> u8 {
+        let (first_voltage, first_charge) = DISCHARGE_CURVE[0];
+        if self.voltage <= first_voltage {
+            return first_charge;
+        }
+        for window in DISCHARGE_CURVE.windows(2) {
+            let (low_voltage, low_charge) = window[0];
+            let (high_voltage, high_charge) = window[1];
+            if self.voltage <= high_voltage {
+                let fraction = (self.voltage - low_voltage) / (high_voltage - low_voltage);
+                return low_charge + (fraction * (high_charge - low_charge) as f32) as u8;
+            }
+        }
+        DISCHARGE_CURVE[DISCHARGE_CURVE.len() - 1].1
+    }
+
+    /// Returns true while the battery voltage is too low to move.
+    pub fn is_below_cutoff(&self) -> bool {
+        self.below_cutoff
+    }
+
+    /// Scales a motor power level so that the motors get the same average voltage as they would
+    /// at the nominal battery voltage.
+    pub fn compensate_power(&self, power: f32) -> f32 {
+        if self.voltage <= 0.0 {
+            return power;
+        }
+        power * (NOMINAL_VOLTAGE / self.voltage).min(MAX_DUTY_COMPENSATION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    /// Feeds the same sample until the filtered voltage has settled on it.
+    fn settle(battery: &mut Battery, voltage: f32) {
+        for _ in 0..200 {
+            battery.add_sample(voltage);
+        }
+        assert_close(battery.voltage(), voltage);
+    }
+
+    #[test]
+    fn filters_the_samples_with_an_exponential_moving_average() {
+        let mut battery = Battery::new(9.0);
+        battery.add_sample(8.0);
+        assert_close(battery.voltage(), 8.9);
+        battery.add_sample(8.0);
+        assert_close(battery.voltage(), 8.81);
+        for _ in 2..10 {
+            battery.add_sample(8.0);
+        }
+        assert_close(battery.voltage(), 8.0 + 0.9f32.powi(10));
+    }
+
+    #[test]
+    fn cutoff_recovers_only_above_the_hysteresis() {
+        let mut battery = Battery::new(6.31);
+        assert!(!battery.is_below_cutoff());
+        for (voltage, below_cutoff) in [
+            (6.31, false),
+            (6.29, true),
+            (6.31, true),
+            (6.59, true),
+            (6.61, false),
+            (6.31, false),
+            (6.29, true),
+        ] {
+            settle(&mut battery, voltage);
+            assert_eq!(
+                battery.is_below_cutoff(),
+                below_cutoff,
+                "voltage {}",
+                voltage
+            );
+        }
+    }
+
+    #[test]
+    fn starts_below_the_cutoff_from_a_low_first_sample() {
+        let mut battery = Battery::new(6.2);
+        assert!(battery.is_below_cutoff());
+        // a single sample above the cutoff doesn't move the filtered voltage far enough
+        battery.add_sample(9.0);
+        assert!(battery.is_below_cutoff());
+    }
+
+    #[test]
+    fn state_of_charge_interpolates_the_discharge_curve() {
+        for (voltage, charge) in [
+            (5.0, 0),
+            (6.0, 0),
+            (6.3, 5),
+            (6.4, 7),
+            (6.9, 20),
+            (7.0, 23),
+            (8.0, 56),
+            (9.1, 93),
+            (9.3, 100),
+            (10.0, 100),
+        ] {
+            assert_eq!(
+                Battery::new(voltage).state_of_charge(),
+                charge,
+                "voltage {}",
+                voltage
+            );
+        }
+    }
+
+    #[test]
+    fn compensation_scales_the_power_to_the_nominal_voltage() {
+        for (voltage, power) in [(9.0, 100.0), (7.5, 120.0), (10.0, 90.0)] {
+            assert_close(Battery::new(voltage).compensate_power(100.0), power);
+        }
+    }
+
+    #[test]
+    fn compensation_is_capped() {
+        assert_close(Battery::new(6.9).compensate_power(100.0), 130.0);
+        assert_close(Battery::new(5.0).compensate_power(100.0), 130.0);
+        // no reading yet
+        assert_close(Battery::new(0.0).compensate_power(100.0), 100.0);
+    }
+}
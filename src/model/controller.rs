@@ -0,0 +1,26 @@
+use crate::system::time::Instant;
+
+/// A feedback controller that computes a control signal from a measurement of the process.
+/// Movement logic is written against this trait so that control strategies can be compared
+/// on the same course.
+pub trait Controller {
+    /// Update the controller with a new measurement and the time of the measurement. Returns
+    /// the control signal.
+    fn update(&mut self, measurement: f32, measurement_time: Instant) -> f32;
+
+    /// Reset the controller to its initial state. `start_time` is the time that the next update
+    /// is measured from.
+    fn reset(&mut self, start_time: Instant);
+
+    /// The setpoint is the desired value of the measurement.
+    fn set_setpoint(&mut self, setpoint: f32);
+
+    /// Sets the operating point for controllers that adapt to it, such as a gain scheduled
+    /// PID controller. Other controllers ignore it.
+    fn set_operating_point(&mut self, _operating_point: f32) {}
+
+    /// The accumulated error integral, for controllers that have one. Used for telemetry.
+    fn error_integral(&self) -> f32 {
+        0.0
+    }
+}
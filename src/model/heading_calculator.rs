@@ -1,6 +1,6 @@
 use crate::{
-    system::millis::millis,
-    println,
+    system::millis::micros,
+    error, info,
 };
 use arduino_hal::{Delay, I2c};
 use mpu6050::{Mpu6050, Mpu6050Error};
@@ -15,7 +15,9 @@ pub struct HeadingCalculator {
     heading: f32,
     mpu6050: Mpu6050<I2c>,
     last_update_rate: f32,
-    last_update_time: u32,
+    /// The `micros()` time of the last gyro read. The gyro rate is integrated over microseconds
+    /// because `millis()` is too coarse for the short time between updates.
+    last_update_micros: u32,
 }
 
 const MPU6050_RA_XG_OFFS_USRH: u8 = 0x13;
@@ -35,16 +37,16 @@ impl HeadingCalculator  {
         let mut mpu6050 = Mpu6050::new(i2c);
         let mut delay = Delay::new();
         match mpu6050.init(&mut delay) {
-            Ok(()) => println!("MPU6050 initialized"),
+            Ok(()) => info!("MPU6050 initialized"),
             Err(Mpu6050Error::InvalidChipId(id)) => {
-                println!("Error initializing MPU6050: InvalidChipId = {}", id)
+                error!("Error initializing MPU6050: InvalidChipId = {}", id)
             }
             Err(Mpu6050Error::I2c(_error)) => {
-                println!("Error initializing MPU6050: I2cError ")
+                error!("Error initializing MPU6050: I2cError ")
             }
         }
         if let Err(_error) = mpu6050.set_gyro_range(mpu6050::device::GyroRange::D250) {
-            println!("Error setting gyro range");
+            error!("Error setting gyro range");
         }
 
         // set the mpu6050 offsets. These were determined by running the calibration code in the
@@ -69,40 +71,38 @@ impl HeadingCalculator  {
         if let Err(_e) = mpu6050.write_byte(MPU6050_RA_ZG_OFFS_USRL, (MPU6050_GYRO_Z_OFFSET & 0xFF) as u8) {
            // todo: handle error
         }
-        println!("Gyro offsets set");
+        info!("Gyro offsets set");
 
         Self {
             heading: 0.0,
             mpu6050,
             last_update_rate: 0.0,
-            last_update_time: millis(),
+            last_update_micros: micros(),
         }
     }
 
     pub fn reset(&mut self) {
         self.heading = 0.0;
         self.last_update_rate = 0.0;
-        self.last_update_time = millis();
+        self.last_update_micros = micros();
     }
 
-    /// updates the heading value with the latest gyro measurement, then returns the current heading in degrees
-    pub fn update(&mut self) -> f32 {
-        let now = millis();
-        let delta_time = now - self.last_update_time;
-        if delta_time > 50 {
-            if let Ok(gyro) = self.mpu6050.get_gyro() {
-                // the heding is about the sensor's Z-axis
-                let delta_rads = gyro.z * delta_time as f32 / 1000.0;
-                self.heading += delta_rads;
-                self.last_update_rate = gyro.z;
-            }
+    /// updates the heading value with the latest gyro measurement. The robot's scheduler calls
+    /// this periodically.
+    pub fn update(&mut self) {
+        let now = micros();
+        if let Ok(gyro) = self.mpu6050.get_gyro() {
+            // the heding is about the sensor's Z-axis
+            let delta_micros = now.wrapping_sub(self.last_update_micros);
+            let delta_rads = gyro.z * delta_micros as f32 / 1_000_000.0;
+            self.heading += delta_rads;
+            self.last_update_rate = gyro.z;
+            self.last_update_micros = now;
         }
-
-        self.heading
     }
 
-    /// returns the current heading in degrees
-    pub fn heading(&mut self) -> f32 {
-        self.update()
+    /// returns the heading as of the last update
+    pub fn heading(&self) -> f32 {
+        self.heading
     }
 }
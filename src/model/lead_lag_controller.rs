@@ -0,0 +1,171 @@
+use micromath::F32Ext;
+use ufmt::{uDebug, uDisplay, uWrite, uwrite};
+
+use super::controller::Controller;
+use crate::system::time::Instant;
+
+/// A discrete lead-lag compensator with the transfer function
+///
+/// ```text
+/// C(s) = gain * (1 + lead_time * s) / (1 + lag_time * s)
+/// ```
+///
+/// A lead time longer than the lag time adds phase lead (damping), and a lag time longer than
+/// the lead time adds low frequency gain. The compensator is discretized with the bilinear
+/// (Tustin) transform using the time between updates, so it tolerates an irregular update period.
+#[derive(Default, Clone)]
+pub struct LeadLagController {
+    pub gain: f32,
+    pub lead_time: f32,
+    pub lag_time: f32,
+    pub setpoint: f32,
+    pub max_control_signal: f32,
+    last_error: f32,
+    last_output: f32,
+    last_time: Instant,
+}
+
+#[allow(dead_code)]
+impl LeadLagController {
+    /// Create a new LeadLagController.
+    /// `gain` is the steady state gain in control signal units per measurement unit.
+    /// `lead_time` and `lag_time` are the time constants of the zero and the pole in milliseconds.
+    pub fn new(gain: f32, lead_time: f32, lag_time: f32) -> Self {
+        Self {
+            gain,
+            lead_time,
+            lag_time,
+            setpoint: 0.0,
+            max_control_signal: 0.0,
+            last_error: 0.0,
+            last_output: 0.0,
+            last_time: Instant::default(),
+        }
+    }
+
+    /// The max control signal is the maximum absolute value that the controller will output.
+    pub fn set_max_control_signal(&mut self, max_control_signal: f32) {
+        self.max_control_signal = max_control_signal;
+    }
+}
+
+impl Controller for LeadLagController {
+    fn update(&mut self, measurement: f32, measurement_time: Instant) -> f32 {
+        if !measurement_time.is_after(self.last_time) {
+            return self.last_output;
+        }
+        let dt = (measurement_time - self.last_time).as_millis() as f32;
+        let error = self.setpoint - measurement;
+        let mut control_signal = (self.gain * (dt + 2.0 * self.lead_time) * error
+            + self.gain * (dt - 2.0 * self.lead_time) * self.last_error
+            - (dt - 2.0 * self.lag_time) * self.last_output)
+            / (dt + 2.0 * self.lag_time);
+        if self.max_control_signal > 0.0 && control_signal.abs() > self.max_control_signal {
+            control_signal = control_signal.signum() * self.max_control_signal;
+        }
+        self.last_error = error;
+        self.last_output = control_signal;
+        self.last_time = measurement_time;
+        control_signal
+    }
+
+    fn reset(&mut self, start_time: Instant) {
+        self.last_error = 0.0;
+        self.last_output = 0.0;
+        self.last_time = start_time;
+    }
+
+    fn set_setpoint(&mut self, setpoint: f32) {
+        self.setpoint = setpoint;
+    }
+}
+
+// formatting f32 needs the firmware's fork of ufmt, which the host tests don't have
+#[cfg(target_arch = "avr")]
+impl uDebug for LeadLagController {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uwrite!(
+            f,
+            "LeadLagController<gain: {}, lead_time: {}, lag_time: {}, setpoint: {}>",
+            self.gain,
+            self.lead_time,
+            self.lag_time,
+            self.setpoint,
+        )
+    }
+}
+
+#[cfg(target_arch = "avr")]
+impl uDisplay for LeadLagController {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uDebug::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= 1e-4 * expected.abs().max(1.0),
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn step_response_follows_the_tustin_difference_equation() {
+        let (gain, lead_time, lag_time, dt) = (2.0, 100.0, 50.0, 10.0);
+        let mut controller = LeadLagController::new(gain, lead_time, lag_time);
+        controller.set_setpoint(1.0);
+        controller.reset(Instant::from_millis(0));
+
+        // u[k] = (K (dt + 2 Tlead) e[k] + K (dt - 2 Tlead) e[k-1] - (dt - 2 Tlag) u[k-1])
+        //        / (dt + 2 Tlag)
+        let u1 = gain * (dt + 2.0 * lead_time) / (dt + 2.0 * lag_time);
+        assert_close(controller.update(0.0, Instant::from_millis(10)), u1);
+        let u2 = (gain * (dt + 2.0 * lead_time) + gain * (dt - 2.0 * lead_time)
+            - (dt - 2.0 * lag_time) * u1)
+            / (dt + 2.0 * lag_time);
+        assert_close(controller.update(0.0, Instant::from_millis(20)), u2);
+        // the lead kicks the output above the steady state gain
+        assert!(u1 > u2 && u2 > gain);
+
+        let mut output = u2;
+        for time in (30..2000).step_by(10) {
+            output = controller.update(0.0, Instant::from_millis(time));
+        }
+        assert_close(output, gain);
+    }
+
+    #[test]
+    fn equal_lead_and_lag_times_are_a_pure_gain() {
+        let mut controller = LeadLagController::new(3.0, 80.0, 80.0);
+        controller.reset(Instant::from_millis(0));
+        // irregular update periods and a changing error
+        for (time, measurement) in [(10, 1.0), (35, -2.0), (40, 0.5), (100, 4.0)] {
+            let output = controller.update(measurement, Instant::from_millis(time));
+            assert_close(output, -3.0 * measurement);
+        }
+    }
+
+    #[test]
+    fn limits_the_control_signal_and_skips_updates_without_elapsed_time() {
+        let mut controller = LeadLagController::new(2.0, 100.0, 50.0);
+        controller.set_max_control_signal(3.0);
+        controller.set_setpoint(1.0);
+        controller.reset(Instant::from_millis(0));
+        assert_close(controller.update(0.0, Instant::from_millis(10)), 3.0);
+        assert_close(controller.update(-5.0, Instant::from_millis(10)), 3.0);
+        controller.set_setpoint(-1.0);
+        assert_close(controller.update(0.0, Instant::from_millis(20)), -3.0);
+    }
+}
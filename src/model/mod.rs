@@ -1,4 +1,15 @@
+pub mod bang_bang_controller;
+pub mod battery;
+pub mod controller;
 #[allow(dead_code)]
 pub mod heading_calculator;
+pub mod lead_lag_controller;
+pub mod motion_monitor;
 pub mod motor_calibration;
+pub mod motor_characterization;
+pub mod motor_output_limiter;
+pub mod motor_power;
+pub mod motor_protection;
 pub mod pid_controller;
+pub mod relay_autotune;
+pub mod stall_timer;
@@ -0,0 +1,154 @@
+use micromath::F32Ext;
+use ufmt::{uDebug, uDisplay, uWrite, uwrite};
+
+use super::{motor_characterization::Wheel, motor_protection::MotorFault, stall_timer::StallTimer};
+use crate::system::time::{Duration, Instant};
+
+/// Why a movement was aborted.
+#[derive(Copy, Clone)]
+pub enum MotionError {
+    /// The wheel was driven but its encoder didn't tick.
+    Stall(Wheel),
+    /// The encoders and the gyro disagreed sharply about how fast the robot is turning, such as
+    /// when a wheel loses traction and the robot spins.
+    SpinOut,
+    /// The heading from the encoders drifted away from the gyro heading, so at least one wheel
+    /// is slipping.
+    Slip,
+    /// The motor protection latched a fault.
+    MotorFault(MotorFault),
+    /// The battery voltage is below the cutoff, so the movement didn't start.
+    LowBattery,
+    /// A `stop` command was received during the movement.
+    Stopped,
+    /// The movement didn't reach its target in time.
+    TimedOut,
+}
+
+impl MotionError {
+    /// A numeric code for the error for telemetry.
+    pub fn code(&self) -> u8 {
+        match self {
+            MotionError::Stall(Wheel::Left) => 1,
+            MotionError::Stall(Wheel::Right) => 2,
+            MotionError::SpinOut => 3,
+            MotionError::Slip => 4,
+            MotionError::MotorFault(_) => 5,
+            MotionError::LowBattery => 6,
+            MotionError::Stopped => 7,
+            MotionError::TimedOut => 8,
+        }
+    }
+}
+
+impl uDebug for MotionError {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        match self {
+            MotionError::Stall(Wheel::Left) => uwrite!(f, "left wheel stalled"),
+            MotionError::Stall(Wheel::Right) => uwrite!(f, "right wheel stalled"),
+            MotionError::SpinOut => uwrite!(f, "spin-out"),
+            MotionError::Slip => uwrite!(f, "wheel slip"),
+            MotionError::MotorFault(fault) => uwrite!(f, "{}", fault),
+            MotionError::LowBattery => uwrite!(f, "battery low"),
+            MotionError::Stopped => uwrite!(f, "stopped"),
+            MotionError::TimedOut => uwrite!(f, "timed out"),
+        }
+    }
+}
+
+impl uDisplay for MotionError {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uDebug::fmt(self, f)
+    }
+}
+
+/// The detection limits for `MotionMonitor`.
+#[derive(Copy, Clone)]
+pub struct MotionMonitorLimits {
+    /// The duty at or above which a motor is expected to turn its wheel.
+    pub stall_duty: u8,
+    /// How long a wheel may be driven without its encoder ticking before it's stalled.
+    pub stall_time: Duration,
+    /// The largest allowed difference between the encoder and gyro turn rates, in radians per
+    /// second.
+    pub spin_out_rate: f32,
+    /// The largest allowed difference between the encoder and gyro headings, in radians.
+    pub slip_heading: f32,
+}
+
+/// Watches a movement for stalled wheels, spin-outs and wheel slip by comparing the commanded
+/// motor duties, the wheel encoders and the gyro. Call `update` once per control loop period.
+pub struct MotionMonitor {
+    limits: MotionMonitorLimits,
+    stall_timers: (StallTimer, StallTimer),
+    last_encoder_heading: f32,
+    last_gyro_heading: f32,
+    last_update_time: Instant,
+}
+
+#[allow(dead_code)]
+impl MotionMonitor {
+    pub fn new(limits: MotionMonitorLimits) -> Self {
+        Self {
+            limits,
+            stall_timers: (StallTimer::default(), StallTimer::default()),
+            last_encoder_heading: 0.0,
+            last_gyro_heading: 0.0,
+            last_update_time: Instant::default(),
+        }
+    }
+
+    /// Starts monitoring a new movement at time `start_time`. The wheel counters and headings
+    /// are expected to be reset to zero at the start of the movement.
+    pub fn reset(&mut self, start_time: Instant) {
+        self.stall_timers = (StallTimer::new(start_time), StallTimer::new(start_time));
+        self.last_encoder_heading = 0.0;
+        self.last_gyro_heading = 0.0;
+        self.last_update_time = start_time;
+    }
+
+    /// Checks the movement at time `now`. `duties` are the (left, right) duties currently applied
+    /// to the motors, `ticks` are the (left, right) wheel encoder tick counts, and the headings
+    /// are the heading changes since the start of the movement in radians, calculated from the
+    /// encoders and measured by the gyro.
+    pub fn update(
+        &mut self,
+        now: Instant,
+        duties: (u8, u8),
+        ticks: (u32, u32),
+        encoder_heading: f32,
+        gyro_heading: f32,
+    ) -> Result<(), MotionError> {
+        let stall_duty = self.limits.stall_duty;
+        if self.stall_timers.0.update(now, duties.0, ticks.0, stall_duty) > self.limits.stall_time {
+            return Err(MotionError::Stall(Wheel::Left));
+        }
+        if self.stall_timers.1.update(now, duties.1, ticks.1, stall_duty) > self.limits.stall_time {
+            return Err(MotionError::Stall(Wheel::Right));
+        }
+
+        let duration = (now - self.last_update_time).as_millis();
+        if duration > 0 {
+            let encoder_rate =
+                (encoder_heading - self.last_encoder_heading) * 1000.0 / duration as f32;
+            let gyro_rate = (gyro_heading - self.last_gyro_heading) * 1000.0 / duration as f32;
+            if (encoder_rate - gyro_rate).abs() > self.limits.spin_out_rate {
+                return Err(MotionError::SpinOut);
+            }
+        }
+        self.last_encoder_heading = encoder_heading;
+        self.last_gyro_heading = gyro_heading;
+        self.last_update_time = now;
+
+        if (encoder_heading - gyro_heading).abs() > self.limits.slip_heading {
+            return Err(MotionError::Slip);
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
-const COUNT_MOTOR_LR_POWER_RATIOS: usize = 12;
-static MOTOR_LR_POWER_RATIOS: [(u8, f32); COUNT_MOTOR_LR_POWER_RATIOS] = [
+use super::motor_power::allocate_motor_power;
+
+pub const COUNT_MOTOR_LR_POWER_RATIOS: usize = 12;
+static DEFAULT_MOTOR_LR_POWER_RATIOS: [(u8, f32); COUNT_MOTOR_LR_POWER_RATIOS] = [
     // (targer_power_level: i32, left_right_turn_ratio: f32)
     (70, 1.00467),
     (80, 0.98837),
@@ -15,30 +17,124 @@ static MOTOR_LR_POWER_RATIOS: [(u8, f32); COUNT_MOTOR_LR_POWER_RATIOS] = [
     (255, 0.87813),
 ];
 
-/// For a nominal power level, returns the calibrated (left, right) motor power levels
-pub fn get_lr_motor_power(target_power_level: u8) -> (u8, u8) {
-    let mut left_power: u8 = 125;
-    let mut right_power: u8 = 125;
-
-    for i in 0..COUNT_MOTOR_LR_POWER_RATIOS {
-        if target_power_level < MOTOR_LR_POWER_RATIOS[i].0 {
-            if i == 0 {
-                left_power = MOTOR_LR_POWER_RATIOS[i].0;
-                right_power =
-                    ((MOTOR_LR_POWER_RATIOS[i].0 as f32) * MOTOR_LR_POWER_RATIOS[i].1) as u8;
-            } else {
-                let lr_ratio = MOTOR_LR_POWER_RATIOS[i - 1].1
-                    + (MOTOR_LR_POWER_RATIOS[i].1 - MOTOR_LR_POWER_RATIOS[i - 1].1)
-                        * (target_power_level - MOTOR_LR_POWER_RATIOS[i - 1].0) as f32
-                        / (MOTOR_LR_POWER_RATIOS[i].0 - MOTOR_LR_POWER_RATIOS[i - 1].0) as f32;
-                left_power = target_power_level;
-                right_power = ((target_power_level as f32) * lr_ratio) as u8;
+/// The table of left/right motor power ratios for a set of nominal power levels. The ratio is the
+/// left wheel's tick count divided by the right wheel's tick count when both motors are driven at
+/// the nominal power level. The table is sorted by ascending power level.
+#[derive(Copy, Clone)]
+pub struct MotorPowerRatios {
+    ratios: [(u8, f32); COUNT_MOTOR_LR_POWER_RATIOS],
+}
+
+impl Default for MotorPowerRatios {
+    fn default() -> Self {
+        Self::new(DEFAULT_MOTOR_LR_POWER_RATIOS)
+    }
+}
+
+#[allow(dead_code)]
+impl MotorPowerRatios {
+    pub fn new(ratios: [(u8, f32); COUNT_MOTOR_LR_POWER_RATIOS]) -> Self {
+        Self { ratios }
+    }
+
+    /// The nominal power levels of the table, in ascending order.
+    pub fn power_levels(&self) -> [u8; COUNT_MOTOR_LR_POWER_RATIOS] {
+        let mut levels = [0u8; COUNT_MOTOR_LR_POWER_RATIOS];
+        for (level, ratio) in levels.iter_mut().zip(self.ratios.iter()) {
+            *level = ratio.0;
+        }
+        levels
+    }
+
+    pub fn ratios(&self) -> &[(u8, f32); COUNT_MOTOR_LR_POWER_RATIOS] {
+        &self.ratios
+    }
+
+    /// Returns the left/right power ratio for a nominal power level, linearly interpolated between
+    /// the table's power levels. Power levels outside of the table get the ratio of the nearest
+    /// table entry.
+    pub fn lr_ratio(&self, target_power_level: u8) -> f32 {
+        if target_power_level <= self.ratios[0].0 {
+            return self.ratios[0].1;
+        }
+        for i in 1..COUNT_MOTOR_LR_POWER_RATIOS {
+            if target_power_level < self.ratios[i].0 {
+                let (lower_power, lower_ratio) = self.ratios[i - 1];
+                let (upper_power, upper_ratio) = self.ratios[i];
+                return lower_ratio
+                    + (upper_ratio - lower_ratio) * (target_power_level - lower_power) as f32
+                        / (upper_power - lower_power) as f32;
             }
-            break;
-        } else if i == COUNT_MOTOR_LR_POWER_RATIOS - 1 {
-            left_power = MOTOR_LR_POWER_RATIOS[i].0;
-            right_power = ((MOTOR_LR_POWER_RATIOS[i].0 as f32) * MOTOR_LR_POWER_RATIOS[i].1) as u8;
+        }
+        self.ratios[COUNT_MOTOR_LR_POWER_RATIOS - 1].1
+    }
+
+    /// For a nominal power level, returns the calibrated (left, right) motor power levels
+    pub fn get_lr_motor_power(&self, target_power_level: u8) -> (u8, u8) {
+        allocate_motor_power(
+            target_power_level as f32,
+            self.lr_ratio(target_power_level),
+            0.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn lr_ratio_interpolates_between_the_power_levels() {
+        let ratios = MotorPowerRatios::default();
+        for (power_level, ratio) in [
+            (70, 1.00467),
+            (75, (1.00467 + 0.98837) / 2.0),
+            (100, 0.99218),
+            (130, (1.00406 + 1.00608) / 2.0),
+            (150, (1.00608 + 0.99909) / 2.0),
+            (215, 0.9729 + (0.95067 - 0.9729) * 0.6),
+            (255, 0.87813),
+        ] {
+            assert_close(ratios.lr_ratio(power_level), ratio);
+        }
+    }
+
+    #[test]
+    fn lr_ratio_outside_the_table_uses_the_nearest_entry() {
+        let mut table = DEFAULT_MOTOR_LR_POWER_RATIOS;
+        table[COUNT_MOTOR_LR_POWER_RATIOS - 1] = (240, 0.9);
+        let ratios = MotorPowerRatios::new(table);
+        for (power_level, ratio) in [(0, 1.00467), (69, 1.00467), (241, 0.9), (255, 0.9)] {
+            assert_close(ratios.lr_ratio(power_level), ratio);
+        }
+    }
+
+    #[test]
+    fn power_levels_lists_the_table_levels() {
+        let ratios = MotorPowerRatios::default();
+        assert_eq!(
+            ratios.power_levels(),
+            [70, 80, 90, 100, 110, 120, 140, 160, 180, 200, 225, 255]
+        );
+    }
+
+    #[test]
+    fn equal_ratios_give_both_motors_the_target_power() {
+        let ratios = MotorPowerRatios::new([(0, 1.0); COUNT_MOTOR_LR_POWER_RATIOS]);
+        for power_level in [0, 80, 255] {
+            assert_eq!(
+                ratios.get_lr_motor_power(power_level),
+                (power_level, power_level)
+            );
         }
     }
-    (left_power, right_power)
 }
@@ -0,0 +1,169 @@
+pub const COUNT_CHARACTERIZATION_DUTIES: usize = 8;
+/// The duty levels at which the steady state wheel speed is measured, in ascending order.
+pub static CHARACTERIZATION_DUTIES: [u8; COUNT_CHARACTERIZATION_DUTIES] =
+    [80, 105, 130, 155, 180, 205, 230, 255];
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Wheel {
+    Left,
+    Right,
+}
+
+/// The measured relationship between a motor's PWM duty and its wheel's speed.
+#[derive(Copy, Clone, Default)]
+pub struct MotorSpeedCurve {
+    /// The lowest duty at which the stopped motor starts turning.
+    pub start_duty: u8,
+    /// The duty below which the turning motor stalls.
+    pub stall_duty: u8,
+    /// The steady state wheel speed in millimeters per second at each of the
+    /// `CHARACTERIZATION_DUTIES` duty levels.
+    pub speeds: [f32; COUNT_CHARACTERIZATION_DUTIES],
+}
+
+#[allow(dead_code)]
+impl MotorSpeedCurve {
+    /// Returns the duty that drives an already turning wheel at `mm_per_s` millimeters per
+    /// second. The curve is linearly interpolated between the measured points, starting from
+    /// zero speed at the stall duty. Speeds beyond the fastest measured speed get the maximum
+    /// duty, and a speed of zero or less gets a duty of zero.
+    pub fn duty_for_speed(&self, mm_per_s: f32) -> u8 {
+        if mm_per_s <= 0.0 {
+            return 0;
+        }
+        let mut lower_duty = self.stall_duty as f32;
+        let mut lower_speed: f32 = 0.0;
+        for (duty, speed) in CHARACTERIZATION_DUTIES.iter().zip(self.speeds.iter()) {
+            let (upper_duty, upper_speed) = (*duty as f32, *speed);
+            if upper_speed <= lower_speed || upper_duty <= lower_duty {
+                // skip points that don't increase the speed, such as points below the stall duty
+                continue;
+            }
+            if mm_per_s <= upper_speed {
+                let duty = lower_duty
+                    + (upper_duty - lower_duty) * (mm_per_s - lower_speed)
+                        / (upper_speed - lower_speed);
+                return duty.min(255.0) as u8;
+            }
+            lower_duty = upper_duty;
+            lower_speed = upper_speed;
+        }
+        255
+    }
+
+    /// The fastest measured steady state wheel speed in millimeters per second.
+    pub fn max_speed(&self) -> f32 {
+        self.speeds.iter().fold(0.0, |max, speed| max.max(*speed))
+    }
+}
+
+/// The duty-to-speed characterization of both drive motors.
+#[derive(Copy, Clone, Default)]
+pub struct MotorCharacterization {
+    pub left: MotorSpeedCurve,
+    pub right: MotorSpeedCurve,
+}
+
+#[allow(dead_code)]
+impl MotorCharacterization {
+    pub fn curve(&self, wheel: Wheel) -> &MotorSpeedCurve {
+        match wheel {
+            Wheel::Left => &self.left,
+            Wheel::Right => &self.right,
+        }
+    }
+
+    pub fn curve_mut(&mut self, wheel: Wheel) -> &mut MotorSpeedCurve {
+        match wheel {
+            Wheel::Left => &mut self.left,
+            Wheel::Right => &mut self.right,
+        }
+    }
+
+    /// Returns the duty that drives `wheel` at `mm_per_s` millimeters per second. This is the
+    /// inverse of the measured duty-to-speed curve, and is meant to be used as the feedforward
+    /// term of a speed controller.
+    pub fn duty_for_speed(&self, wheel: Wheel, mm_per_s: f32) -> u8 {
+        self.curve(wheel).duty_for_speed(mm_per_s)
+    }
+
+    /// The lowest duty at which the stopped `wheel` starts turning.
+    pub fn start_duty(&self, wheel: Wheel) -> u8 {
+        self.curve(wheel).start_duty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A curve that stalls below a duty of 70 and doesn't turn the wheel at the lowest measured
+    /// duty yet.
+    fn curve() -> MotorSpeedCurve {
+        MotorSpeedCurve {
+            start_duty: 90,
+            stall_duty: 70,
+            speeds: [0.0, 100.0, 200.0, 300.0, 380.0, 440.0, 480.0, 500.0],
+        }
+    }
+
+    #[test]
+    fn duty_for_speed_interpolates_between_the_measured_points() {
+        let curve = curve();
+        for (speed, duty) in [
+            // between the stall duty and the first point that turns the wheel
+            (50.0, 87),
+            (100.0, 105),
+            (150.0, 117),
+            (200.0, 130),
+            (340.0, 167),
+            (490.0, 242),
+            (500.0, 255),
+        ] {
+            assert_eq!(curve.duty_for_speed(speed), duty, "speed {}", speed);
+        }
+    }
+
+    #[test]
+    fn duty_for_speed_stays_out_of_the_deadband() {
+        let curve = curve();
+        assert_eq!(curve.duty_for_speed(0.0), 0);
+        assert_eq!(curve.duty_for_speed(-100.0), 0);
+        // the slowest speeds get about the stall duty rather than a duty that doesn't turn the
+        // wheel
+        assert_eq!(curve.duty_for_speed(0.1), 70);
+        assert_eq!(curve.duty_for_speed(1.0), 70);
+    }
+
+    #[test]
+    fn duty_for_speed_saturates_beyond_the_fastest_measured_speed() {
+        let curve = curve();
+        assert_eq!(curve.max_speed(), 500.0);
+        assert_eq!(curve.duty_for_speed(501.0), 255);
+        assert_eq!(curve.duty_for_speed(10_000.0), 255);
+    }
+
+    #[test]
+    fn duty_for_speed_skips_points_that_dont_increase_the_speed() {
+        let curve = MotorSpeedCurve {
+            start_duty: 90,
+            stall_duty: 100,
+            // 80 is below the stall duty, and the wheel slows down at 155
+            speeds: [20.0, 100.0, 200.0, 190.0, 300.0, 400.0, 500.0, 600.0],
+        };
+        for (speed, duty) in [(50.0, 102), (200.0, 130), (250.0, 155), (300.0, 180)] {
+            assert_eq!(curve.duty_for_speed(speed), duty, "speed {}", speed);
+        }
+    }
+
+    #[test]
+    fn characterization_uses_the_curve_of_each_wheel() {
+        let mut characterization = MotorCharacterization::default();
+        *characterization.curve_mut(Wheel::Left) = curve();
+        assert_eq!(characterization.duty_for_speed(Wheel::Left, 100.0), 105);
+        assert_eq!(characterization.start_duty(Wheel::Left), 90);
+        // an uncharacterized wheel never reaches any speed
+        assert_eq!(characterization.duty_for_speed(Wheel::Right, 100.0), 255);
+        assert_eq!(characterization.start_duty(Wheel::Right), 0);
+    }
+}
@@ -0,0 +1,111 @@
+/// Limits how quickly a motor's output may change. The output is a signed duty, where the sign is
+/// the direction the motor turns and zero means the motor coasts.
+///
+/// Increases in the output's magnitude are limited to `max_duty_change_per_ms`. Decreases are
+/// applied immediately, since cutting power doesn't draw a current spike. When the direction
+/// changes, the motor first coasts for at least `dead_time` milliseconds before it's driven in the
+/// new direction. A limit of zero disables the respective limit.
+#[derive(Default, Clone)]
+pub struct MotorOutputLimiter {
+    max_duty_change_per_ms: u8,
+    dead_time: u32,
+    output: i16,
+    last_direction: i16,
+    last_update_time: u32,
+    coast_start_time: u32,
+    ramping: bool,
+}
+
+#[allow(dead_code)]
+impl MotorOutputLimiter {
+    pub fn new(max_duty_change_per_ms: u8, dead_time: u32) -> Self {
+        Self {
+            max_duty_change_per_ms,
+            dead_time,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_max_duty_change_per_ms(&mut self, max_duty_change_per_ms: u8) {
+        self.max_duty_change_per_ms = max_duty_change_per_ms;
+    }
+
+    pub fn set_dead_time(&mut self, dead_time: u32) {
+        self.dead_time = dead_time;
+    }
+
+    /// The output most recently returned by `update`.
+    pub fn output(&self) -> i16 {
+        self.output
+    }
+
+    /// Returns true while the output hasn't reached the last target yet.
+    pub fn is_limiting(&self) -> bool {
+        self.ramping
+    }
+
+    /// Moves the output towards `target` as far as the limits allow at time `now`, in
+    /// milliseconds, and returns the new output.
+    pub fn update(&mut self, target: i16, now: u32) -> i16 {
+        // only time spent ramping counts towards the allowed change, otherwise the first
+        // update after a long steady period could jump straight to the target
+        let elapsed = if self.ramping {
+            now.wrapping_sub(self.last_update_time)
+        } else {
+            0
+        };
+        self.last_update_time = now;
+
+        let target_direction = target.signum();
+        if self.output != 0 && target_direction != self.output.signum() {
+            // stopping or changing direction. cut the power and let the motor coast.
+            self.coast(now);
+        }
+        if target == 0 {
+            self.ramping = false;
+            return self.output;
+        }
+
+        if self.output == 0
+            && self.last_direction != 0
+            && target_direction != self.last_direction
+            && now.wrapping_sub(self.coast_start_time) < self.dead_time
+        {
+            // still waiting out the dead time before driving in the new direction
+            self.ramping = true;
+            return self.output;
+        }
+
+        let target_magnitude = target.unsigned_abs();
+        let output_magnitude = self.output.unsigned_abs();
+        let magnitude = if self.max_duty_change_per_ms == 0 || target_magnitude <= output_magnitude
+        {
+            target_magnitude
+        } else {
+            let max_change = elapsed
+                .max(1)
+                .saturating_mul(self.max_duty_change_per_ms as u32)
+                .min(u16::MAX as u32) as u16;
+            target_magnitude.min(output_magnitude.saturating_add(max_change))
+        };
+        self.output = target_direction * magnitude as i16;
+        self.last_direction = target_direction;
+        self.ramping = self.output != target;
+        self.output
+    }
+
+    /// Records that the motor was stopped outside of the limiter, such as by braking. The
+    /// dead time applies from this point before the motor is driven in the other direction.
+    pub fn force_stop(&mut self, now: u32) {
+        if self.output != 0 {
+            self.coast(now);
+        }
+        self.ramping = false;
+    }
+
+    fn coast(&mut self, now: u32) {
+        self.last_direction = self.output.signum();
+        self.output = 0;
+        self.coast_start_time = now;
+    }
+}
@@ -0,0 +1,174 @@
+use micromath::F32Ext;
+
+const MAX_MOTOR_POWER: f32 = 255.0;
+
+/// The difference between the right and left motor power per unit of turn correction. The
+/// original one-sided correction added the full correction to one motor and took half of it from
+/// the other, a differential of 1.5 times the correction. Keeping that differential keeps the
+/// meaning of the heading controller gains, including gains saved to persistent settings.
+const TURN_CORRECTION_DIFFERENTIAL: f32 = 1.5;
+
+/// Converts a nominal power level into (left, right) motor power levels.
+///
+/// `base_power` is the nominal power level of both motors.
+/// `lr_ratio` is the calibrated left/right ratio at that power level, that is, the right motor
+/// needs `lr_ratio` times the left motor's power to turn its wheel at the same speed. The ratio
+/// correction is spread over both motors, so the geometric mean of the two power levels stays at
+/// the nominal power level.
+/// `turn_correction` is a differential power adjustment. A positive correction turns the robot
+/// left. The right motor's power exceeds the left motor's power by `TURN_CORRECTION_DIFFERENTIAL`
+/// times the correction, split evenly between the two motors.
+///
+/// If a power level would exceed the maximum, both power levels are scaled down so that the larger
+/// one is at the maximum. This desaturation keeps the ratio between the two motors, and thus the
+/// robot's turning intent. Power levels below zero are clamped to zero.
+pub fn allocate_motor_power(base_power: f32, lr_ratio: f32, turn_correction: f32) -> (u8, u8) {
+    let lr_ratio = if lr_ratio > 0.0 { lr_ratio } else { 1.0 };
+    // the square root is approximate, so the right power is derived from the left power to keep
+    // the ratio exact. only the geometric mean is off by the approximation error.
+    let left_base_power = base_power / F32Ext::sqrt(lr_ratio);
+    let right_base_power = left_base_power * lr_ratio;
+    let side_correction = turn_correction * TURN_CORRECTION_DIFFERENTIAL / 2.0;
+    let mut left_power = left_base_power - side_correction;
+    let mut right_power = right_base_power + side_correction;
+
+    let max_power = left_power.max(right_power);
+    if max_power > MAX_MOTOR_POWER {
+        let scale = MAX_MOTOR_POWER / max_power;
+        left_power *= scale;
+        right_power *= scale;
+    }
+    (to_motor_power(left_power), to_motor_power(right_power))
+}
+
+/// Rounds a power level to the nearest motor power, saturating at the power limits.
+#[allow(clippy::neg_cmp_op_on_partial_ord)]
+fn to_motor_power(power: f32) -> u8 {
+    // written so that NaN saturates to zero
+    if !(power > 0.0) {
+        0
+    } else if power >= MAX_MOTOR_POWER {
+        255
+    } else {
+        (power + 0.5) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that a power level is within the rounding and the error of the approximate square
+    /// root, about 1% near a ratio of one, of the exact power level.
+    fn assert_near(actual: u8, expected: f32) {
+        let tolerance = 0.5 + expected.abs() * 0.01;
+        assert!(
+            (actual as f32 - expected).abs() <= tolerance,
+            "expected {} to be within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn equal_ratio_without_correction_applies_the_base_power_to_both_motors() {
+        for base_power in 0..=255u8 {
+            let (left, right) = allocate_motor_power(base_power as f32, 1.0, 0.0);
+            assert_eq!(left, base_power);
+            assert_eq!(right, base_power);
+        }
+    }
+
+    #[test]
+    fn ratio_is_spread_over_both_motors() {
+        for base_power in 40..=200u8 {
+            let (left, right) = allocate_motor_power(base_power as f32, 1.21, 0.0);
+            assert_near(left, base_power as f32 / 1.1);
+            assert_near(right, base_power as f32 * 1.1);
+        }
+    }
+
+    #[test]
+    fn ratio_below_one_gives_the_left_motor_more_power() {
+        let (left, right) = allocate_motor_power(100.0, 0.81, 0.0);
+        assert_near(left, 100.0 / 0.9);
+        assert_near(right, 90.0);
+    }
+
+    #[test]
+    fn invalid_ratio_is_ignored() {
+        assert_eq!(allocate_motor_power(100.0, 0.0, 0.0), (100, 100));
+        assert_eq!(allocate_motor_power(100.0, -1.0, 0.0), (100, 100));
+    }
+
+    #[test]
+    fn turn_correction_keeps_the_original_differential() {
+        let (left, right) = allocate_motor_power(125.0, 1.0, 20.0);
+        assert_eq!((left, right), (110, 140));
+        assert_eq!(right - left, 30);
+
+        let (left, right) = allocate_motor_power(125.0, 1.0, -20.0);
+        assert_eq!((left, right), (140, 110));
+    }
+
+    #[test]
+    fn turn_correction_is_added_to_the_ratio_corrected_powers() {
+        let (left, right) = allocate_motor_power(121.0, 1.21, 10.0);
+        assert_near(left, 110.0 - 7.5);
+        assert_near(right, 133.1 + 7.5);
+    }
+
+    #[test]
+    fn saturation_scales_both_motors_and_keeps_the_ratio() {
+        let (left, right) = allocate_motor_power(250.0, 1.21, 0.0);
+        assert_eq!(right, 255);
+        assert_near(left, 255.0 / 1.21);
+
+        // right would be 200 + 150 = 350 and left 200 - 150 = 50
+        let (left, right) = allocate_motor_power(200.0, 1.0, 200.0);
+        assert_eq!(right, 255);
+        assert_near(left, 50.0 * 255.0 / 350.0);
+
+        let (left, right) = allocate_motor_power(200.0, 1.0, -200.0);
+        assert_eq!(left, 255);
+        assert_near(right, 50.0 * 255.0 / 350.0);
+    }
+
+    #[test]
+    fn saturation_keeps_the_ratio_over_the_whole_power_range() {
+        for base_power in 0..=255u8 {
+            for lr_ratio in [0.8, 1.0, 1.1, 1.3] {
+                let (left, right) = allocate_motor_power(base_power as f32 * 1.5, lr_ratio, 0.0);
+                if base_power == 0 {
+                    assert_eq!((left, right), (0, 0));
+                    continue;
+                }
+                let ratio = right as f32 / left as f32;
+                // rounding both powers to whole duties is the only ratio error
+                let allowed_error = lr_ratio * 1.5 / left.min(right) as f32;
+                assert!(
+                    (ratio - lr_ratio).abs() <= allowed_error,
+                    "base power {} with ratio {} allocated ({}, {})",
+                    base_power,
+                    lr_ratio,
+                    left,
+                    right
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn negative_powers_are_clamped_to_zero() {
+        assert_eq!(allocate_motor_power(10.0, 1.0, 100.0), (0, 85));
+        assert_eq!(allocate_motor_power(10.0, 1.0, -100.0), (85, 0));
+        assert_eq!(allocate_motor_power(-50.0, 1.0, 0.0), (0, 0));
+    }
+
+    #[test]
+    fn nan_allocates_zero_power() {
+        assert_eq!(allocate_motor_power(f32::NAN, 1.0, 0.0), (0, 0));
+        assert_eq!(allocate_motor_power(100.0, 1.0, f32::NAN), (0, 0));
+    }
+}
@@ -0,0 +1,203 @@
+use ufmt::{uDebug, uDisplay, uWrite, uwrite};
+
+use super::{motor_characterization::Wheel, stall_timer::StallTimer};
+use crate::system::time::{Duration, Instant};
+
+/// A source of motor current measurements.
+pub trait CurrentSensor {
+    /// Returns the (left, right) motor currents in milliamps.
+    fn read_currents(&mut self) -> (u16, u16);
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MotorFaultKind {
+    /// The motor current stayed above the overcurrent limit.
+    Overcurrent,
+    /// The motor was driven hard and drew current, but its wheel didn't turn.
+    Stall,
+}
+
+/// A latched motor fault.
+#[derive(Copy, Clone)]
+pub struct MotorFault {
+    pub kind: MotorFaultKind,
+    pub wheel: Wheel,
+    /// The time of the fault.
+    pub time: Instant,
+    /// The motor current at the time of the fault in milliamps.
+    pub current: u16,
+}
+
+impl MotorFault {
+    /// A numeric code for the fault for telemetry. Zero is reserved for no fault.
+    pub fn code(&self) -> u8 {
+        let wheel_offset = match self.wheel {
+            Wheel::Left => 0,
+            Wheel::Right => 1,
+        };
+        match self.kind {
+            MotorFaultKind::Overcurrent => 1 + wheel_offset,
+            MotorFaultKind::Stall => 3 + wheel_offset,
+        }
+    }
+}
+
+impl uDebug for MotorFault {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        let kind = match self.kind {
+            MotorFaultKind::Overcurrent => "overcurrent",
+            MotorFaultKind::Stall => "stall",
+        };
+        let wheel = match self.wheel {
+            Wheel::Left => "left",
+            Wheel::Right => "right",
+        };
+        uwrite!(
+            f,
+            "MotorFault<{} on {} motor at {} ms, current: {} mA>",
+            kind,
+            wheel,
+            self.time,
+            self.current,
+        )
+    }
+}
+
+impl uDisplay for MotorFault {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uDebug::fmt(self, f)
+    }
+}
+
+/// The fault detection limits for `MotorProtection`.
+#[derive(Copy, Clone)]
+pub struct MotorProtectionLimits {
+    /// The current in milliamps above which a motor is overcurrent.
+    pub overcurrent_limit: u16,
+    /// The number of consecutive samples above the overcurrent limit that trigger a fault. This
+    /// lets the motors draw their inrush current when they start.
+    pub overcurrent_samples: u8,
+    /// The duty at or above which a motor is expected to turn its wheel.
+    pub stall_duty: u8,
+    /// The current in milliamps at or above which a motor that isn't turning is stalled.
+    pub stall_current: u16,
+    /// How long a motor may be driven without its wheel turning before it's stalled.
+    pub stall_time: Duration,
+}
+
+/// Detects motor overcurrent and stall faults from the motor currents, the duties and the wheel
+/// encoder tick counts. A detected fault is latched until it is cleared.
+pub struct MotorProtection<CS: CurrentSensor> {
+    sensor: CS,
+    limits: MotorProtectionLimits,
+    currents: (u16, u16),
+    overcurrent_counts: (u8, u8),
+    stall_timers: (StallTimer, StallTimer),
+    fault: Option<MotorFault>,
+}
+
+#[allow(dead_code)]
+impl<CS: CurrentSensor> MotorProtection<CS> {
+    pub fn new(sensor: CS, limits: MotorProtectionLimits) -> Self {
+        Self {
+            sensor,
+            limits,
+            currents: (0, 0),
+            overcurrent_counts: (0, 0),
+            stall_timers: (StallTimer::default(), StallTimer::default()),
+            fault: None,
+        }
+    }
+
+    /// Samples the motor currents and checks for faults. `duties` are the (left, right) duties
+    /// currently applied to the motors and `ticks` are the (left, right) wheel encoder tick counts.
+    /// Returns a newly detected fault. Once a fault is latched, no further faults are detected
+    /// until it is cleared.
+    pub fn check(&mut self, now: Instant, duties: (u8, u8), ticks: (u32, u32)) -> Option<MotorFault> {
+        self.currents = self.sensor.read_currents();
+        if self.fault.is_some() {
+            return None;
+        }
+
+        let left = self.check_motor(
+            Wheel::Left,
+            now,
+            duties.0,
+            ticks.0,
+            self.currents.0,
+        );
+        let right = self.check_motor(
+            Wheel::Right,
+            now,
+            duties.1,
+            ticks.1,
+            self.currents.1,
+        );
+        self.fault = left.or(right);
+        self.fault
+    }
+
+    fn check_motor(
+        &mut self,
+        wheel: Wheel,
+        now: Instant,
+        duty: u8,
+        ticks: u32,
+        current: u16,
+    ) -> Option<MotorFault> {
+        let (overcurrent_count, stall_timer) = match wheel {
+            Wheel::Left => (&mut self.overcurrent_counts.0, &mut self.stall_timers.0),
+            Wheel::Right => (&mut self.overcurrent_counts.1, &mut self.stall_timers.1),
+        };
+
+        if current > self.limits.overcurrent_limit {
+            *overcurrent_count = overcurrent_count.saturating_add(1);
+        } else {
+            *overcurrent_count = 0;
+        }
+        if *overcurrent_count >= self.limits.overcurrent_samples {
+            return Some(MotorFault {
+                kind: MotorFaultKind::Overcurrent,
+                wheel,
+                time: now,
+                current,
+            });
+        }
+
+        let stalled_time = stall_timer.update(now, duty, ticks, self.limits.stall_duty);
+        if stalled_time > self.limits.stall_time && current >= self.limits.stall_current {
+            return Some(MotorFault {
+                kind: MotorFaultKind::Stall,
+                wheel,
+                time: now,
+                current,
+            });
+        }
+        None
+    }
+
+    /// The latched fault, if any.
+    pub fn fault(&self) -> Option<MotorFault> {
+        self.fault
+    }
+
+    /// Clears the latched fault. `now` restarts the stall timers, so that a motor isn't
+    /// immediately found stalled again.
+    pub fn clear_fault(&mut self, now: Instant) {
+        self.fault = None;
+        self.overcurrent_counts = (0, 0);
+        self.stall_timers.0.restart(now);
+        self.stall_timers.1.restart(now);
+    }
+
+    /// The most recently sampled (left, right) motor currents in milliamps.
+    pub fn currents(&self) -> (u16, u16) {
+        self.currents
+    }
+}
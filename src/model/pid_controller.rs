@@ -1,7 +1,73 @@
 use micromath::F32Ext;
 use ufmt::{uDebug, uDisplay, uWrite, uwrite};
 
-use crate::println;
+use super::controller::Controller;
+use crate::{debug, system::time::Instant, warn};
+
+/// A set of PID gains. See `PIDController::new` for the units of the gains.
+#[derive(Default, Copy, Clone)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+impl PidGains {
+    pub fn new(kp: f32, ki: f32, kd: f32) -> Self {
+        Self { kp, ki, kd }
+    }
+}
+
+// formatting f32 needs the firmware's fork of ufmt, which the host tests don't have
+#[cfg(target_arch = "avr")]
+impl uDisplay for PidGains {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uwrite!(f, "kp = {}, ki = {}, kd = {}", self.kp, self.ki, self.kd)
+    }
+}
+
+/// A point in a PID gain schedule. The schedule is a table of gains keyed on an operating point
+/// variable, such as the target motor power. The table must be sorted by ascending operating point.
+#[derive(Copy, Clone)]
+pub struct GainSchedulePoint {
+    pub operating_point: f32,
+    pub gains: PidGains,
+}
+
+impl GainSchedulePoint {
+    pub const fn new(operating_point: f32, kp: f32, ki: f32, kd: f32) -> Self {
+        Self {
+            operating_point,
+            gains: PidGains { kp, ki, kd },
+        }
+    }
+}
+
+/// Returns the gains for an operating point, linearly interpolated between the two nearest points
+/// in the schedule. Operating points outside of the schedule get the gains of the nearest end point.
+pub fn interpolate_gains(schedule: &[GainSchedulePoint], operating_point: f32) -> Option<PidGains> {
+    let first = schedule.first()?;
+    if operating_point <= first.operating_point {
+        return Some(first.gains);
+    }
+    for i in 1..schedule.len() {
+        let upper = &schedule[i];
+        if operating_point < upper.operating_point {
+            let lower = &schedule[i - 1];
+            let fraction = (operating_point - lower.operating_point)
+                / (upper.operating_point - lower.operating_point);
+            return Some(PidGains::new(
+                lower.gains.kp + (upper.gains.kp - lower.gains.kp) * fraction,
+                lower.gains.ki + (upper.gains.ki - lower.gains.ki) * fraction,
+                lower.gains.kd + (upper.gains.kd - lower.gains.kd) * fraction,
+            ));
+        }
+    }
+    schedule.last().map(|point| point.gains)
+}
 
 #[derive(Default, Clone)]
 pub struct PIDController {
@@ -11,8 +77,11 @@ pub struct PIDController {
     pub setpoint: f32,
     pub integral: f32,
     pub last_error: f32,
-    pub last_time: u32,
+    pub last_time: Instant,
     pub max_control_signal: f32,
+    pub feedforward: f32,
+    pub operating_point: f32,
+    gain_schedule: Option<&'static [GainSchedulePoint]>,
 }
 
 #[allow(dead_code)]
@@ -31,11 +100,24 @@ impl PIDController {
             setpoint: 0.0,
             integral: 0.0,
             last_error: 0.0,
-            last_time: 0,
+            last_time: Instant::default(),
             max_control_signal: 0.0,
+            feedforward: 0.0,
+            operating_point: 0.0,
+            gain_schedule: None,
         }
     }
 
+    /// Create a new PIDController from a set of gains.
+    pub fn from_gains(gains: &PidGains) -> Self {
+        Self::new(gains.kp, gains.ki, gains.kd)
+    }
+
+    /// Returns the controller's current gains.
+    pub fn gains(&self) -> PidGains {
+        PidGains::new(self.kp, self.ki, self.kd)
+    }
+
     /// The setpoint is the desired value of the measurement.
     pub fn set_setpoint(&mut self, setpoint: f32) {
         self.setpoint = setpoint;
@@ -46,24 +128,84 @@ impl PIDController {
         self.max_control_signal = max_control_signal;
     }
 
-    /// Update the controller with a new measurement and the time of the measurement.
-    pub fn update(&mut self, measurement: f32, measurement_time: u32) -> f32 {
-        if self.last_time > measurement_time {
-            println!(
+    /// The feedforward term is added to the control signal on every update. Use it for the part
+    /// of the control signal that is known ahead of time, such as the motor power needed to
+    /// reach a target speed, so that the feedback terms only need to correct the error.
+    pub fn set_feedforward(&mut self, feedforward: f32) {
+        self.feedforward = feedforward;
+    }
+
+    /// Sets a gain schedule. While a gain schedule is set, the gains are interpolated from the
+    /// schedule whenever the operating point changes. The schedule's gains immediately replace
+    /// the current gains.
+    pub fn set_gain_schedule(&mut self, schedule: &'static [GainSchedulePoint]) {
+        self.gain_schedule = Some(schedule);
+        self.apply_gain_schedule();
+    }
+
+    /// Removes the gain schedule. The current gains remain in effect.
+    pub fn clear_gain_schedule(&mut self) {
+        self.gain_schedule = None;
+    }
+
+    /// Sets the operating point used to look up the gains in the gain schedule.
+    pub fn set_operating_point(&mut self, operating_point: f32) {
+        if operating_point != self.operating_point {
+            self.operating_point = operating_point;
+            self.apply_gain_schedule();
+        }
+    }
+
+    /// Replaces the gains with the scheduled gains at the current operating point. When `ki`
+    /// changes, the integral is rescaled by the ratio of the old to the new `ki` so that the
+    /// integral term's contribution to the control signal doesn't jump.
+    fn apply_gain_schedule(&mut self) {
+        if let Some(gains) = self
+            .gain_schedule
+            .and_then(|schedule| interpolate_gains(schedule, self.operating_point))
+        {
+            if gains.ki != self.ki && gains.ki != 0.0 && self.ki != 0.0 {
+                self.integral *= self.ki / gains.ki;
+            }
+            self.kp = gains.kp;
+            self.ki = gains.ki;
+            self.kd = gains.kd;
+        }
+    }
+
+    /// Update the controller with a new measurement, the time of the measurement, and the
+    /// current operating point for the gain schedule.
+    pub fn update_with_operating_point(
+        &mut self,
+        measurement: f32,
+        measurement_time: Instant,
+        operating_point: f32,
+    ) -> f32 {
+        self.set_operating_point(operating_point);
+        self.update(measurement, measurement_time)
+    }
+
+    /// Update the controller with a new measurement and the time of the measurement. The time
+    /// between updates is computed with wrapping arithmetic, so the controller keeps working
+    /// when the millisecond counter wraps around.
+    pub fn update(&mut self, measurement: f32, measurement_time: Instant) -> f32 {
+        if self.last_time.is_after(measurement_time) {
+            warn!(
                 "Time went backwards! {} -> {}",
                 self.last_time, measurement_time
             );
             return 0.0;
         } else if self.last_time == measurement_time {
-            println!("Time didn't change! {}", measurement_time);
+            debug!("Time didn't change! {}", measurement_time);
             return 0.0;
         }
 
-        let dt = (measurement_time - self.last_time) as f32;
+        let dt = (measurement_time - self.last_time).as_millis() as f32;
         let error = self.setpoint - measurement;
         self.integral += error * dt;
         let derivative = (error - self.last_error) / dt;
-        let mut control_signal = self.kp * error + self.ki * self.integral + self.kd * derivative;
+        let mut control_signal =
+            self.kp * error + self.ki * self.integral + self.kd * derivative + self.feedforward;
         self.last_error = error;
         self.last_time = measurement_time;
         if self.max_control_signal > 0.0 && control_signal.abs() > self.max_control_signal {
@@ -73,13 +215,36 @@ impl PIDController {
     }
 
     /// Reset the controller to its initial state.
-    pub fn reset(&mut self, start_time: u32) {
+    pub fn reset(&mut self, start_time: Instant) {
         self.integral = 0.0;
         self.last_error = 0.0;
         self.last_time = start_time;
     }
 }
 
+impl Controller for PIDController {
+    fn update(&mut self, measurement: f32, measurement_time: Instant) -> f32 {
+        PIDController::update(self, measurement, measurement_time)
+    }
+
+    fn reset(&mut self, start_time: Instant) {
+        PIDController::reset(self, start_time);
+    }
+
+    fn set_setpoint(&mut self, setpoint: f32) {
+        PIDController::set_setpoint(self, setpoint);
+    }
+
+    fn set_operating_point(&mut self, operating_point: f32) {
+        PIDController::set_operating_point(self, operating_point);
+    }
+
+    fn error_integral(&self) -> f32 {
+        self.integral
+    }
+}
+
+#[cfg(target_arch = "avr")]
 impl uDebug for PIDController {
     fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
     where
@@ -87,17 +252,20 @@ impl uDebug for PIDController {
     {
         uwrite!(
             f,
-            "PIDController<kp: {}, ki: {}, kd: {}, setpoint: {}>",
+            "PIDController<kp: {}, ki: {}, kd: {}, setpoint: {}, feedforward: {}, scheduled: {}>",
             self.kp,
             self.ki,
             self.kd,
             self.setpoint,
+            self.feedforward,
+            self.gain_schedule.is_some(),
         )?;
 
         Ok(())
     }
 }
 
+#[cfg(target_arch = "avr")]
 impl uDisplay for PIDController {
     fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
     where
@@ -106,3 +274,101 @@ impl uDisplay for PIDController {
         uDebug::fmt(self, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SCHEDULE: [GainSchedulePoint; 3] = [
+        GainSchedulePoint::new(80.0, 10.0, 0.1, 1.0),
+        GainSchedulePoint::new(160.0, 20.0, 0.3, 3.0),
+        GainSchedulePoint::new(240.0, 30.0, 0.0, 3.0),
+    ];
+
+    fn assert_gains(gains: PidGains, kp: f32, ki: f32, kd: f32) {
+        assert!(
+            (gains.kp - kp).abs() < 1e-4
+                && (gains.ki - ki).abs() < 1e-6
+                && (gains.kd - kd).abs() < 1e-5,
+            "expected kp = {}, ki = {}, kd = {}, got kp = {}, ki = {}, kd = {}",
+            kp,
+            ki,
+            kd,
+            gains.kp,
+            gains.ki,
+            gains.kd
+        );
+    }
+
+    #[test]
+    fn interpolates_between_schedule_points() {
+        assert_gains(interpolate_gains(&SCHEDULE, 120.0).unwrap(), 15.0, 0.2, 2.0);
+        assert_gains(
+            interpolate_gains(&SCHEDULE, 100.0).unwrap(),
+            12.5,
+            0.15,
+            1.5,
+        );
+        assert_gains(interpolate_gains(&SCHEDULE, 160.0).unwrap(), 20.0, 0.3, 3.0);
+        assert_gains(
+            interpolate_gains(&SCHEDULE, 200.0).unwrap(),
+            25.0,
+            0.15,
+            3.0,
+        );
+    }
+
+    #[test]
+    fn operating_points_outside_the_schedule_get_the_end_point_gains() {
+        assert_gains(interpolate_gains(&SCHEDULE, 80.0).unwrap(), 10.0, 0.1, 1.0);
+        assert_gains(interpolate_gains(&SCHEDULE, 0.0).unwrap(), 10.0, 0.1, 1.0);
+        assert_gains(interpolate_gains(&SCHEDULE, 240.0).unwrap(), 30.0, 0.0, 3.0);
+        assert_gains(interpolate_gains(&SCHEDULE, 255.0).unwrap(), 30.0, 0.0, 3.0);
+        assert!(interpolate_gains(&[], 100.0).is_none());
+    }
+
+    #[test]
+    fn setting_a_schedule_replaces_the_gains() {
+        let mut controller = PIDController::new(1.0, 1.0, 1.0);
+        controller.set_operating_point(160.0);
+        assert_gains(controller.gains(), 1.0, 1.0, 1.0);
+        controller.set_gain_schedule(&SCHEDULE);
+        assert_gains(controller.gains(), 20.0, 0.3, 3.0);
+        controller.clear_gain_schedule();
+        controller.set_operating_point(80.0);
+        assert_gains(controller.gains(), 20.0, 0.3, 3.0);
+    }
+
+    #[test]
+    fn ki_changes_rescale_the_integral_to_keep_the_integral_term() {
+        let mut controller = PIDController::new(0.0, 0.0, 0.0);
+        controller.set_gain_schedule(&SCHEDULE);
+        controller.set_operating_point(80.0);
+        controller.integral = 30.0;
+        let integral_term = controller.ki * controller.integral;
+
+        controller.set_operating_point(160.0);
+        assert!((controller.ki - 0.3).abs() < 1e-6);
+        assert!((controller.integral - 10.0).abs() < 1e-4);
+        assert!((controller.ki * controller.integral - integral_term).abs() < 1e-4);
+
+        // a ki of zero has no integral term to keep, so the integral is left as it is
+        controller.set_operating_point(240.0);
+        assert!((controller.integral - 10.0).abs() < 1e-4);
+        controller.set_operating_point(160.0);
+        assert!((controller.integral - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn integral_term_doesnt_jump_when_the_operating_point_changes() {
+        let mut controller = PIDController::new(0.0, 0.0, 0.0);
+        controller.set_gain_schedule(&SCHEDULE);
+        controller.set_operating_point(80.0);
+        controller.reset(Instant::from_millis(0));
+        // kp and kd are scheduled too, so only the integral term is compared
+        controller.update(-1.0, Instant::from_millis(100));
+        let before = controller.ki * controller.integral;
+        controller.set_operating_point(120.0);
+        assert!((controller.ki * controller.integral - before).abs() < 1e-4);
+    }
+}
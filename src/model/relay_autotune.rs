@@ -0,0 +1,275 @@
+use core::f32::consts::PI;
+
+use micromath::F32Ext;
+use ufmt::{uDebug, uDisplay, uWrite, uwrite};
+
+use super::pid_controller::PidGains;
+use crate::system::time::{Duration, Instant};
+
+/// The number of oscillation cycles ignored at the start of the experiment while the
+/// system settles into a stable limit cycle.
+const SETTLING_CYCLES: u8 = 2;
+
+/// Runs an Åström–Hägglund relay feedback experiment on a control loop. The relay
+/// drives the loop into a limit cycle, and the amplitude and period of the resulting
+/// oscillation give the ultimate gain and ultimate period of the loop, from which
+/// PID gains can be computed.
+///
+/// The relay output is positive when the measurement is below the setpoint, so it acts
+/// in the same direction as a `PIDController` with positive gains.
+pub struct RelayAutoTuner {
+    setpoint: f32,
+    relay_amplitude: f32,
+    hysteresis: f32,
+    measured_cycles: u8,
+    output: f32,
+    cycle_start_time: Option<Instant>,
+    cycle_max: f32,
+    cycle_min: f32,
+    completed_cycles: u8,
+    period_sum: Duration,
+    amplitude_sum: f32,
+}
+
+#[allow(dead_code)]
+impl RelayAutoTuner {
+    /// Create a new relay experiment.
+    /// `relay_amplitude` is the magnitude of the relay output in control signal units.
+    /// `hysteresis` is the error band, in measurement units, that the measurement must
+    /// cross before the relay switches. It keeps sensor noise from chattering the relay.
+    /// `measured_cycles` is the number of oscillation cycles averaged for the result.
+    pub fn new(setpoint: f32, relay_amplitude: f32, hysteresis: f32, measured_cycles: u8) -> Self {
+        Self {
+            setpoint,
+            relay_amplitude,
+            hysteresis,
+            measured_cycles,
+            output: relay_amplitude,
+            cycle_start_time: None,
+            cycle_max: f32::MIN,
+            cycle_min: f32::MAX,
+            completed_cycles: 0,
+            period_sum: Duration::ZERO,
+            amplitude_sum: 0.0,
+        }
+    }
+
+    /// Update the relay with a new measurement and the time of the measurement. Returns the
+    /// relay output.
+    pub fn update(&mut self, measurement: f32, measurement_time: Instant) -> f32 {
+        if self.is_complete() {
+            return 0.0;
+        }
+        self.cycle_max = self.cycle_max.max(measurement);
+        self.cycle_min = self.cycle_min.min(measurement);
+
+        let error = self.setpoint - measurement;
+        if self.output > 0.0 && error < -self.hysteresis {
+            self.output = -self.relay_amplitude;
+        } else if self.output < 0.0 && error > self.hysteresis {
+            // a switch to positive output marks the end of one full oscillation cycle
+            self.output = self.relay_amplitude;
+            if let Some(start_time) = self.cycle_start_time {
+                self.completed_cycles += 1;
+                if self.completed_cycles > SETTLING_CYCLES {
+                    self.period_sum += measurement_time - start_time;
+                    self.amplitude_sum += (self.cycle_max - self.cycle_min) / 2.0;
+                }
+            }
+            self.cycle_start_time = Some(measurement_time);
+            self.cycle_max = measurement;
+            self.cycle_min = measurement;
+        }
+        self.output
+    }
+
+    /// Returns true once enough oscillation cycles have been measured.
+    pub fn is_complete(&self) -> bool {
+        self.measured_cycle_count() >= self.measured_cycles
+    }
+
+    /// The number of oscillation cycles measured so far, not counting the settling cycles.
+    pub fn measured_cycle_count(&self) -> u8 {
+        self.completed_cycles.saturating_sub(SETTLING_CYCLES)
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    /// Returns the experiment's result, or `None` if the experiment isn't complete or the
+    /// loop did not oscillate beyond the hysteresis band.
+    pub fn result(&self) -> Option<RelayTuningResult> {
+        let cycles = self.measured_cycle_count();
+        if !self.is_complete() || cycles == 0 {
+            return None;
+        }
+        let amplitude = self.amplitude_sum / cycles as f32;
+        if amplitude <= self.hysteresis {
+            return None;
+        }
+        // the describing function of a relay with hysteresis
+        let ultimate_gain = 4.0 * self.relay_amplitude
+            / (PI * (amplitude * amplitude - self.hysteresis * self.hysteresis).sqrt());
+        Some(RelayTuningResult {
+            ultimate_gain,
+            ultimate_period: self.period_sum.as_millis() as f32 / cycles as f32,
+            amplitude,
+        })
+    }
+}
+
+/// The measured ultimate gain and period of a control loop. The period is in milliseconds,
+/// so the suggested gains are in the units `PIDController` expects when it is updated with
+/// an `Instant`.
+#[derive(Copy, Clone)]
+pub struct RelayTuningResult {
+    pub ultimate_gain: f32,
+    pub ultimate_period: f32,
+    pub amplitude: f32,
+}
+
+#[allow(dead_code)]
+impl RelayTuningResult {
+    /// The classic Ziegler–Nichols PID tuning. Aggressive, with noticeable overshoot.
+    pub fn ziegler_nichols(&self) -> PidGains {
+        let kp = 0.6 * self.ultimate_gain;
+        let ti = self.ultimate_period / 2.0;
+        let td = self.ultimate_period / 8.0;
+        PidGains::new(kp, kp / ti, kp * td)
+    }
+
+    /// The Tyreus–Luyben PID tuning. More conservative than Ziegler–Nichols, with less
+    /// overshoot and better robustness.
+    pub fn tyreus_luyben(&self) -> PidGains {
+        let kp = self.ultimate_gain / 2.2;
+        let ti = 2.2 * self.ultimate_period;
+        let td = self.ultimate_period / 6.3;
+        PidGains::new(kp, kp / ti, kp * td)
+    }
+}
+
+// formatting f32 needs the firmware's fork of ufmt, which the host tests don't have
+#[cfg(target_arch = "avr")]
+impl uDebug for RelayTuningResult {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uwrite!(
+            f,
+            "RelayTuningResult<ultimate_gain: {}, ultimate_period: {}, amplitude: {}>",
+            self.ultimate_gain,
+            self.ultimate_period,
+            self.amplitude,
+        )
+    }
+}
+
+#[cfg(target_arch = "avr")]
+impl uDisplay for RelayTuningResult {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uDebug::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_PERIOD: u32 = 10;
+    const RELAY_AMPLITUDE: f32 = 10.0;
+    const HYSTERESIS: f32 = 0.2;
+
+    /// Feeds one cycle of a square wave about a setpoint of zero, starting at `start` ms: half a
+    /// period at `amplitude`, then half a period at `-amplitude`, sampled every 10 ms. Returns
+    /// the time the next cycle starts.
+    fn feed_cycle(relay: &mut RelayAutoTuner, start: u32, amplitude: f32, period: u32) -> u32 {
+        for time in (start..start + period).step_by(SAMPLE_PERIOD as usize) {
+            let measurement = if time < start + period / 2 {
+                amplitude
+            } else {
+                -amplitude
+            };
+            relay.update(measurement, Instant::from_millis(time));
+        }
+        start + period
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn relay_switches_outside_the_hysteresis_band() {
+        let mut relay = RelayAutoTuner::new(0.0, RELAY_AMPLITUDE, HYSTERESIS, 4);
+        assert_eq!(relay.update(0.1, Instant::from_millis(0)), RELAY_AMPLITUDE);
+        assert_eq!(
+            relay.update(0.3, Instant::from_millis(10)),
+            -RELAY_AMPLITUDE
+        );
+        assert_eq!(
+            relay.update(-0.1, Instant::from_millis(20)),
+            -RELAY_AMPLITUDE
+        );
+        assert_eq!(
+            relay.update(-0.3, Instant::from_millis(30)),
+            RELAY_AMPLITUDE
+        );
+    }
+
+    #[test]
+    fn measures_the_ultimate_gain_and_period_after_the_settling_cycles() {
+        let mut relay = RelayAutoTuner::new(0.0, RELAY_AMPLITUDE, HYSTERESIS, 4);
+        // a measured cycle runs from one switch to positive output to the next, which is from
+        // the start of one negative half period to the start of the next. the cycles that end
+        // at the start of the second and third negative half periods are the settling cycles,
+        // and they are larger and faster than the limit cycle.
+        let mut time = feed_cycle(&mut relay, 0, 5.0, 400);
+        time = feed_cycle(&mut relay, time, 5.0, 400);
+        time = feed_cycle(&mut relay, time, 1.0, 1000);
+        assert_eq!(relay.measured_cycle_count(), 0);
+        while !relay.is_complete() {
+            assert!(relay.result().is_none());
+            time = feed_cycle(&mut relay, time, 1.0, 1000);
+        }
+        assert_eq!(relay.measured_cycle_count(), 4);
+        // the experiment stops once it is complete
+        assert_eq!(relay.update(-1.0, Instant::from_millis(time)), 0.0);
+
+        let result = relay.result().unwrap();
+        let amplitude = 1.0f32;
+        assert_close(result.amplitude, amplitude);
+        assert_close(result.ultimate_period, 1000.0);
+        let expected_gain =
+            4.0 * RELAY_AMPLITUDE / (PI * (amplitude * amplitude - HYSTERESIS * HYSTERESIS).sqrt());
+        assert_close(result.ultimate_gain, expected_gain);
+    }
+
+    #[test]
+    fn suggests_ziegler_nichols_and_tyreus_luyben_gains() {
+        let result = RelayTuningResult {
+            ultimate_gain: 10.0,
+            ultimate_period: 1000.0,
+            amplitude: 1.0,
+        };
+        let gains = result.ziegler_nichols();
+        assert_close(gains.kp, 6.0);
+        assert_close(gains.ki, 6.0 / 500.0);
+        assert_close(gains.kd, 6.0 * 125.0);
+
+        let gains = result.tyreus_luyben();
+        let kp = 10.0 / 2.2;
+        assert_close(gains.kp, kp);
+        assert_close(gains.ki, kp / 2200.0);
+        assert_close(gains.kd, kp * 1000.0 / 6.3);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::system::time::{Duration, Instant};
+
+/// Measures how long a motor has been driven without its wheel turning. The wheel counts as
+/// turning while its encoder ticks or while its motor isn't driven hard enough to turn it.
+#[derive(Copy, Clone, Default)]
+pub struct StallTimer {
+    last_ticks: u32,
+    last_turning_time: Instant,
+}
+
+#[allow(dead_code)]
+impl StallTimer {
+    /// Starts timing at `start_time`, with the wheel's encoder tick count at zero.
+    pub fn new(start_time: Instant) -> Self {
+        Self {
+            last_ticks: 0,
+            last_turning_time: start_time,
+        }
+    }
+
+    /// Restarts the timer at `now` without changing the last tick count.
+    pub fn restart(&mut self, now: Instant) {
+        self.last_turning_time = now;
+    }
+
+    /// Records the motor's `duty` and the wheel's encoder `ticks` at time `now`, and returns how
+    /// long the wheel hasn't turned while its motor was driven at or above `stall_duty`.
+    pub fn update(&mut self, now: Instant, duty: u8, ticks: u32, stall_duty: u8) -> Duration {
+        if ticks != self.last_ticks || duty < stall_duty {
+            self.last_ticks = ticks;
+            self.last_turning_time = now;
+        }
+        now - self.last_turning_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STALL_DUTY: u8 = 100;
+
+    #[test]
+    fn times_a_driven_wheel_that_doesnt_tick() {
+        let mut timer = StallTimer::new(Instant::from_millis(1000));
+        assert_eq!(timer.update(Instant::from_millis(1100), 150, 0, STALL_DUTY).as_millis(), 100);
+        assert_eq!(timer.update(Instant::from_millis(1600), 150, 0, STALL_DUTY).as_millis(), 600);
+    }
+
+    #[test]
+    fn ticks_restart_the_timer() {
+        let mut timer = StallTimer::new(Instant::from_millis(0));
+        assert_eq!(timer.update(Instant::from_millis(300), 150, 2, STALL_DUTY).as_millis(), 0);
+        assert_eq!(timer.update(Instant::from_millis(500), 150, 2, STALL_DUTY).as_millis(), 200);
+    }
+
+    #[test]
+    fn a_wheel_driven_below_the_stall_duty_counts_as_turning() {
+        let mut timer = StallTimer::new(Instant::from_millis(0));
+        assert_eq!(timer.update(Instant::from_millis(800), 60, 0, STALL_DUTY).as_millis(), 0);
+        assert_eq!(timer.update(Instant::from_millis(900), 150, 0, STALL_DUTY).as_millis(), 100);
+    }
+
+    #[test]
+    fn restart_keeps_the_last_tick_count() {
+        let mut timer = StallTimer::new(Instant::from_millis(0));
+        timer.update(Instant::from_millis(100), 150, 5, STALL_DUTY);
+        timer.restart(Instant::from_millis(700));
+        assert_eq!(timer.update(Instant::from_millis(750), 150, 5, STALL_DUTY).as_millis(), 50);
+    }
+}
@@ -0,0 +1,604 @@
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::PwmPin;
+
+use crate::{
+    model::motor_output_limiter::MotorOutputLimiter,
+    system::time::{Duration, Instant},
+};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum MotorDirection {
+    Forward,
+    Reverse,
+    Coast,
+}
+
+/// The pins that drive one motor of an H-bridge. Each driver implements the pin levels of its
+/// H-bridge here, and `MotorChannel` does everything else.
+pub trait MotorPins {
+    /// Drives the motor with a signed output. A positive output drives the motor forward and a
+    /// negative output drives it in reverse, with the magnitude as the duty. Zero lets the motor
+    /// coast.
+    fn drive(&mut self, output: i16);
+
+    /// Actively brakes the motor by shorting its terminals.
+    fn brake(&mut self);
+
+    /// Returns the duty currently applied to the motor. It is zero while the motor coasts and
+    /// the max duty while it brakes.
+    fn get_duty(&self) -> u8;
+
+    fn get_max_duty(&self) -> u8;
+}
+
+/// One motor channel of a `DualMotorDriver`. It turns the requested duty and direction into the
+/// signed output that its pins apply, after the output limits.
+///
+/// The channel has no clock of its own. The caller passes the time to `update` and `brake`, and
+/// requests in between are applied with the time of the last of those calls. Since `update` is
+/// called every few milliseconds, that only delays a slew rate limited ramp by as much.
+pub struct MotorChannel<PINS> {
+    pins: PINS,
+    direction: MotorDirection,
+    duty: u8,
+    limiter: MotorOutputLimiter,
+    now: Instant,
+}
+
+#[allow(dead_code)]
+impl<PINS: MotorPins> MotorChannel<PINS> {
+    pub fn new(pins: PINS) -> Self {
+        Self {
+            pins,
+            direction: MotorDirection::Coast,
+            duty: 0,
+            limiter: MotorOutputLimiter::default(),
+            now: Instant::default(),
+        }
+    }
+
+    pub fn pins(&self) -> &PINS {
+        &self.pins
+    }
+
+    pub fn pins_mut(&mut self) -> &mut PINS {
+        &mut self.pins
+    }
+
+    /// Sets the duty without changing the direction.
+    pub fn set_duty(&mut self, duty: u8) {
+        self.duty = duty;
+        self.apply();
+    }
+
+    pub fn set_direction(&mut self, direction: MotorDirection) {
+        self.direction = direction;
+        self.apply();
+    }
+
+    /// Sets the signed speed. See `DualMotorDriver::set_speed_a`.
+    pub fn set_speed(&mut self, speed: i16) {
+        self.duty = speed.unsigned_abs().min(self.pins.get_max_duty() as u16) as u8;
+        self.direction = match speed {
+            0 => MotorDirection::Coast,
+            s if s > 0 => MotorDirection::Forward,
+            _ => MotorDirection::Reverse,
+        };
+        self.apply();
+    }
+
+    /// Returns the duty currently applied to the motor.
+    pub fn get_duty(&self) -> u8 {
+        self.pins.get_duty()
+    }
+
+    /// Actively brakes the motor at time `now`. The channel coasts once it is driven again, and
+    /// the direction change dead time applies from `now`.
+    pub fn brake(&mut self, now: Instant) {
+        self.now = now;
+        self.direction = MotorDirection::Coast;
+        self.limiter.force_stop(now.as_millis());
+        self.pins.brake();
+    }
+
+    /// Moves a limited output towards the requested output at time `now`.
+    pub fn update(&mut self, now: Instant) {
+        self.now = now;
+        if self.limiter.is_limiting() {
+            self.apply();
+        }
+    }
+
+    /// Returns true while the output hasn't reached the requested output yet.
+    pub fn is_limiting(&self) -> bool {
+        self.limiter.is_limiting()
+    }
+
+    pub fn set_slew_rate_limit(&mut self, max_duty_change_per_ms: u8) {
+        self.limiter
+            .set_max_duty_change_per_ms(max_duty_change_per_ms);
+    }
+
+    pub fn set_direction_change_dead_time(&mut self, dead_time: Duration) {
+        self.limiter.set_dead_time(dead_time.as_millis());
+    }
+
+    fn apply(&mut self) {
+        let target = match self.direction {
+            MotorDirection::Forward => self.duty as i16,
+            MotorDirection::Reverse => -(self.duty as i16),
+            MotorDirection::Coast => 0,
+        };
+        let output = self.limiter.update(target, self.now.as_millis());
+        self.pins.drive(output);
+    }
+}
+
+/// The pins of a motor channel with two direction inputs and a PWM pin, such as the L298N with
+/// its enable pin or the TB6612FNG with its PWM input. Both H-bridges brake a motor when both of
+/// its inputs are high while the PWM pin is high.
+pub struct DirectionPwmPins<IN1, IN2, PWM> {
+    in1: IN1,
+    in2: IN2,
+    pwm: PWM,
+}
+
+impl<IN1, IN2, PWM> DirectionPwmPins<IN1, IN2, PWM>
+where
+    IN1: OutputPin,
+    IN2: OutputPin,
+    PWM: PwmPin<Duty = u8>,
+{
+    pub fn new(in1: IN1, in2: IN2, pwm: PWM) -> Self {
+        Self { in1, in2, pwm }
+    }
+}
+
+impl<IN1, IN2, PWM> MotorPins for DirectionPwmPins<IN1, IN2, PWM>
+where
+    IN1: OutputPin,
+    IN2: OutputPin,
+    PWM: PwmPin<Duty = u8>,
+{
+    fn drive(&mut self, output: i16) {
+        if output == 0 {
+            // both inputs low puts the outputs in high impedance, so the motor coasts. The duty
+            // is zeroed too so that `get_duty` doesn't report it.
+            self.pwm.set_duty(0);
+            self.pwm.disable();
+            self.in1.set_low().ok();
+            self.in2.set_low().ok();
+        } else {
+            self.pwm.set_duty(output.unsigned_abs() as u8);
+            if output > 0 {
+                self.in1.set_high().ok();
+                self.in2.set_low().ok();
+            } else {
+                self.in1.set_low().ok();
+                self.in2.set_high().ok();
+            }
+            self.pwm.enable();
+        }
+    }
+
+    fn brake(&mut self) {
+        self.in1.set_high().ok();
+        self.in2.set_high().ok();
+        self.pwm.set_duty(self.pwm.get_max_duty());
+        self.pwm.enable();
+    }
+
+    fn get_duty(&self) -> u8 {
+        self.pwm.get_duty()
+    }
+
+    fn get_max_duty(&self) -> u8 {
+        self.pwm.get_max_duty()
+    }
+}
+
+/// A driver for two DC motors, such as the L298N, TB6612FNG or DRV8833 dual H-bridges. Motor A
+/// is the left motor and motor B is the right motor.
+///
+/// A driver provides its two motor channels, and the motors are controlled through the
+/// channels. A motor's duty and direction are set separately. The motor outputs may be slew rate
+/// limited, and a motor may be made to coast for a dead time when it changes direction, so
+/// `update` must be called regularly to move the outputs towards the requested outputs.
+#[allow(dead_code)]
+pub trait DualMotorDriver {
+    type PinsA: MotorPins;
+    type PinsB: MotorPins;
+
+    fn channel_a(&self) -> &MotorChannel<Self::PinsA>;
+
+    fn channel_a_mut(&mut self) -> &mut MotorChannel<Self::PinsA>;
+
+    fn channel_b(&self) -> &MotorChannel<Self::PinsB>;
+
+    fn channel_b_mut(&mut self) -> &mut MotorChannel<Self::PinsB>;
+
+    /// Sets the duty of motor A without changing its direction.
+    fn set_duty_a(&mut self, duty: u8) {
+        self.channel_a_mut().set_duty(duty);
+    }
+
+    /// Sets the duty of motor B without changing its direction.
+    fn set_duty_b(&mut self, duty: u8) {
+        self.channel_b_mut().set_duty(duty);
+    }
+
+    /// Returns the duty currently applied to motor A.
+    fn get_duty_a(&self) -> u8 {
+        self.channel_a().get_duty()
+    }
+
+    /// Returns the duty currently applied to motor B.
+    fn get_duty_b(&self) -> u8 {
+        self.channel_b().get_duty()
+    }
+
+    fn set_direction_a(&mut self, direction: MotorDirection) {
+        self.channel_a_mut().set_direction(direction);
+    }
+
+    fn set_direction_b(&mut self, direction: MotorDirection) {
+        self.channel_b_mut().set_direction(direction);
+    }
+
+    /// Sets the signed speed of motor A. A positive speed drives the motor forward and a negative
+    /// speed drives it in reverse, with the magnitude as the duty. The magnitude saturates at the
+    /// max duty. A speed of zero lets the motor coast.
+    fn set_speed_a(&mut self, speed: i16) {
+        self.channel_a_mut().set_speed(speed);
+    }
+
+    /// Sets the signed speed of motor B. See `set_speed_a`.
+    fn set_speed_b(&mut self, speed: i16) {
+        self.channel_b_mut().set_speed(speed);
+    }
+
+    /// Actively brakes motor A by shorting its terminals at time `now`. Braking is not slew
+    /// rate limited.
+    fn brake_a(&mut self, now: Instant) {
+        self.channel_a_mut().brake(now);
+    }
+
+    /// Actively brakes motor B by shorting its terminals at time `now`. Braking is not slew
+    /// rate limited.
+    fn brake_b(&mut self, now: Instant) {
+        self.channel_b_mut().brake(now);
+    }
+
+    /// Limits how quickly the motor duty may increase, in duty per millisecond. A limit of zero
+    /// disables the slew rate limit.
+    fn set_slew_rate_limit(&mut self, max_duty_change_per_ms: u8) {
+        self.channel_a_mut()
+            .set_slew_rate_limit(max_duty_change_per_ms);
+        self.channel_b_mut()
+            .set_slew_rate_limit(max_duty_change_per_ms);
+    }
+
+    /// Sets the minimum time that a motor coasts before it is driven in the opposite direction.
+    /// A dead time of zero disables it.
+    fn set_direction_change_dead_time(&mut self, dead_time: Duration) {
+        self.channel_a_mut()
+            .set_direction_change_dead_time(dead_time);
+        self.channel_b_mut()
+            .set_direction_change_dead_time(dead_time);
+    }
+
+    /// Moves limited motor outputs towards their requested outputs at time `now`. Call this
+    /// regularly from the main loop.
+    fn update(&mut self, now: Instant) {
+        self.channel_a_mut().update(now);
+        self.channel_b_mut().update(now);
+    }
+
+    fn set_duty(&mut self, duty_a: u8, duty_b: u8) {
+        self.set_duty_a(duty_a);
+        self.set_duty_b(duty_b);
+    }
+
+    fn set_speed(&mut self, speed_a: i16, speed_b: i16) {
+        self.set_speed_a(speed_a);
+        self.set_speed_b(speed_b);
+    }
+
+    fn forward(&mut self) {
+        self.forward_a();
+        self.forward_b();
+    }
+
+    fn forward_a(&mut self) {
+        self.set_direction_a(MotorDirection::Forward);
+    }
+
+    fn forward_b(&mut self) {
+        self.set_direction_b(MotorDirection::Forward);
+    }
+
+    fn reverse(&mut self) {
+        self.reverse_a();
+        self.reverse_b();
+    }
+
+    fn reverse_a(&mut self) {
+        self.set_direction_a(MotorDirection::Reverse);
+    }
+
+    fn reverse_b(&mut self) {
+        self.set_direction_b(MotorDirection::Reverse);
+    }
+
+    /// Lets both motors coast.
+    fn stop(&mut self) {
+        self.stop_a();
+        self.stop_b();
+    }
+
+    fn stop_a(&mut self) {
+        self.set_direction_a(MotorDirection::Coast);
+    }
+
+    fn stop_b(&mut self) {
+        self.set_direction_b(MotorDirection::Coast);
+    }
+
+    /// Actively brakes both motors at time `now`. Unlike `stop`, which lets the motors coast,
+    /// braking stops the wheels quickly.
+    fn brake(&mut self, now: Instant) {
+        self.brake_a(now);
+        self.brake_b(now);
+    }
+}
+
+/// Mock pins for the driver tests.
+#[cfg(test)]
+pub mod mock {
+    use embedded_hal::PwmPin;
+    use embedded_hal_mock::eh0::digital::{Mock, Transaction};
+
+    pub use embedded_hal_mock::eh0::digital::{
+        Mock as MockOutputPin, State, Transaction as PinTransaction,
+    };
+
+    /// Adapts the mock PWM pin, which has a `u16` duty, to the `u8` duty that the drivers use.
+    #[derive(Clone)]
+    pub struct MockPwmPin(Mock);
+
+    impl MockPwmPin {
+        pub fn new(expectations: &[Transaction]) -> Self {
+            Self(Mock::new(expectations))
+        }
+
+        pub fn done(&mut self) {
+            self.0.done();
+        }
+    }
+
+    impl PwmPin for MockPwmPin {
+        type Duty = u8;
+
+        fn disable(&mut self) {
+            self.0.disable();
+        }
+
+        fn enable(&mut self) {
+            self.0.enable();
+        }
+
+        fn get_duty(&self) -> u8 {
+            self.0.get_duty() as u8
+        }
+
+        fn get_max_duty(&self) -> u8 {
+            self.0.get_max_duty() as u8
+        }
+
+        fn set_duty(&mut self, duty: u8) {
+            self.0.set_duty(duty as u16);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{MockOutputPin, MockPwmPin, PinTransaction as T, State};
+    use super::*;
+
+    /// Records what a channel applied to its pins.
+    #[derive(Default)]
+    struct RecordingPins {
+        output: i16,
+        braked: bool,
+    }
+
+    impl MotorPins for RecordingPins {
+        fn drive(&mut self, output: i16) {
+            self.output = output;
+            self.braked = false;
+        }
+
+        fn brake(&mut self) {
+            self.braked = true;
+        }
+
+        fn get_duty(&self) -> u8 {
+            self.output.unsigned_abs() as u8
+        }
+
+        fn get_max_duty(&self) -> u8 {
+            200
+        }
+    }
+
+    fn at(millis: u32) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    fn channel() -> MotorChannel<RecordingPins> {
+        MotorChannel::new(RecordingPins::default())
+    }
+
+    #[test]
+    fn duty_and_direction_are_applied_separately() {
+        let mut channel = channel();
+        channel.set_duty(100);
+        assert_eq!(channel.pins().output, 0);
+        channel.set_direction(MotorDirection::Forward);
+        assert_eq!(channel.pins().output, 100);
+        channel.set_direction(MotorDirection::Reverse);
+        assert_eq!(channel.pins().output, -100);
+        channel.set_duty(40);
+        assert_eq!(channel.pins().output, -40);
+        assert_eq!(channel.get_duty(), 40);
+        channel.set_direction(MotorDirection::Coast);
+        assert_eq!(channel.pins().output, 0);
+    }
+
+    #[test]
+    fn speed_saturates_at_the_max_duty() {
+        let mut channel = channel();
+        channel.set_speed(150);
+        assert_eq!(channel.pins().output, 150);
+        channel.set_speed(-1000);
+        assert_eq!(channel.pins().output, -200);
+        channel.set_speed(i16::MIN);
+        assert_eq!(channel.pins().output, -200);
+        channel.set_speed(0);
+        assert_eq!(channel.pins().output, 0);
+    }
+
+    #[test]
+    fn slew_rate_limit_ramps_the_output_on_update() {
+        let mut channel = channel();
+        channel.set_slew_rate_limit(2);
+        channel.update(at(1000));
+        channel.set_speed(100);
+        assert_eq!(channel.pins().output, 2);
+        assert!(channel.is_limiting());
+        channel.update(at(1010));
+        assert_eq!(channel.pins().output, 22);
+        channel.update(at(1100));
+        assert_eq!(channel.pins().output, 100);
+        assert!(!channel.is_limiting());
+
+        // decreases aren't limited
+        channel.set_speed(10);
+        assert_eq!(channel.pins().output, 10);
+    }
+
+    #[test]
+    fn slew_rate_limit_ramps_across_a_clock_wraparound() {
+        let mut channel = channel();
+        channel.set_slew_rate_limit(2);
+        channel.update(at(u32::MAX - 4));
+        channel.set_speed(100);
+        assert_eq!(channel.pins().output, 2);
+        channel.update(at(5));
+        assert_eq!(channel.pins().output, 22);
+    }
+
+    #[test]
+    fn direction_change_coasts_for_the_dead_time() {
+        let mut channel = channel();
+        channel.set_direction_change_dead_time(Duration::from_millis(20));
+        channel.update(at(1000));
+        channel.set_speed(100);
+        assert_eq!(channel.pins().output, 100);
+        channel.set_speed(-100);
+        assert_eq!(channel.pins().output, 0);
+        channel.update(at(1019));
+        assert_eq!(channel.pins().output, 0);
+        channel.update(at(1020));
+        assert_eq!(channel.pins().output, -100);
+    }
+
+    #[test]
+    fn brake_starts_the_dead_time() {
+        let mut channel = channel();
+        channel.set_direction_change_dead_time(Duration::from_millis(20));
+        channel.set_speed(100);
+        channel.brake(at(500));
+        assert!(channel.pins().braked);
+        channel.update(at(510));
+        assert!(channel.pins().braked);
+        channel.set_speed(-100);
+        assert_eq!(channel.pins().output, 0);
+        channel.update(at(520));
+        assert_eq!(channel.pins().output, -100);
+    }
+
+    #[test]
+    fn brake_is_not_slew_rate_limited_and_then_coasts() {
+        let mut channel = channel();
+        channel.set_slew_rate_limit(1);
+        channel.set_speed(100);
+        channel.brake(at(10));
+        assert!(channel.pins().braked);
+        assert!(!channel.is_limiting());
+        channel.set_direction(MotorDirection::Coast);
+        assert!(!channel.pins().braked);
+        assert_eq!(channel.pins().output, 0);
+    }
+
+    #[test]
+    fn direction_pwm_pins_drive_the_inputs_and_the_pwm_pin() {
+        let mut in1 = MockOutputPin::new(&[
+            T::set(State::High),
+            T::set(State::Low),
+            T::set(State::Low),
+            T::set(State::High),
+        ]);
+        let mut in2 = MockOutputPin::new(&[
+            T::set(State::Low),
+            T::set(State::High),
+            T::set(State::Low),
+            T::set(State::High),
+        ]);
+        let mut pwm = MockPwmPin::new(&[
+            T::set_duty(120),
+            T::enable(),
+            T::set_duty(60),
+            T::enable(),
+            T::set_duty(0),
+            T::disable(),
+            T::get_max_duty(255),
+            T::set_duty(255),
+            T::enable(),
+        ]);
+        let mut pins = DirectionPwmPins::new(in1.clone(), in2.clone(), pwm.clone());
+        pins.drive(120);
+        pins.drive(-60);
+        pins.drive(0);
+        pins.brake();
+        in1.done();
+        in2.done();
+        pwm.done();
+    }
+
+    #[test]
+    fn direction_pwm_pins_report_no_duty_while_coasting() {
+        let mut in1 = MockOutputPin::new(&[T::set(State::High), T::set(State::Low)]);
+        let mut in2 = MockOutputPin::new(&[T::set(State::Low), T::set(State::Low)]);
+        let mut pwm = MockPwmPin::new(&[
+            T::get_max_duty(255),
+            T::set_duty(150),
+            T::enable(),
+            T::get_duty(150),
+            T::get_max_duty(255),
+            T::set_duty(0),
+            T::disable(),
+            T::get_duty(0),
+        ]);
+        let mut channel =
+            MotorChannel::new(DirectionPwmPins::new(in1.clone(), in2.clone(), pwm.clone()));
+        channel.set_speed(150);
+        assert_eq!(channel.get_duty(), 150);
+        channel.set_speed(0);
+        assert_eq!(channel.get_duty(), 0);
+        in1.done();
+        in2.done();
+        pwm.done();
+    }
+}
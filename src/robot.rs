@@ -1,35 +1,126 @@
-use arduino_hal::{delay_ms, I2c};
-use micromath::F32Ext;
-use ufmt::{uDebug, uDisplay, uWrite, uwrite, Formatter};
+use arduino_hal::{delay_ms, Eeprom, I2c};
+use ufmt::uDisplay;
 
 use crate::{
-    l298n::motor_controller::MotorController,
     model::{
-        heading_calculator::HeadingCalculator, motor_calibration::get_lr_motor_power,
-        pid_controller::PIDController,
+        bang_bang_controller::BangBangController,
+        controller::Controller,
+        heading_calculator::HeadingCalculator,
+        lead_lag_controller::LeadLagController,
+        motion_monitor::{MotionError, MotionMonitor, MotionMonitorLimits},
+        motor_calibration::MotorPowerRatios,
+        motor_characterization::{MotorCharacterization, Wheel},
+        motor_power::allocate_motor_power,
+        motor_protection::{CurrentSensor, MotorFault, MotorProtection, MotorProtectionLimits},
+        pid_controller::{PIDController, PidGains},
+        relay_autotune::{RelayAutoTuner, RelayTuningResult},
     },
-    print_with_fn, println,
-    system::{data_logging::log_csv_headers, millis::millis},
-    telemetry::{ForwardMovementTelemetryRow, FORWARD_MOVEMENT_TELEMETRY_HEADERS},
+    debug, error, info,
+    motor_driver::DualMotorDriver,
+    println,
+    shell::{
+        parser::{Command, LineReader, ShellError},
+        CommandShell,
+    },
+    system::{
+        battery_monitor::BatteryMonitor,
+        scheduler::{Scheduler, TaskStats},
+        serial_print::read_console_byte,
+        settings::PersistentSettings,
+        time::{Duration, Instant},
+    },
+    telemetry::{
+        drain_telemetry, flush_telemetry, log_telemetry_headers, log_telemetry_row,
+        take_dropped_telemetry_records, telemetry_row::TelemetryRow,
+        ForwardMovementTelemetryRow, MotionDiagnosticRow, RelayAutoTuneTelemetryRow,
+        TelemetryLog, TelemetryStream,
+    },
+    warn,
 };
 use avr_device::atmega2560::exint::{eicra, eimsk};
 use avr_device::generic::Reg;
 use avr_device::interrupt;
 use avr_device::interrupt::Mutex;
 use core::cell::Cell;
-use embedded_hal::{
-    digital::v2::{InputPin, OutputPin},
-    PwmPin,
-};
+use embedded_hal::digital::v2::InputPin;
 
 const WHEEL_CIRCUMFERENCE: f32 = 214.0; // millimeters
 const WHEEL_BASE: f32 = 132.5; // millimeters
 const WHEEL_ENCODER_TICK_COUNT: u32 = 20;
-const CONTROL_LOOP_PERIOD: u32 = 75; // milliseconds
+const CONTROL_LOOP_PERIOD: Duration = Duration::from_millis(75);
+const BRAKE_TIME: u16 = 100; // milliseconds
+// limit how fast the motor power rises so that large changes don't brown out the Mega
+const MOTOR_SLEW_RATE_LIMIT: u8 = 2; // duty per millisecond
+const MOTOR_DIRECTION_CHANGE_DEAD_TIME: Duration = Duration::from_millis(20);
+
+// motor protection
+const MOTOR_PROTECTION_SAMPLE_PERIOD: Duration = Duration::from_millis(10);
+const MOTOR_PROTECTION_LIMITS: MotorProtectionLimits = MotorProtectionLimits {
+    overcurrent_limit: 1500, // milliamps
+    overcurrent_samples: 3,
+    stall_duty: 100,
+    stall_current: 600, // milliamps
+    stall_time: Duration::from_millis(500),
+};
+
+// movement monitoring. a single encoder tick difference between the wheels is a heading change
+// of about 0.08 radians, so the limits leave room for the encoder resolution.
+const MOTION_MONITOR_LIMITS: MotionMonitorLimits = MotionMonitorLimits {
+    stall_duty: 100,
+    stall_time: Duration::from_millis(750),
+    spin_out_rate: 4.0, // radians per second
+    slip_heading: 0.5,  // radians
+};
+
+// scheduled tasks. when several tasks are due, the one with the highest priority runs first.
+const ROBOT_TASK_CAPACITY: usize = 8;
+const MOTOR_UPDATE_PERIOD: Duration = Duration::from_millis(1);
+const HEADING_UPDATE_PERIOD: Duration = Duration::from_millis(50);
+const BATTERY_SAMPLE_PERIOD: Duration = Duration::from_millis(50);
+const TELEMETRY_DRAIN_PERIOD: Duration = Duration::from_millis(1);
+const TELEMETRY_LOG_WRITE_PERIOD: Duration = Duration::from_millis(10);
+const MOTOR_PROTECTION_PRIORITY: u8 = 6;
+const MOTOR_UPDATE_PRIORITY: u8 = 5;
+const HEADING_UPDATE_PRIORITY: u8 = 4;
+const CONTROL_LOOP_PRIORITY: u8 = 3;
+const BATTERY_MONITOR_PRIORITY: u8 = 2;
+const TELEMETRY_DRAIN_PRIORITY: u8 = 1;
+// writing to the SD card takes a few milliseconds per block, so it runs last
+const TELEMETRY_LOG_WRITE_PRIORITY: u8 = 0;
 
 const HEADING_PID_CONTROLLER_KP: f32 = 20.0;
 const HEADING_PID_CONTROLLER_KI: f32 = 0.0;
 const HEADING_PID_CONTROLLER_KD: f32 = 0.0;
+// the heading controllers are limited to this much turn correction, in motor power
+const HEADING_MAX_CONTROL_SIGNAL: f32 = 30.0;
+// alternative heading controllers, to compare against the PID controller on the same course
+const HEADING_BANG_BANG_OUTPUT: f32 = 10.0; // motor power
+const HEADING_BANG_BANG_HYSTERESIS: f32 = 0.02; // radians
+const HEADING_LEAD_LAG_GAIN: f32 = 20.0;
+const HEADING_LEAD_LAG_LEAD_TIME: f32 = 150.0; // milliseconds
+const HEADING_LEAD_LAG_LAG_TIME: f32 = 50.0; // milliseconds
+
+// in place turn parameters
+const TURN_POWER: u8 = 110;
+// slow down close to the target heading so that the turn doesn't overshoot
+const TURN_SLOW_POWER: u8 = 80;
+const TURN_SLOW_DOWN_ANGLE: f32 = 0.35; // radians
+const TURN_TOLERANCE: f32 = 0.035; // radians
+const TURN_TIMEOUT: Duration = Duration::from_secs(5);
+// the turn controllers reach the full turn power at the slow down angle
+const TURN_GAIN: f32 = TURN_POWER as f32 / TURN_SLOW_DOWN_ANGLE; // motor power per radian
+const TURN_LEAD_LAG_LEAD_TIME: f32 = 100.0; // milliseconds
+const TURN_LEAD_LAG_LAG_TIME: f32 = 50.0; // milliseconds
+
+// relay auto-tune experiment parameters
+const AUTOTUNE_TARGET_POWER: u8 = 125;
+const AUTOTUNE_MEASURED_CYCLES: u8 = 4;
+const AUTOTUNE_TIMEOUT: Duration = Duration::from_secs(20);
+const HEADING_AUTOTUNE_RELAY_AMPLITUDE: f32 = 20.0; // motor power
+const HEADING_AUTOTUNE_HYSTERESIS: f32 = 0.02; // radians
+const WHEEL_SPEED_AUTOTUNE_RELAY_AMPLITUDE: f32 = 40.0; // motor power
+const WHEEL_SPEED_AUTOTUNE_HYSTERESIS: f32 = 10.0; // millimeters per second
+const WHEEL_SPEED_AUTOTUNE_SPIN_UP_TIME: Duration = Duration::from_secs(1);
 
 static LEFT_WHEEL_COUNTER: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
 static RIGHT_WHEEL_COUNTER: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
@@ -52,46 +143,74 @@ fn INT2() {
     });
 }
 
+/// The tasks that the robot's scheduler runs from `handle_loop`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum RobotTask {
+    UpdateMotors,
+    CheckMotorProtection,
+    UpdateHeading,
+    UpdateBatteryMonitor,
+    DrainTelemetry,
+    /// Writes the rows queued in the telemetry log, such as to the SD card.
+    WriteTelemetryLog,
+    /// A movement's control loop. `handle_loop` returns it to the movement, which runs it.
+    ControlLoop,
+}
+
+impl uDisplay for RobotTask {
+    fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: ufmt::uWrite + ?Sized,
+    {
+        let name = match self {
+            RobotTask::UpdateMotors => "motors",
+            RobotTask::CheckMotorProtection => "motor protection",
+            RobotTask::UpdateHeading => "heading",
+            RobotTask::UpdateBatteryMonitor => "battery monitor",
+            RobotTask::DrainTelemetry => "telemetry",
+            RobotTask::WriteTelemetryLog => "telemetry log",
+            RobotTask::ControlLoop => "control loop",
+        };
+        f.write_str(name)
+    }
+}
+
 /// This is the main hardware abstractions for the robot. It is repsponsible for setting up
 /// and providing access to the robot's hardware.
-pub struct Robot<
-    INA1: OutputPin,
-    INA2: OutputPin,
-    INB1: OutputPin,
-    INB2: OutputPin,
-    ENA: PwmPin<Duty = u8>,
-    ENB: PwmPin<Duty = u8>,
-    BUTT1: InputPin,
-> {
-    motors: MotorController<INA1, INA2, INB1, INB2, ENA, ENB>,
+pub struct Robot<MOTORS: DualMotorDriver, BUTT1: InputPin, CS: CurrentSensor, LOG: TelemetryLog>
+{
+    motors: MOTORS,
+    motor_protection: MotorProtection<CS>,
+    battery_monitor: BatteryMonitor,
     button: BUTT1,
     button_pressed: bool,
     heading_calculator: HeadingCalculator,
+    settings: PersistentSettings,
+    heading_pid_gains: PidGains,
+    motor_power_ratios: MotorPowerRatios,
+    motor_characterization: Option<MotorCharacterization>,
+    telemetry_log: LOG,
+    scheduler: Scheduler<RobotTask, ROBOT_TASK_CAPACITY>,
+    /// Reads the console while a movement blocks the command shell.
+    console_reader: LineReader,
+    /// A `stop` command was received during the current movement.
+    stop_requested: bool,
 }
 
-
 #[allow(dead_code)]
-impl<
-        INA1: OutputPin,
-        INA2: OutputPin,
-        INB1: OutputPin,
-        INB2: OutputPin,
-        ENA: PwmPin<Duty = u8>,
-        ENB: PwmPin<Duty = u8>,
-        BUTT1: InputPin,
-    > Robot<INA1, INA2, INB1, INB2, ENA, ENB, BUTT1>
+impl<MOTORS: DualMotorDriver, BUTT1: InputPin, CS: CurrentSensor, LOG: TelemetryLog>
+    Robot<MOTORS, BUTT1, CS, LOG>
 {
     pub fn new(
-        ina1_pin: INA1,
-        ina2_pin: INA2,
-        inb1_pin: INB1,
-        inb2_pin: INB2,
-        ena_pin: ENA,
-        enb_pin: ENB,
+        mut motors: MOTORS,
         button_pin: BUTT1,
         eicra: &Reg<eicra::EICRA_SPEC>,
         eimsk: &Reg<eimsk::EIMSK_SPEC>,
         i2c: I2c,
+        eeprom: Eeprom,
+        current_sensor: CS,
+        battery_monitor: BatteryMonitor,
+        telemetry_log: LOG,
     ) -> Self {
         // set up wheel counter interupts
         eicra.modify(|_, w| w.isc2().val_0x03());
@@ -102,25 +221,301 @@ impl<
         });
         // create self structure
         let heading_calculator = HeadingCalculator::new(i2c);
+        let settings = PersistentSettings::new(eeprom);
+        let heading_pid_gains = match settings.heading_pid_gains() {
+            Some(gains) => {
+                println!("Loaded saved heading PID gains: {}", gains);
+                gains
+            }
+            None => PidGains::new(
+                HEADING_PID_CONTROLLER_KP,
+                HEADING_PID_CONTROLLER_KI,
+                HEADING_PID_CONTROLLER_KD,
+            ),
+        };
+        let motor_power_ratios = match settings.motor_power_ratios() {
+            Some(ratios) => {
+                println!("Loaded saved L/R power ratio table");
+                ratios
+            }
+            None => MotorPowerRatios::default(),
+        };
+        let motor_characterization = settings.motor_characterization();
+        if motor_characterization.is_some() {
+            println!("Loaded saved motor speed characterization");
+        }
+
+        println!(
+            "Battery voltage = {} V, state of charge = {}%",
+            battery_monitor.voltage(),
+            battery_monitor.state_of_charge(),
+        );
+
+        motors.set_slew_rate_limit(MOTOR_SLEW_RATE_LIMIT);
+        motors.set_direction_change_dead_time(MOTOR_DIRECTION_CHANGE_DEAD_TIME);
+
+        // the scheduler has room for all of the robot's tasks, so adding them can't fail
+        let mut scheduler = Scheduler::new();
+        let _ = scheduler.add_periodic(
+            RobotTask::CheckMotorProtection,
+            MOTOR_PROTECTION_SAMPLE_PERIOD,
+            MOTOR_PROTECTION_PRIORITY,
+        );
+        let _ = scheduler.add_periodic(
+            RobotTask::UpdateMotors,
+            MOTOR_UPDATE_PERIOD,
+            MOTOR_UPDATE_PRIORITY,
+        );
+        let _ = scheduler.add_periodic(
+            RobotTask::UpdateHeading,
+            HEADING_UPDATE_PERIOD,
+            HEADING_UPDATE_PRIORITY,
+        );
+        let _ = scheduler.add_periodic(
+            RobotTask::UpdateBatteryMonitor,
+            BATTERY_SAMPLE_PERIOD,
+            BATTERY_MONITOR_PRIORITY,
+        );
+        let _ = scheduler.add_periodic(
+            RobotTask::DrainTelemetry,
+            TELEMETRY_DRAIN_PERIOD,
+            TELEMETRY_DRAIN_PRIORITY,
+        );
+        let _ = scheduler.add_periodic(
+            RobotTask::WriteTelemetryLog,
+            TELEMETRY_LOG_WRITE_PERIOD,
+            TELEMETRY_LOG_WRITE_PRIORITY,
+        );
 
         println!("Robot initialized");
         Self {
-            motors: MotorController::new(ina1_pin, ina2_pin, inb1_pin, inb2_pin, ena_pin, enb_pin),
+            motors,
+            motor_protection: MotorProtection::new(current_sensor, MOTOR_PROTECTION_LIMITS),
+            battery_monitor,
             button: button_pin,
             button_pressed: false,
             heading_calculator,
+            settings,
+            heading_pid_gains,
+            motor_power_ratios,
+            motor_characterization,
+            telemetry_log,
+            scheduler,
+            console_reader: LineReader::new(),
+            stop_requested: false,
         }
-        
     }
 
-    /// This function is called in the main loop to allow the robot to handle state updates
-    pub fn handle_loop(&mut self) {
+    /// Logs a telemetry table's header row to the console and the telemetry log.
+    fn log_headers<R: TelemetryRow>(&mut self, stream: TelemetryStream) {
+        log_telemetry_headers::<R>(stream);
+        self.telemetry_log.log_headers::<R>(stream);
+    }
+
+    /// Logs a telemetry row to the console and the telemetry log.
+    fn log_row<R: TelemetryRow>(&mut self, stream: TelemetryStream, row: &R) {
+        log_telemetry_row(stream, row);
+        self.telemetry_log.log_row(stream, row);
+    }
+
+    /// Sends the run's queued telemetry to the console, reports any rows the telemetry recorder
+    /// dropped, and ends the run in the telemetry log.
+    fn end_telemetry_run(&mut self) {
+        flush_telemetry();
+        let dropped_rows = take_dropped_telemetry_records();
+        if dropped_rows > 0 {
+            println!("Telemetry recorder dropped {} rows", dropped_rows);
+        }
+        self.telemetry_log.end_run();
+    }
+
+    /// This function is called in the main loop to allow the robot to handle state updates. It
+    /// runs the robot's due tasks. A due task that the caller runs itself, such as a movement's
+    /// control loop, is returned instead, and the caller calls `finish_task` once it has run it.
+    /// The heading is updated right before the control loop is returned, so the control loop
+    /// doesn't act on a heading that is up to a heading update period old.
+    ///
+    /// While a movement's control loop is running, the command shell can't read the console, so
+    /// `handle_loop` reads it to catch a `stop` command.
+    pub fn handle_loop(&mut self) -> Option<RobotTask> {
         // unset button press if button is not pressed
         if self.button.is_high().ok().unwrap() {
             self.button_pressed = false;
         }
 
+        if self.scheduler.contains(RobotTask::ControlLoop) {
+            self.poll_console();
+        }
+
+        // run at most as many tasks as fit in the scheduler, so that handle_loop returns even
+        // when the tasks fall behind
+        for _ in 0..ROBOT_TASK_CAPACITY {
+            let task = match self.scheduler.start_next() {
+                Some(task) => task,
+                None => break,
+            };
+            match task {
+                RobotTask::UpdateMotors => self.motors.update(Instant::now()),
+                RobotTask::CheckMotorProtection => self.check_motor_protection(),
+                RobotTask::UpdateHeading => self.heading_calculator.update(),
+                RobotTask::UpdateBatteryMonitor => self.update_battery_monitor(),
+                RobotTask::DrainTelemetry => drain_telemetry(),
+                RobotTask::WriteTelemetryLog => self.telemetry_log.write_queued(),
+                RobotTask::ControlLoop => {
+                    self.update_heading();
+                    return Some(task);
+                }
+            }
+            self.scheduler.finish(task);
+        }
+        None
+    }
+
+    /// Reads the command lines received during a movement. A `stop` line requests that the
+    /// movement stops, and any other command is rejected, since the shell runs one command at a
+    /// time.
+    fn poll_console(&mut self) {
+        while let Some(byte) = read_console_byte() {
+            match self.console_reader.push(byte) {
+                Some(Ok(Command::Stop)) => {
+                    self.stop_requested = true;
+                    CommandShell::report(Ok(()));
+                }
+                Some(Ok(_)) => CommandShell::report(Err(ShellError::Busy)),
+                Some(Err(error)) => CommandShell::report(Err(error)),
+                None => {}
+            }
+        }
+    }
+
+    /// Reads the gyro now rather than waiting for the heading update task, which then runs one
+    /// period from now.
+    fn update_heading(&mut self) {
         self.heading_calculator.update();
+        self.scheduler.restart(RobotTask::UpdateHeading);
+    }
+
+    /// Records that the caller has run a task returned by `handle_loop`.
+    pub fn finish_task(&mut self, task: RobotTask) {
+        self.scheduler.finish(task);
+    }
+
+    /// Iterates over the robot's scheduled tasks and their run statistics.
+    pub fn task_stats(&self) -> impl Iterator<Item = (RobotTask, TaskStats)> + '_ {
+        self.scheduler.task_stats()
+    }
+
+    /// Starts running a movement's control loop every control loop period.
+    fn start_control_loop(&mut self) {
+        self.stop_requested = false;
+        // the scheduler has room for all of the robot's tasks
+        let _ = self.scheduler.add_periodic(
+            RobotTask::ControlLoop,
+            CONTROL_LOOP_PERIOD,
+            CONTROL_LOOP_PRIORITY,
+        );
+    }
+
+    fn stop_control_loop(&mut self) {
+        self.scheduler.remove(RobotTask::ControlLoop);
+    }
+
+    fn update_battery_monitor(&mut self) {
+        let was_below_cutoff = self.battery_monitor.is_below_cutoff();
+        self.battery_monitor.update();
+        if self.battery_monitor.is_below_cutoff() != was_below_cutoff {
+            if was_below_cutoff {
+                info!("Battery recovered: {} V", self.battery_monitor.voltage());
+            } else {
+                warn!("Battery low: {} V", self.battery_monitor.voltage());
+            }
+        }
+    }
+
+    /// The filtered battery voltage in volts.
+    pub fn battery_voltage(&self) -> f32 {
+        self.battery_monitor.voltage()
+    }
+
+    /// The estimated battery state of charge in percent.
+    pub fn battery_state_of_charge(&self) -> u8 {
+        self.battery_monitor.state_of_charge()
+    }
+
+    /// Returns true while the battery voltage is below the cutoff. Movements refuse to start
+    /// while the battery is low.
+    pub fn is_battery_low(&self) -> bool {
+        self.battery_monitor.is_below_cutoff()
+    }
+
+    /// Sets the motor duties as they are, without compensating for the battery voltage.
+    fn set_motor_duty(&mut self, left_duty: u8, right_duty: u8) {
+        self.motors.set_duty(left_duty, right_duty);
+    }
+
+    /// Sets the motor duties with `allocate_motor_power`. The base power is compensated for the
+    /// battery voltage before it's allocated, so that the turn correction and the desaturation
+    /// apply to the duties the motors actually get.
+    fn set_motor_power(&mut self, base_power: f32, lr_ratio: f32, turn_correction: f32) {
+        let base_power = self.battery_monitor.compensate_power(base_power);
+        let (left_duty, right_duty) = allocate_motor_power(base_power, lr_ratio, turn_correction);
+        self.set_motor_duty(left_duty, right_duty);
+    }
+
+    /// Samples the motor protection and keeps the motor power cut while a fault is latched.
+    fn check_motor_protection(&mut self) {
+        let now = Instant::now();
+        let duties = (self.motors.get_duty_a(), self.motors.get_duty_b());
+        let ticks = (self.get_left_wheel_counter(), self.get_right_wheel_counter());
+        if let Some(fault) = self.motor_protection.check(now, duties, ticks) {
+            error!("Motor fault: {}", fault);
+        }
+        if self.motor_fault().is_some() {
+            self.motors.stop();
+        }
+    }
+
+    /// Returns the latched motor fault, if any. The motors are kept stopped and movements end
+    /// early while a fault is latched.
+    pub fn motor_fault(&self) -> Option<MotorFault> {
+        self.motor_protection.fault()
+    }
+
+    /// Clears the latched motor fault so that the robot can move again.
+    pub fn clear_motor_fault(&mut self) {
+        self.motor_protection.clear_fault(Instant::now());
+        info!("Motor fault cleared");
+    }
+
+    /// Returns the most recently sampled (left, right) motor currents in milliamps.
+    pub fn motor_currents(&self) -> (u16, u16) {
+        self.motor_protection.currents()
+    }
+
+    /// Returns the gyro heading in radians since the last movement started. Left turns are
+    /// positive.
+    pub fn heading(&self) -> f32 {
+        self.heading_calculator.heading()
+    }
+
+    pub fn heading_pid_gains(&self) -> PidGains {
+        self.heading_pid_gains
+    }
+
+    /// Sets the heading PID gains used by subsequent movements, without saving them.
+    pub fn set_heading_pid_gains(&mut self, gains: PidGains) {
+        self.heading_pid_gains = gains;
+    }
+
+    /// Saves the current heading PID gains to persistent settings.
+    pub fn save_heading_pid_gains(&mut self) {
+        self.settings.save_heading_pid_gains(&self.heading_pid_gains);
+        println!("Saved heading PID gains");
+    }
+
+    /// Returns the telemetry code of the latched motor fault, or zero if there is none.
+    fn motor_fault_code(&self) -> u8 {
+        self.motor_fault().map_or(0, |fault| fault.code())
     }
 
     /// Resets the wheel counters to 0
@@ -162,7 +557,7 @@ impl<
         // the button is active low
         if self.button.is_low().ok().unwrap() {
             if !self.button_pressed {
-                println!("robot button pressed");
+                debug!("robot button pressed");
                 self.button_pressed = true;
                 return true;
             }
@@ -172,23 +567,54 @@ impl<
         false
     }
 
-    pub fn straight(&mut self, distance_mm: u32) -> &mut Self {
+    /// Moves the robot straight ahead for `distance_mm` millimeters, holding the heading with
+    /// the heading PID controller.
+    pub fn straight(&mut self, distance_mm: u32) -> Result<&mut Self, MotionError> {
+        let mut controller = PIDController::from_gains(&self.heading_pid_gains);
+        controller.set_max_control_signal(HEADING_MAX_CONTROL_SIGNAL);
+        self.straight_with_controller(distance_mm, controller)
+    }
+
+    /// A bang-bang heading controller for `straight_with_controller`.
+    pub fn heading_bang_bang_controller(&self) -> BangBangController {
+        BangBangController::new(HEADING_BANG_BANG_OUTPUT, HEADING_BANG_BANG_HYSTERESIS)
+    }
+
+    /// A lead-lag heading controller for `straight_with_controller`.
+    pub fn heading_lead_lag_controller(&self) -> LeadLagController {
+        let mut controller = LeadLagController::new(
+            HEADING_LEAD_LAG_GAIN,
+            HEADING_LEAD_LAG_LEAD_TIME,
+            HEADING_LEAD_LAG_LAG_TIME,
+        );
+        controller.set_max_control_signal(HEADING_MAX_CONTROL_SIGNAL);
+        controller
+    }
+
+    /// Moves the robot straight ahead for `distance_mm` millimeters, holding the heading with
+    /// the passed controller. A positive control signal turns the robot left.
+    ///
+    /// The movement is refused if the battery is low, and aborted with an error if a wheel stalls
+    /// or slips, the robot spins out, the motor protection latches a fault, or a `stop` command
+    /// is received.
+    pub fn straight_with_controller<C: Controller + uDisplay>(
+        &mut self,
+        distance_mm: u32,
+        mut controller: C,
+    ) -> Result<&mut Self, MotionError> {
         println!("Robot move straight, distance = {}", distance_mm);
+        if self.is_battery_low() {
+            return Err(MotionError::LowBattery);
+        }
         let target_power: u8 = 125;
-        let (left_target_power, right_target_power) = get_lr_motor_power(target_power);
-        let mut controller = PIDController::new(
-            HEADING_PID_CONTROLLER_KP,
-            HEADING_PID_CONTROLLER_KI,
-            HEADING_PID_CONTROLLER_KD,
-        );
+        let lr_ratio = self.motor_power_ratios.lr_ratio(target_power);
         // we want a heading of 0.0 (straight ahead)
         controller.set_setpoint(0.0);
-        controller.set_max_control_signal(30.0);
         println!("controller = {}", controller);
         // heading is in radians
         let mut heading: f32 = 0.0;
 
-        self.motors.set_duty(left_target_power, right_target_power);
+        self.set_motor_power(target_power as f32, lr_ratio, 0.0);
 
         let target_wheel_tick_count: u32 =
             1 + ((WHEEL_ENCODER_TICK_COUNT * distance_mm) as f32 / WHEEL_CIRCUMFERENCE) as u32;
@@ -200,37 +626,52 @@ impl<
             target_wheel_tick_count,
         );
 
-        print_with_fn!(|f| { log_csv_headers(f, &FORWARD_MOVEMENT_TELEMETRY_HEADERS,) });
+        self.telemetry_log.start_run();
+        self.log_headers::<ForwardMovementTelemetryRow>(TelemetryStream::ForwardMovement);
         let mut last_left_ticks = 0;
         let mut last_right_ticks = 0;
-        let mut last_checkin_time = millis();
-        controller.reset(last_checkin_time);
+        let start_time = Instant::now();
+        controller.reset(start_time);
         self.heading_calculator.reset();
+        let mut motion_monitor = MotionMonitor::new(MOTION_MONITOR_LIMITS);
+        motion_monitor.reset(start_time);
         self.motors.forward();
 
-        let mut data_row = ForwardMovementTelemetryRow::new(
-            last_checkin_time,
-            0,
-            0,
-            0.0,
-            0.0,
-            0.0,
-            self.heading_calculator.heading(),
-            0.0,
-            controller.integral,
-            self.motors.get_duty_a(),
-            self.motors.get_duty_b(),
+        let gyro_heading = self.heading_calculator.heading();
+        self.log_row(
+            TelemetryStream::ForwardMovement,
+            &ForwardMovementTelemetryRow {
+                timestamp: start_time.as_millis(),
+                gyro_heading,
+                control_error_integral: controller.error_integral(),
+                updated_left_power: self.motors.get_duty_a(),
+                updated_right_power: self.motors.get_duty_b(),
+                left_current: self.motor_currents().0,
+                right_current: self.motor_currents().1,
+                motor_fault: self.motor_fault_code(),
+                ..Default::default()
+            },
         );
 
-        println!("{}", data_row);
-
+        self.start_control_loop();
         while (self.get_left_wheel_counter() + self.get_right_wheel_counter()) / 2
             < target_wheel_tick_count
         {
-            self.handle_loop();
-            if millis() - last_checkin_time > CONTROL_LOOP_PERIOD
-            {
-                let current_time = millis();
+            let task = self.handle_loop();
+            if let Some(fault) = self.motor_fault() {
+                let gyro_heading = self.heading_calculator.heading();
+                return Err(self.abort_movement(
+                    MotionError::MotorFault(fault),
+                    heading,
+                    gyro_heading,
+                ));
+            }
+            if self.stop_requested {
+                let gyro_heading = self.heading_calculator.heading();
+                return Err(self.abort_movement(MotionError::Stopped, heading, gyro_heading));
+            }
+            if task == Some(RobotTask::ControlLoop) {
+                let current_time = Instant::now();
                 let left_ticks = self.get_left_wheel_counter();
                 let right_ticks = self.get_right_wheel_counter();
                 let delta_left_ticks = left_ticks - last_left_ticks;
@@ -246,56 +687,56 @@ impl<
                 heading += heading_change;
                 let current_heading = self.heading_calculator.heading();
 
+                if let Err(error) = motion_monitor.update(
+                    current_time,
+                    (self.motors.get_duty_a(), self.motors.get_duty_b()),
+                    (left_ticks, right_ticks),
+                    heading,
+                    current_heading,
+                ) {
+                    return Err(self.abort_movement(error, heading, current_heading));
+                }
+
                 // get control signal from PID controller
                 let control_signal = controller.update(current_heading, current_time);
 
                 // set motor power. positive control signal means turn left, a negative control signal means turn right
-                let adjustment = control_signal.abs() as u8;
-                if control_signal.is_sign_positive() {
-                    self.motors.set_duty(
-                        left_target_power - adjustment / 2,
-                        right_target_power + adjustment,
-                    );
-                } else {
-                    self.motors.set_duty(
-                        left_target_power + adjustment,
-                        right_target_power - adjustment / 2,
-                    );
-                }
+                self.set_motor_power(target_power as f32, lr_ratio, control_signal);
 
-                println!(
-                    "{}",
-                    data_row.update(
-                        current_time,
-                        left_ticks,
-                        right_ticks,
+                self.log_row(
+                    TelemetryStream::ForwardMovement,
+                    &ForwardMovementTelemetryRow {
+                        timestamp: current_time.as_millis(),
+                        left_encoder: left_ticks,
+                        right_encoder: right_ticks,
                         distance,
-                        heading_change,
-                        heading,
-                        current_heading,
+                        delta_heading: heading_change,
+                        current_heading: heading,
+                        gyro_heading: current_heading,
                         control_signal,
-                        controller.integral,
-                        self.motors.get_duty_a(),
-                        self.motors.get_duty_b(),
-                    )
+                        control_error_integral: controller.error_integral(),
+                        updated_left_power: self.motors.get_duty_a(),
+                        updated_right_power: self.motors.get_duty_b(),
+                        left_current: self.motor_currents().0,
+                        right_current: self.motor_currents().1,
+                        motor_fault: self.motor_fault_code(),
+                    },
                 );
 
                 // update last checkin values
                 last_left_ticks = left_ticks;
                 last_right_ticks = right_ticks;
-                last_checkin_time = current_time;
+                self.finish_task(RobotTask::ControlLoop);
             }
         }
-        self.motors.stop();
-        let stop_millis = millis();
-        let left_ticks = self.get_left_wheel_counter();
-        let right_ticks = self.get_right_wheel_counter();
+        self.stop_control_loop();
         let left_power = self.motors.get_duty_a();
         let right_power = self.motors.get_duty_b();
-        // ensure a stop by reversing for a short time
-        self.motors.set_duty(255, 255);
-        self.motors.reverse();
-        delay_ms(100);
+        self.motors.brake(Instant::now());
+        let stop_time = Instant::now();
+        let left_ticks = self.get_left_wheel_counter();
+        let right_ticks = self.get_right_wheel_counter();
+        delay_ms(BRAKE_TIME);
         self.motors.stop();
 
         let distance = ((left_ticks + right_ticks) / 2) as f32 * WHEEL_CIRCUMFERENCE
@@ -304,22 +745,27 @@ impl<
             * (right_ticks as f32 - left_ticks as f32)
             / WHEEL_BASE;
         heading += heading_change;
-        println!(
-            "{}\n",
-            data_row.update(
-                stop_millis,
-                left_ticks,
-                right_ticks,
+        let gyro_heading = self.heading_calculator.heading();
+        self.log_row(
+            TelemetryStream::ForwardMovement,
+            &ForwardMovementTelemetryRow {
+                timestamp: stop_time.as_millis(),
+                left_encoder: left_ticks,
+                right_encoder: right_ticks,
                 distance,
-                heading_change,
-                heading,
-                self.heading_calculator.heading(),
-                0.0,
-                controller.integral,
-                left_power,
-                right_power,
-            )
+                delta_heading: heading_change,
+                current_heading: heading,
+                gyro_heading,
+                control_signal: 0.0,
+                control_error_integral: controller.error_integral(),
+                updated_left_power: left_power,
+                updated_right_power: right_power,
+                left_current: self.motor_currents().0,
+                right_current: self.motor_currents().1,
+                motor_fault: self.motor_fault_code(),
+            },
         );
+        self.end_telemetry_run();
         println!(
             "Stop overshoot: left_ticks = {}, right_ticks = {}",
             self.get_left_wheel_counter() - left_ticks,
@@ -330,110 +776,637 @@ impl<
         // println!("Plotting control signal");
         // print_with_fn!(|f| {
         //     data.plot(f, |row: &ForwardMovementTelemetryRow| {
-        //         row.control_signal() as i32
+        //         row.control_signal as i32
         //     })
         // });
 
-        self
+        Ok(self)
+    }
+
+    /// Stops an aborted movement and prints a diagnostic row with the robot's state when it
+    /// was aborted. Returns the passed error.
+    fn abort_movement(
+        &mut self,
+        error: MotionError,
+        encoder_heading: f32,
+        gyro_heading: f32,
+    ) -> MotionError {
+        self.stop_control_loop();
+        let diagnostic_row = MotionDiagnosticRow {
+            timestamp: Instant::now().as_millis(),
+            error,
+            left_duty: self.motors.get_duty_a(),
+            right_duty: self.motors.get_duty_b(),
+            left_encoder: self.get_left_wheel_counter(),
+            right_encoder: self.get_right_wheel_counter(),
+            encoder_heading,
+            gyro_heading,
+        };
+        self.brake_to_stop();
+        flush_telemetry();
+        println!("\nMovement aborted: {} (error code {})", error, error.code());
+        self.log_headers::<MotionDiagnosticRow>(TelemetryStream::MotionDiagnostic);
+        self.log_row(TelemetryStream::MotionDiagnostic, &diagnostic_row);
+        self.end_telemetry_run();
+        error
+    }
+
+    /// Checks a movement with `motion_monitor` at time `now`, and aborts it if the monitor finds
+    /// an error or a `stop` command was received. `encoder_heading` is the heading change since
+    /// the start of the movement calculated from the wheel encoders.
+    fn check_motion(
+        &mut self,
+        motion_monitor: &mut MotionMonitor,
+        now: Instant,
+        encoder_heading: f32,
+    ) -> Result<(), MotionError> {
+        let gyro_heading = self.heading_calculator.heading();
+        if self.stop_requested {
+            let error = MotionError::Stopped;
+            return Err(self.abort_movement(error, encoder_heading, gyro_heading));
+        }
+        motion_monitor
+            .update(
+                now,
+                (self.motors.get_duty_a(), self.motors.get_duty_b()),
+                (self.get_left_wheel_counter(), self.get_right_wheel_counter()),
+                encoder_heading,
+                gyro_heading,
+            )
+            .map_err(|error| self.abort_movement(error, encoder_heading, gyro_heading))
+    }
+
+    /// Calculates the heading change of a forward movement from the wheel encoders, with the
+    /// wheel counters reset at the start of the movement. Left turn is positive.
+    fn forward_encoder_heading(&self) -> f32 {
+        (WHEEL_CIRCUMFERENCE / WHEEL_ENCODER_TICK_COUNT as f32)
+            * (self.get_right_wheel_counter() as f32 - self.get_left_wheel_counter() as f32)
+            / WHEEL_BASE
     }
 
+    /// Turns the robot in place by `degrees` using the gyro heading, with a proportional
+    /// controller that slows the turn down close to the target heading. A positive angle turns
+    /// left.
+    pub fn turn(&mut self, degrees: i16) -> Result<&mut Self, MotionError> {
+        let mut controller = PIDController::new(TURN_GAIN, 0.0, 0.0);
+        controller.set_max_control_signal(TURN_POWER as f32);
+        self.turn_with_controller(degrees, controller)
+    }
+
+    /// A bang-bang turn controller for `turn_with_controller`, which turns at full power until
+    /// the robot is within the turn tolerance of the target heading.
+    pub fn turn_bang_bang_controller(&self) -> BangBangController {
+        BangBangController::new(TURN_POWER as f32, TURN_TOLERANCE)
+    }
+
+    /// A lead-lag turn controller for `turn_with_controller`.
+    pub fn turn_lead_lag_controller(&self) -> LeadLagController {
+        let mut controller =
+            LeadLagController::new(TURN_GAIN, TURN_LEAD_LAG_LEAD_TIME, TURN_LEAD_LAG_LAG_TIME);
+        controller.set_max_control_signal(TURN_POWER as f32);
+        controller
+    }
+
+    /// Turns the robot in place by `degrees` using the gyro heading. A positive angle turns left.
+    /// The passed controller sets the turn power from the heading every control loop period,
+    /// with the target heading as its setpoint. The wheels keep turning in the direction of the
+    /// turn, so the power is kept between the slow turn power, which still turns the robot, and
+    /// the turn power.
+    ///
+    /// The turn is refused if the battery is low, and aborted with an error if a wheel stalls
+    /// or slips, the robot spins out, the motor protection latches a fault, a `stop` command is
+    /// received, or the turn doesn't reach the target heading in time.
+    pub fn turn_with_controller<C: Controller + uDisplay>(
+        &mut self,
+        degrees: i16,
+        mut controller: C,
+    ) -> Result<&mut Self, MotionError> {
+        println!("Robot turn, degrees = {}", degrees);
+        if self.is_battery_low() {
+            return Err(MotionError::LowBattery);
+        }
+        if degrees == 0 {
+            return Ok(self);
+        }
+        let target_heading = degrees as f32 * core::f32::consts::PI / 180.0;
+        controller.set_setpoint(target_heading);
+        println!("controller = {}", controller);
+        self.reset_wheel_counters();
+        self.heading_calculator.reset();
+        let mut motion_monitor = MotionMonitor::new(MOTION_MONITOR_LIMITS);
+        let start_time = Instant::now();
+        motion_monitor.reset(start_time);
+        controller.reset(start_time);
+        self.set_motor_power(TURN_POWER as f32, 1.0, 0.0);
+        if degrees > 0 {
+            self.motors.reverse_a();
+            self.motors.forward_b();
+        } else {
+            self.motors.forward_a();
+            self.motors.reverse_b();
+        }
+
+        let deadline = Instant::after(TURN_TIMEOUT);
+        self.start_control_loop();
+        loop {
+            let task = self.handle_loop();
+            // the turn stops on the heading, so read the gyro on every pass rather than every
+            // heading update period
+            self.update_heading();
+            let heading = self.heading_calculator.heading();
+            // the wheels turn in opposite directions, so every tick turns the robot
+            let mut encoder_heading = (self.get_left_wheel_counter()
+                + self.get_right_wheel_counter()) as f32
+                * (WHEEL_CIRCUMFERENCE / WHEEL_ENCODER_TICK_COUNT as f32)
+                / WHEEL_BASE;
+            if degrees < 0 {
+                encoder_heading = -encoder_heading;
+            }
+            if let Some(fault) = self.motor_fault() {
+                return Err(self.abort_movement(
+                    MotionError::MotorFault(fault),
+                    encoder_heading,
+                    heading,
+                ));
+            }
+            if task == Some(RobotTask::ControlLoop) {
+                let current_time = Instant::now();
+                self.check_motion(&mut motion_monitor, current_time, encoder_heading)?;
+                // a positive control signal turns left, so flip it for a right turn
+                let mut power = controller.update(heading, current_time);
+                if degrees < 0 {
+                    power = -power;
+                }
+                let power = power.clamp(TURN_SLOW_POWER as f32, TURN_POWER as f32);
+                self.set_motor_power(power, 1.0, 0.0);
+                self.finish_task(RobotTask::ControlLoop);
+            }
+            let remaining_angle = if degrees > 0 {
+                target_heading - heading
+            } else {
+                heading - target_heading
+            };
+            if remaining_angle <= TURN_TOLERANCE {
+                break;
+            }
+            if deadline.has_passed() {
+                println!("Turn timed out, remaining angle = {} rad", remaining_angle);
+                let error = MotionError::TimedOut;
+                return Err(self.abort_movement(error, encoder_heading, heading));
+            }
+        }
+        self.stop_control_loop();
+        self.brake_to_stop();
+        println!("Done turning, heading = {}", self.heading_calculator.heading());
+        Ok(self)
+    }
+
+    /// Brakes the motors to a stop.
+    pub fn stop(&mut self) {
+        self.brake_to_stop();
+    }
+
+    /// Runs a relay feedback experiment on the heading control loop while driving forward,
+    /// prints the relay telemetry and the suggested PID gains. If `save_gains` is true, the
+    /// Tyreus–Luyben gains are saved to persistent settings and used by subsequent movements.
+    /// The experiment is aborted, without a result, if the motion monitor finds an error.
+    pub fn autotune_heading(&mut self, save_gains: bool) -> Option<RelayTuningResult> {
+        println!("Starting heading relay auto-tune");
+        let lr_ratio = self.motor_power_ratios.lr_ratio(AUTOTUNE_TARGET_POWER);
+        let mut relay = RelayAutoTuner::new(
+            0.0,
+            HEADING_AUTOTUNE_RELAY_AMPLITUDE,
+            HEADING_AUTOTUNE_HYSTERESIS,
+            AUTOTUNE_MEASURED_CYCLES,
+        );
+
+        self.telemetry_log.start_run();
+        self.log_headers::<RelayAutoTuneTelemetryRow>(TelemetryStream::RelayAutoTune);
+        self.reset_wheel_counters();
+        self.heading_calculator.reset();
+        let mut motion_monitor = MotionMonitor::new(MOTION_MONITOR_LIMITS);
+        motion_monitor.reset(Instant::now());
+        self.set_motor_power(AUTOTUNE_TARGET_POWER as f32, lr_ratio, 0.0);
+        self.motors.forward();
+        let deadline = Instant::after(AUTOTUNE_TIMEOUT);
+        self.start_control_loop();
+        while !relay.is_complete() && !deadline.has_passed() && self.motor_fault().is_none() {
+            if self.handle_loop() == Some(RobotTask::ControlLoop) {
+                let current_time = Instant::now();
+                let encoder_heading = self.forward_encoder_heading();
+                if self
+                    .check_motion(&mut motion_monitor, current_time, encoder_heading)
+                    .is_err()
+                {
+                    return None;
+                }
+                let current_heading = self.heading_calculator.heading();
+                let relay_output = relay.update(current_heading, current_time);
+
+                // positive relay output means turn left, same as the heading PID controller
+                self.set_motor_power(AUTOTUNE_TARGET_POWER as f32, lr_ratio, relay_output);
+                self.log_row(
+                    TelemetryStream::RelayAutoTune,
+                    &RelayAutoTuneTelemetryRow {
+                        timestamp: current_time.as_millis(),
+                        setpoint: 0.0,
+                        measurement: current_heading,
+                        relay_output,
+                        updated_left_power: self.motors.get_duty_a(),
+                        updated_right_power: self.motors.get_duty_b(),
+                    },
+                );
+                self.finish_task(RobotTask::ControlLoop);
+            }
+        }
+        self.stop_control_loop();
+        self.brake_to_stop();
+        self.end_telemetry_run();
+
+        let result = relay.result();
+        if let Some(gains) = Self::report_autotune_result(&result) {
+            self.heading_pid_gains = gains;
+            if save_gains {
+                self.settings.save_heading_pid_gains(&gains);
+                println!("Saved heading PID gains");
+            }
+        }
+        result
+    }
+
+    /// Runs a relay feedback experiment on the wheel speed control loop, prints the relay
+    /// telemetry and the suggested PID gains. The wheel speed is the average speed of both
+    /// wheels in millimeters per second, and the relay output is added to both motors' power.
+    /// Nothing controls the wheel speed yet, so the gains are only printed. The experiment is
+    /// aborted, without a result, if the motion monitor finds an error.
+    pub fn autotune_wheel_speed(&mut self) -> Option<RelayTuningResult> {
+        println!("Starting wheel speed relay auto-tune");
+        let lr_ratio = self.motor_power_ratios.lr_ratio(AUTOTUNE_TARGET_POWER);
+        self.set_motor_power(AUTOTUNE_TARGET_POWER as f32, lr_ratio, 0.0);
+        self.reset_wheel_counters();
+        self.heading_calculator.reset();
+        let mut motion_monitor = MotionMonitor::new(MOTION_MONITOR_LIMITS);
+        motion_monitor.reset(Instant::now());
+        self.motors.forward();
+        self.start_control_loop();
+
+        // let the wheels spin up, then use the steady state speed as the relay setpoint
+        let spin_up_time = WHEEL_SPEED_AUTOTUNE_SPIN_UP_TIME / 2;
+        self.wait_moving_forward(spin_up_time, &mut motion_monitor).ok()?;
+        let spin_up_ticks = self.get_left_wheel_counter() + self.get_right_wheel_counter();
+        let spin_up_midpoint_time = Instant::now();
+        self.wait_moving_forward(spin_up_time, &mut motion_monitor).ok()?;
+        let setpoint = Self::wheel_speed(
+            self.get_left_wheel_counter() + self.get_right_wheel_counter() - spin_up_ticks,
+            spin_up_midpoint_time.elapsed(),
+        );
+        println!("Wheel speed setpoint = {} mm/s", setpoint);
+
+        let mut relay = RelayAutoTuner::new(
+            setpoint,
+            WHEEL_SPEED_AUTOTUNE_RELAY_AMPLITUDE,
+            WHEEL_SPEED_AUTOTUNE_HYSTERESIS,
+            AUTOTUNE_MEASURED_CYCLES,
+        );
+        self.telemetry_log.start_run();
+        self.log_headers::<RelayAutoTuneTelemetryRow>(TelemetryStream::RelayAutoTune);
+        let deadline = Instant::after(AUTOTUNE_TIMEOUT);
+        let mut last_checkin_time = Instant::now();
+        let mut last_ticks = self.get_left_wheel_counter() + self.get_right_wheel_counter();
+        let mut speed = setpoint;
+        while !relay.is_complete() && !deadline.has_passed() && self.motor_fault().is_none() {
+            if self.handle_loop() == Some(RobotTask::ControlLoop) {
+                let current_time = Instant::now();
+                let encoder_heading = self.forward_encoder_heading();
+                if self
+                    .check_motion(&mut motion_monitor, current_time, encoder_heading)
+                    .is_err()
+                {
+                    return None;
+                }
+                let ticks = self.get_left_wheel_counter() + self.get_right_wheel_counter();
+                // the encoders are coarse, so smooth the speed measurement
+                speed = (speed
+                    + Self::wheel_speed(ticks - last_ticks, current_time - last_checkin_time))
+                    / 2.0;
+                let relay_output = relay.update(speed, current_time);
+                self.set_motor_power(AUTOTUNE_TARGET_POWER as f32 + relay_output, lr_ratio, 0.0);
+                self.log_row(
+                    TelemetryStream::RelayAutoTune,
+                    &RelayAutoTuneTelemetryRow {
+                        timestamp: current_time.as_millis(),
+                        setpoint,
+                        measurement: speed,
+                        relay_output,
+                        updated_left_power: self.motors.get_duty_a(),
+                        updated_right_power: self.motors.get_duty_b(),
+                    },
+                );
+                last_ticks = ticks;
+                last_checkin_time = current_time;
+                self.finish_task(RobotTask::ControlLoop);
+            }
+        }
+        self.stop_control_loop();
+        self.brake_to_stop();
+        self.end_telemetry_run();
+
+        let result = relay.result();
+        Self::report_autotune_result(&result);
+        result
+    }
+
+    /// Prints a relay experiment's result and returns the suggested gains.
+    fn report_autotune_result(result: &Option<RelayTuningResult>) -> Option<PidGains> {
+        match result {
+            Some(result) => {
+                let zn_gains = result.ziegler_nichols();
+                let tl_gains = result.tyreus_luyben();
+                println!("Auto-tune result: {}", result);
+                println!("    Ziegler-Nichols gains: {}", zn_gains);
+                println!("    Tyreus-Luyben gains: {}", tl_gains);
+                Some(tl_gains)
+            }
+            None => {
+                println!("Auto-tune failed: the loop did not oscillate");
+                None
+            }
+        }
+    }
+
+    /// Converts a combined tick count of both wheels over `duration` to the average wheel speed
+    /// in millimeters per second.
+    fn wheel_speed(combined_ticks: u32, duration: Duration) -> f32 {
+        Self::ticks_to_speed(combined_ticks, duration) / 2.0
+    }
+
+    /// Converts a tick count of one wheel over `duration` to the wheel speed in millimeters per
+    /// second.
+    fn ticks_to_speed(ticks: u32, duration: Duration) -> f32 {
+        if duration.is_zero() {
+            return 0.0;
+        }
+        ticks as f32 * WHEEL_CIRCUMFERENCE / WHEEL_ENCODER_TICK_COUNT as f32
+            / duration.as_secs_f32()
+    }
+
+    /// Runs the loop handling for `duration`.
+    fn wait(&mut self, duration: Duration) {
+        let start_time = Instant::now();
+        while !start_time.has_elapsed(duration) {
+            self.handle_loop();
+        }
+    }
+
+    /// Runs the loop handling for `duration` while the robot drives forward, checking the
+    /// movement with `motion_monitor` every control loop period. The control loop must be
+    /// started.
+    fn wait_moving_forward(
+        &mut self,
+        duration: Duration,
+        motion_monitor: &mut MotionMonitor,
+    ) -> Result<(), MotionError> {
+        let start_time = Instant::now();
+        while !start_time.has_elapsed(duration) {
+            if self.handle_loop() == Some(RobotTask::ControlLoop) {
+                let encoder_heading = self.forward_encoder_heading();
+                if let Some(fault) = self.motor_fault() {
+                    let gyro_heading = self.heading_calculator.heading();
+                    let error = MotionError::MotorFault(fault);
+                    return Err(self.abort_movement(error, encoder_heading, gyro_heading));
+                }
+                self.check_motion(motion_monitor, Instant::now(), encoder_heading)?;
+                self.finish_task(RobotTask::ControlLoop);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the duty that drives `wheel` at `mm_per_s` millimeters per second according to the
+    /// saved motor characterization, or `None` if the motors haven't been characterized.
+    pub fn duty_for_speed(&self, wheel: Wheel, mm_per_s: f32) -> Option<u8> {
+        self.motor_characterization
+            .as_ref()
+            .map(|characterization| characterization.duty_for_speed(wheel, mm_per_s))
+    }
+
+    /// Measures each motor's start and stall duty thresholds and its steady state wheel speed at
+    /// each of the `CHARACTERIZATION_DUTIES` duty levels, then saves the characterization to
+    /// persistent settings. The robot drives forward the whole time, so it needs several meters
+    /// of room. Returns `None`, without saving anything, if a motor fault is latched before or
+    /// during the characterization. The duties aren't compensated for the battery voltage, so the
+    /// logged duties are the ones applied to the motors.
+    #[cfg(feature = "characterize_motors")]
+    pub fn characterize_motors(&mut self) -> Option<MotorCharacterization> {
+        use crate::model::motor_characterization::CHARACTERIZATION_DUTIES;
+        use crate::telemetry::MotorCharacterizationRow;
+
+        const DUTY_RAMP_STEP: u8 = 5;
+        const DUTY_RAMP_STEP_TIME: Duration = Duration::from_millis(250);
+        const STALL_DETECTION_TIME: Duration = Duration::from_millis(500);
+        const SPEED_SETTLE_TIME: Duration = Duration::from_millis(300);
+        const SPEED_MEASUREMENT_TIME: Duration = Duration::from_millis(700);
+
+        println!("Characterizing motors");
+        let mut characterization = MotorCharacterization::default();
+        characterization.left.start_duty = 255;
+        characterization.right.start_duty = 255;
+
+        // ramp the duty up from zero until both wheels start turning
+        if self.motor_fault_aborts_run() {
+            return None;
+        }
+        self.reset_wheel_counters();
+        let mut duty: u8 = 0;
+        self.set_motor_duty(duty, duty);
+        self.motors.forward();
+        let mut left_started = false;
+        let mut right_started = false;
+        while !(left_started && right_started) && duty < 255 {
+            duty = duty.saturating_add(DUTY_RAMP_STEP);
+            self.set_motor_duty(duty, duty);
+            self.wait(DUTY_RAMP_STEP_TIME);
+            if self.motor_fault_aborts_run() {
+                return None;
+            }
+            if !left_started && self.get_left_wheel_counter() > 0 {
+                left_started = true;
+                characterization.left.start_duty = duty;
+            }
+            if !right_started && self.get_right_wheel_counter() > 0 {
+                right_started = true;
+                characterization.right.start_duty = duty;
+            }
+        }
+        println!(
+            "Start duty: left = {}, right = {}",
+            characterization.left.start_duty, characterization.right.start_duty
+        );
+
+        // then ramp the duty down until both wheels stall
+        let mut left_stalled = false;
+        let mut right_stalled = false;
+        while !(left_stalled && right_stalled) && duty > 0 {
+            let left_ticks = self.get_left_wheel_counter();
+            let right_ticks = self.get_right_wheel_counter();
+            self.wait(STALL_DETECTION_TIME);
+            if self.motor_fault_aborts_run() {
+                return None;
+            }
+            if !left_stalled && self.get_left_wheel_counter() == left_ticks {
+                left_stalled = true;
+                characterization.left.stall_duty = duty;
+            }
+            if !right_stalled && self.get_right_wheel_counter() == right_ticks {
+                right_stalled = true;
+                characterization.right.stall_duty = duty;
+            }
+            duty = duty.saturating_sub(DUTY_RAMP_STEP);
+            self.set_motor_duty(duty, duty);
+        }
+        println!(
+            "Stall duty: left = {}, right = {}",
+            characterization.left.stall_duty, characterization.right.stall_duty
+        );
+
+        // finally, measure the steady state speed at each duty level
+        self.telemetry_log.start_run();
+        self.log_headers::<MotorCharacterizationRow>(TelemetryStream::MotorCharacterization);
+        for (i, duty) in CHARACTERIZATION_DUTIES.iter().enumerate() {
+            self.set_motor_duty(*duty, *duty);
+            self.wait(SPEED_SETTLE_TIME);
+            let left_ticks = self.get_left_wheel_counter();
+            let right_ticks = self.get_right_wheel_counter();
+            let start_time = Instant::now();
+            self.wait(SPEED_MEASUREMENT_TIME);
+            let duration = start_time.elapsed();
+            if self.motor_fault_aborts_run() {
+                self.end_telemetry_run();
+                return None;
+            }
+            characterization.left.speeds[i] =
+                Self::ticks_to_speed(self.get_left_wheel_counter() - left_ticks, duration);
+            characterization.right.speeds[i] =
+                Self::ticks_to_speed(self.get_right_wheel_counter() - right_ticks, duration);
+            self.log_row(
+                TelemetryStream::MotorCharacterization,
+                &MotorCharacterizationRow {
+                    duty: *duty,
+                    left_speed: characterization.left.speeds[i],
+                    right_speed: characterization.right.speeds[i],
+                },
+            );
+        }
+        self.brake_to_stop();
+        self.end_telemetry_run();
+
+        self.settings.save_motor_characterization(&characterization);
+        self.motor_characterization = Some(characterization);
+        println!("Saved motor speed characterization");
+        Some(characterization)
+    }
+
+    /// Stops the motors by actively braking them for a short time, then lets them coast.
+    fn brake_to_stop(&mut self) {
+        self.motors.brake(Instant::now());
+        delay_ms(BRAKE_TIME);
+        self.motors.stop();
+    }
+
+    /// Returns true, after stopping the motors, if a motor fault is latched. The calibration
+    /// routines check this before and after each test run, since a latched fault keeps the
+    /// motors stopped and would spoil the measurements.
+    #[cfg(any(feature = "calibrate_motors", feature = "characterize_motors"))]
+    fn motor_fault_aborts_run(&mut self) -> bool {
+        match self.motor_fault() {
+            Some(fault) => {
+                error!("Motor fault {}, aborting the calibration", fault);
+                self.brake_to_stop();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Measures the left/right wheel tick ratio at each power level of the L/R power ratio table,
+    /// fits a new table from the average ratios and saves it to persistent settings. The robot
+    /// drives forward for every test run, so it needs plenty of room. Returns `None`, without
+    /// saving anything, if a motor fault is latched before or during a test run. The power levels
+    /// aren't compensated for the battery voltage, so the logged power levels are the duties
+    /// applied to the motors.
     #[cfg(feature = "calibrate_motors")]
-    pub fn calibrate_motors(&mut self) {
-        println!("{}", F!("Calibrating motors"));
+    pub fn calibrate_motors(&mut self) -> Option<MotorPowerRatios> {
+        use crate::telemetry::MotorCalibrationRow;
+
+        println!("Calibrating motors");
 
-        const COUNT_TEST_POWER_LEVELS: usize = 12;
         const COUNT_TEST_RUNS: usize = 10;
-        const COUNT_TEST_DATA_ROWS: usize = COUNT_TEST_POWER_LEVELS * COUNT_TEST_RUNS;
-        let test_power_levels: [u8; COUNT_TEST_POWER_LEVELS] =
-            [70, 80, 90, 100, 110, 120, 140, 160, 180, 200, 225, 255];
-
-        let mut data = uDataTable::<MotorCalibrationRow, COUNT_TEST_DATA_ROWS, 5>::new([
-            "test_id",
-            "power",
-            "left_ticks",
-            "right_ticks",
-            "lr_ratio",
-        ]);
+        const TEST_RUN_TICKS: u32 = 200;
+        const TEST_RUN_TIMEOUT: Duration = Duration::from_secs(10);
+        let test_power_levels = self.motor_power_ratios.power_levels();
+        let mut fitted_ratios = *self.motor_power_ratios.ratios();
 
+        self.telemetry_log.start_run();
+        self.log_headers::<MotorCalibrationRow>(TelemetryStream::MotorCalibration);
         let mut test_id: u16 = 0;
-        for test_power in test_power_levels.iter() {
-            println!("{}{}", F!("testing power: "), test_power);
-            for i in 0..COUNT_TEST_RUNS {
-                println!("    run #{}", i);
+        for (level_index, test_power) in test_power_levels.iter().enumerate() {
+            let mut ratio_sum: f32 = 0.0;
+            let mut ratio_count: u16 = 0;
+            for _ in 0..COUNT_TEST_RUNS {
                 test_id += 1;
-                let left_power = *test_power;
-                let right_power = *test_power;
-                self.motors.set_duty(left_power, right_power);
+                if self.motor_fault_aborts_run() {
+                    self.end_telemetry_run();
+                    return None;
+                }
+                self.set_motor_duty(*test_power, *test_power);
                 self.reset_wheel_counters();
                 self.motors.forward();
-                while self.get_left_wheel_counter() < 200 {
+                let deadline = Instant::after(TEST_RUN_TIMEOUT);
+                while self.get_left_wheel_counter() < TEST_RUN_TICKS && !deadline.has_passed() {
                     self.handle_loop();
                 }
-                self.motors.stop();
+                self.motors.brake(Instant::now());
                 let left_ticks = self.get_left_wheel_counter();
                 let right_ticks = self.get_right_wheel_counter();
-                self.motors.set_duty(255, 255);
-                self.motors.reverse();
                 delay_ms(50);
                 self.motors.stop();
                 delay_ms(1000);
-                let lr_ratio = left_ticks as f32 / right_ticks as f32;
-                if let Err(row) = data.append(MotorCalibrationRow {
-                    test_id,
-                    power: *test_power,
-                    left_ticks,
-                    right_ticks,
-                    lr_ratio,
-                }) {
-                    println!("{}{}", F!("Error appending row to data table: "), row);
+                if self.motor_fault_aborts_run() {
+                    self.end_telemetry_run();
+                    return None;
                 }
-
-                println!(
-                    "        left_ticks: {}, right_ticks: {}, lr_ratio: {}",
-                    left_ticks, right_ticks, lr_ratio
+                if left_ticks == 0 || right_ticks == 0 {
+                    println!("Test run {} did not move, skipping it", test_id);
+                    continue;
+                }
+                let lr_ratio = left_ticks as f32 / right_ticks as f32;
+                ratio_sum += lr_ratio;
+                ratio_count += 1;
+                self.log_row(
+                    TelemetryStream::MotorCalibration,
+                    &MotorCalibrationRow {
+                        test_id,
+                        power: *test_power,
+                        left_ticks,
+                        right_ticks,
+                        lr_ratio,
+                    },
                 );
             }
+            if ratio_count > 0 {
+                fitted_ratios[level_index].1 = ratio_sum / ratio_count as f32;
+            }
         }
 
-        println!(
-            "{}{}",
-            F!("Done with motor calibration. Data collected:\n"),
-            data
-        );
-    }
-}
-
-#[derive(Default, Copy, Clone)]
-pub struct MotorCalibrationRow {
-    test_id: u16,
-    power: u8,
-    left_ticks: u32,
-    right_ticks: u32,
-    lr_ratio: f32,
-}
-
-impl uDebug for MotorCalibrationRow {
-    fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
-    where
-        W: uWrite + ?Sized,
-    {
-        uwrite!(f, "MotorCalibrationRow {{ test_id: {}, power: {}, left_ticks: {}, right_ticks: {}, lr_ratio: {} }}", self.test_id, self.power, self.left_ticks, self.right_ticks, self.lr_ratio)
-    }
-}
-
-impl uDisplay for MotorCalibrationRow {
-    fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
-    where
-        W: uWrite + ?Sized,
-    {
-        uwrite!(
-            f,
-            "{}, {}, {}, {}, {}",
-            self.test_id,
-            self.power,
-            self.left_ticks,
-            self.right_ticks,
-            self.lr_ratio
-        )
+        self.end_telemetry_run();
+        let ratios = MotorPowerRatios::new(fitted_ratios);
+        println!("\nDone with motor calibration. Fitted L/R power ratio table:");
+        for (power, lr_ratio) in ratios.ratios().iter() {
+            println!("    ({}, {}),", power, lr_ratio);
+        }
+        self.settings.save_motor_power_ratios(&ratios);
+        self.motor_power_ratios = ratios;
+        println!("Saved L/R power ratio table");
+        Some(ratios)
     }
 }
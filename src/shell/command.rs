@@ -0,0 +1,61 @@
+use super::parser::ShellError;
+use crate::{system::flash_str::FlashStr, F};
+
+impl ShellError {
+    pub fn message(&self) -> FlashStr {
+        match self {
+            ShellError::UnknownCommand => F!("unknown command, type help for a list of commands"),
+            ShellError::MissingArgument => F!("missing argument"),
+            ShellError::InvalidArgument => F!("invalid argument"),
+            ShellError::ExtraArgument => F!("too many arguments"),
+            ShellError::LineTooLong => F!("command line too long"),
+            ShellError::CommandFailed => F!("command failed"),
+            ShellError::Busy => F!("the robot is moving, only stop is accepted"),
+        }
+    }
+}
+
+/// A command's entry in the `help` listing. The help text is kept in program memory.
+pub struct CommandSpec {
+    pub usage: FlashStr,
+    pub description: FlashStr,
+}
+
+pub static COMMANDS: [CommandSpec; 9] = [
+    CommandSpec {
+        usage: F!("straight <mm> [pid|bangbang|leadlag]"),
+        description: F!("drive straight ahead, holding the heading with the given controller"),
+    },
+    CommandSpec {
+        usage: F!("turn <degrees> [pid|bangbang|leadlag]"),
+        description: F!("turn in place with the given controller, positive angles turn left"),
+    },
+    CommandSpec {
+        usage: F!("stop"),
+        description: F!("stop the robot, also during a movement"),
+    },
+    CommandSpec {
+        usage: F!("pid <kp|ki|kd> <gain>"),
+        description: F!("set a heading PID gain"),
+    },
+    CommandSpec {
+        usage: F!("get <heading|encoders|battery|currents|pid|tasks>"),
+        description: F!("print a value"),
+    },
+    CommandSpec {
+        usage: F!("reset encoders"),
+        description: F!("reset the wheel counters"),
+    },
+    CommandSpec {
+        usage: F!("config save"),
+        description: F!("save the heading PID gains"),
+    },
+    CommandSpec {
+        usage: F!("log <off|error|warn|info|debug|trace>"),
+        description: F!("set the log level"),
+    },
+    CommandSpec {
+        usage: F!("help"),
+        description: F!("list the commands"),
+    },
+];
@@ -0,0 +1,159 @@
+pub mod command;
+pub mod parser;
+
+use embedded_hal::digital::v2::InputPin;
+
+use self::{
+    command::COMMANDS,
+    parser::{Command, ControllerKind, LineReader, PidTerm, Quantity, ShellError},
+};
+use crate::{
+    model::motor_protection::CurrentSensor, motor_driver::DualMotorDriver, println, robot::Robot,
+    system::{
+        log::{log_level, set_log_level},
+        serial_print::read_console_byte,
+    },
+    telemetry::TelemetryLog,
+};
+
+/// A line oriented command shell on the console. Each command line is answered with `OK` or with
+/// `ERR <code>: <message>`, so that a script on the host can drive the robot.
+pub struct CommandShell {
+    line_reader: LineReader,
+}
+
+#[allow(dead_code)]
+impl CommandShell {
+    pub fn new() -> Self {
+        Self {
+            line_reader: LineReader::new(),
+        }
+    }
+
+    /// Reads the bytes received by the console and runs each completed command line. Call this
+    /// from the main loop. Commands that move the robot return when the movement is done, and
+    /// the robot reads the console itself while it moves, so that `stop` can abort the movement.
+    pub fn poll<MOTORS, BUTT1, CS, LOG>(&mut self, robot: &mut Robot<MOTORS, BUTT1, CS, LOG>)
+    where
+        MOTORS: DualMotorDriver,
+        BUTT1: InputPin,
+        CS: CurrentSensor,
+        LOG: TelemetryLog,
+    {
+        while let Some(byte) = read_console_byte() {
+            if let Some(result) = self.line_reader.push(byte) {
+                Self::report(result.and_then(|command| Self::execute(command, robot)));
+            }
+        }
+    }
+
+    fn execute<MOTORS, BUTT1, CS, LOG>(
+        command: Command,
+        robot: &mut Robot<MOTORS, BUTT1, CS, LOG>,
+    ) -> Result<(), ShellError>
+    where
+        MOTORS: DualMotorDriver,
+        BUTT1: InputPin,
+        CS: CurrentSensor,
+        LOG: TelemetryLog,
+    {
+        match command {
+            Command::Straight(distance_mm, kind) => {
+                let result = match kind {
+                    ControllerKind::Pid => robot.straight(distance_mm),
+                    ControllerKind::BangBang => robot.straight_with_controller(
+                        distance_mm,
+                        robot.heading_bang_bang_controller(),
+                    ),
+                    ControllerKind::LeadLag => robot.straight_with_controller(
+                        distance_mm,
+                        robot.heading_lead_lag_controller(),
+                    ),
+                };
+                if let Err(error) = result {
+                    println!("Movement failed: {}", error);
+                    return Err(ShellError::CommandFailed);
+                }
+            }
+            Command::Turn(degrees, kind) => {
+                let result = match kind {
+                    ControllerKind::Pid => robot.turn(degrees),
+                    ControllerKind::BangBang => robot.turn_with_controller(
+                        degrees,
+                        robot.turn_bang_bang_controller(),
+                    ),
+                    ControllerKind::LeadLag => robot.turn_with_controller(
+                        degrees,
+                        robot.turn_lead_lag_controller(),
+                    ),
+                };
+                if let Err(error) = result {
+                    println!("Turn failed: {}", error);
+                    return Err(ShellError::CommandFailed);
+                }
+            }
+            Command::Stop => robot.stop(),
+            Command::SetPidGain(term, gain) => {
+                let mut gains = robot.heading_pid_gains();
+                match term {
+                    PidTerm::Kp => gains.kp = gain,
+                    PidTerm::Ki => gains.ki = gain,
+                    PidTerm::Kd => gains.kd = gain,
+                }
+                robot.set_heading_pid_gains(gains);
+                println!("heading PID gains: {}", gains);
+            }
+            Command::Get(quantity) => match quantity {
+                Quantity::Heading => {
+                    println!("heading = {} deg", robot.heading().to_degrees());
+                }
+                Quantity::Encoders => println!(
+                    "encoders: left = {}, right = {}",
+                    robot.get_left_wheel_counter(),
+                    robot.get_right_wheel_counter()
+                ),
+                Quantity::Battery => println!(
+                    "battery = {} V, state of charge = {}%",
+                    robot.battery_voltage(),
+                    robot.battery_state_of_charge()
+                ),
+                Quantity::Currents => {
+                    let (left, right) = robot.motor_currents();
+                    println!("currents: left = {} mA, right = {} mA", left, right);
+                }
+                Quantity::Pid => println!("heading PID gains: {}", robot.heading_pid_gains()),
+                Quantity::Tasks => {
+                    for (task, stats) in robot.task_stats() {
+                        println!("    {}: {}", task, stats);
+                    }
+                }
+            },
+            Command::ResetEncoders => robot.reset_wheel_counters(),
+            Command::ConfigSave => robot.save_heading_pid_gains(),
+            Command::SetLogLevel(level) => {
+                set_log_level(level);
+                println!("log level = {}", log_level());
+            }
+            Command::Help => {
+                for spec in COMMANDS.iter() {
+                    println!("    {}: {}", spec.usage, spec.description);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers a command line with `OK` or `ERR <code>: <message>`.
+    pub fn report(result: Result<(), ShellError>) {
+        match result {
+            Ok(()) => println!("OK"),
+            Err(error) => println!("ERR {}: {}", error.code(), error.message()),
+        }
+    }
+}
+
+impl Default for CommandShell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
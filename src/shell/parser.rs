@@ -0,0 +1,470 @@
+use core::str::FromStr;
+
+use crate::system::log_level::LogLevel;
+
+/// The longest command line, in bytes.
+pub const MAX_LINE_LENGTH: usize = 48;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+/// The largest turn angle, in degrees.
+const MAX_TURN_ANGLE: i16 = 360;
+
+/// A command parsed from a shell command line.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Command {
+    /// Drives straight ahead for a distance in millimeters, holding the heading with a
+    /// controller of the given kind.
+    Straight(u32, ControllerKind),
+    /// Turns in place by an angle in degrees, with a controller of the given kind. Positive
+    /// angles turn left.
+    Turn(i16, ControllerKind),
+    /// Stops the robot. During a movement, it aborts the movement.
+    Stop,
+    SetPidGain(PidTerm, f32),
+    Get(Quantity),
+    ResetEncoders,
+    ConfigSave,
+    SetLogLevel(LogLevel),
+    Help,
+}
+
+/// The kind of controller a movement uses, so that control strategies can be compared on the
+/// same course. Movements use the PID controller unless another one is given.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ControllerKind {
+    Pid,
+    BangBang,
+    LeadLag,
+}
+
+/// A gain of the heading PID controller.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PidTerm {
+    Kp,
+    Ki,
+    Kd,
+}
+
+/// A value that the `get` command reports.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Quantity {
+    Heading,
+    Encoders,
+    Battery,
+    Currents,
+    Pid,
+    /// The run statistics of the robot's scheduled tasks.
+    Tasks,
+}
+
+/// Why the shell rejected or failed a command. The shell reports it as `ERR <code>: <message>`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ShellError {
+    UnknownCommand,
+    MissingArgument,
+    InvalidArgument,
+    ExtraArgument,
+    LineTooLong,
+    /// The command was valid, but the robot couldn't carry it out, such as a movement that was
+    /// aborted.
+    CommandFailed,
+    /// A command other than `stop` was received while the robot was moving.
+    Busy,
+}
+
+impl ShellError {
+    /// The status code reported for the error. Success is reported as `OK`, which is code 0.
+    pub fn code(&self) -> u8 {
+        match self {
+            ShellError::UnknownCommand => 1,
+            ShellError::MissingArgument => 2,
+            ShellError::InvalidArgument => 3,
+            ShellError::ExtraArgument => 4,
+            ShellError::LineTooLong => 5,
+            ShellError::CommandFailed => 6,
+            ShellError::Busy => 7,
+        }
+    }
+}
+
+/// Assembles command lines from the bytes received by the console. Lines end with a carriage
+/// return or a line feed, and backspace or delete removes the last byte.
+pub struct LineReader {
+    line: [u8; MAX_LINE_LENGTH],
+    length: usize,
+    /// The current line didn't fit, so it is discarded when it ends.
+    overflowed: bool,
+}
+
+impl LineReader {
+    pub fn new() -> Self {
+        Self {
+            line: [0; MAX_LINE_LENGTH],
+            length: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Adds a received byte. When the byte ends a line that isn't blank, returns the command
+    /// parsed from the line, or why the line was rejected.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Command, ShellError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let result = if self.overflowed {
+                    Err(ShellError::LineTooLong)
+                } else {
+                    match core::str::from_utf8(&self.line[..self.length]) {
+                        Ok(line) => parse_command(line).transpose()?,
+                        Err(_) => Err(ShellError::UnknownCommand),
+                    }
+                };
+                self.length = 0;
+                self.overflowed = false;
+                Some(result)
+            }
+            BACKSPACE | DELETE => {
+                self.length = self.length.saturating_sub(1);
+                None
+            }
+            _ if self.length < MAX_LINE_LENGTH => {
+                self.line[self.length] = byte;
+                self.length += 1;
+                None
+            }
+            _ => {
+                self.overflowed = true;
+                None
+            }
+        }
+    }
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses a command line. The command and its arguments are separated by whitespace. Returns
+/// `None` for a blank line.
+pub fn parse_command(line: &str) -> Result<Option<Command>, ShellError> {
+    let mut tokens = line.split_ascii_whitespace();
+    let name = match tokens.next() {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let command = match name {
+        "straight" => {
+            let distance_mm = parse_number(tokens.next())?;
+            Command::Straight(distance_mm, parse_controller_kind(tokens.next())?)
+        }
+        "turn" => {
+            let degrees: i16 = parse_number(tokens.next())?;
+            if !(-MAX_TURN_ANGLE..=MAX_TURN_ANGLE).contains(&degrees) {
+                return Err(ShellError::InvalidArgument);
+            }
+            Command::Turn(degrees, parse_controller_kind(tokens.next())?)
+        }
+        "stop" => Command::Stop,
+        "pid" => {
+            let term = parse_keyword(
+                tokens.next(),
+                &[
+                    ("kp", PidTerm::Kp),
+                    ("ki", PidTerm::Ki),
+                    ("kd", PidTerm::Kd),
+                ],
+            )?;
+            let gain: f32 = parse_number(tokens.next())?;
+            if !gain.is_finite() {
+                return Err(ShellError::InvalidArgument);
+            }
+            Command::SetPidGain(term, gain)
+        }
+        "get" => Command::Get(parse_keyword(
+            tokens.next(),
+            &[
+                ("heading", Quantity::Heading),
+                ("encoders", Quantity::Encoders),
+                ("battery", Quantity::Battery),
+                ("currents", Quantity::Currents),
+                ("pid", Quantity::Pid),
+                ("tasks", Quantity::Tasks),
+            ],
+        )?),
+        "reset" => parse_keyword(tokens.next(), &[("encoders", Command::ResetEncoders)])?,
+        "config" => parse_keyword(tokens.next(), &[("save", Command::ConfigSave)])?,
+        "log" => Command::SetLogLevel(parse_keyword(
+            tokens.next(),
+            &[
+                ("off", LogLevel::Off),
+                ("error", LogLevel::Error),
+                ("warn", LogLevel::Warn),
+                ("info", LogLevel::Info),
+                ("debug", LogLevel::Debug),
+                ("trace", LogLevel::Trace),
+            ],
+        )?),
+        "help" => Command::Help,
+        _ => return Err(ShellError::UnknownCommand),
+    };
+    if tokens.next().is_some() {
+        return Err(ShellError::ExtraArgument);
+    }
+    Ok(Some(command))
+}
+
+fn parse_number<T: FromStr>(token: Option<&str>) -> Result<T, ShellError> {
+    token
+        .ok_or(ShellError::MissingArgument)?
+        .parse()
+        .map_err(|_| ShellError::InvalidArgument)
+}
+
+/// Parses an optional controller kind, which defaults to the PID controller.
+fn parse_controller_kind(token: Option<&str>) -> Result<ControllerKind, ShellError> {
+    match token {
+        Some(_) => parse_keyword(
+            token,
+            &[
+                ("pid", ControllerKind::Pid),
+                ("bangbang", ControllerKind::BangBang),
+                ("leadlag", ControllerKind::LeadLag),
+            ],
+        ),
+        None => Ok(ControllerKind::Pid),
+    }
+}
+
+fn parse_keyword<T: Copy>(token: Option<&str>, keywords: &[(&str, T)]) -> Result<T, ShellError> {
+    let token = token.ok_or(ShellError::MissingArgument)?;
+    keywords
+        .iter()
+        .find(|(keyword, _)| *keyword == token)
+        .map(|(_, value)| *value)
+        .ok_or(ShellError::InvalidArgument)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parses_to(line: &str, command: Command) -> bool {
+        parse_command(line) == Ok(Some(command))
+    }
+
+    fn fails_with(line: &str, error: ShellError) -> bool {
+        parse_command(line) == Err(error)
+    }
+
+    /// Pushes `bytes` and returns the result of the last completed line.
+    fn read_line(reader: &mut LineReader, bytes: &[u8]) -> Option<Result<Command, ShellError>> {
+        bytes
+            .iter()
+            .fold(None, |result, byte| reader.push(*byte).or(result))
+    }
+
+    #[test]
+    fn parses_every_command() {
+        assert!(parses_to(
+            "straight 500",
+            Command::Straight(500, ControllerKind::Pid)
+        ));
+        assert!(parses_to(
+            "turn -90",
+            Command::Turn(-90, ControllerKind::Pid)
+        ));
+        assert!(parses_to(
+            "turn 360",
+            Command::Turn(360, ControllerKind::Pid)
+        ));
+        assert!(parses_to("stop", Command::Stop));
+        assert!(parses_to(
+            "pid kp 18",
+            Command::SetPidGain(PidTerm::Kp, 18.0)
+        ));
+        assert!(parses_to(
+            "pid ki 0.5",
+            Command::SetPidGain(PidTerm::Ki, 0.5)
+        ));
+        assert!(parses_to(
+            "pid kd -2.5",
+            Command::SetPidGain(PidTerm::Kd, -2.5)
+        ));
+        assert!(parses_to("get heading", Command::Get(Quantity::Heading)));
+        assert!(parses_to("get encoders", Command::Get(Quantity::Encoders)));
+        assert!(parses_to("get battery", Command::Get(Quantity::Battery)));
+        assert!(parses_to("get currents", Command::Get(Quantity::Currents)));
+        assert!(parses_to("get pid", Command::Get(Quantity::Pid)));
+        assert!(parses_to("get tasks", Command::Get(Quantity::Tasks)));
+        assert!(parses_to("reset encoders", Command::ResetEncoders));
+        assert!(parses_to("config save", Command::ConfigSave));
+        assert!(parses_to("log off", Command::SetLogLevel(LogLevel::Off)));
+        assert!(parses_to(
+            "log error",
+            Command::SetLogLevel(LogLevel::Error)
+        ));
+        assert!(parses_to("log warn", Command::SetLogLevel(LogLevel::Warn)));
+        assert!(parses_to("log info", Command::SetLogLevel(LogLevel::Info)));
+        assert!(parses_to(
+            "log debug",
+            Command::SetLogLevel(LogLevel::Debug)
+        ));
+        assert!(parses_to(
+            "log trace",
+            Command::SetLogLevel(LogLevel::Trace)
+        ));
+        assert!(parses_to("help", Command::Help));
+    }
+
+    #[test]
+    fn movements_take_an_optional_controller_kind() {
+        for (line, command) in [
+            (
+                "straight 500 pid",
+                Command::Straight(500, ControllerKind::Pid),
+            ),
+            (
+                "straight 500 bangbang",
+                Command::Straight(500, ControllerKind::BangBang),
+            ),
+            (
+                "straight 500 leadlag",
+                Command::Straight(500, ControllerKind::LeadLag),
+            ),
+            ("turn -45 pid", Command::Turn(-45, ControllerKind::Pid)),
+            (
+                "turn -45 bangbang",
+                Command::Turn(-45, ControllerKind::BangBang),
+            ),
+            (
+                "turn -45 leadlag",
+                Command::Turn(-45, ControllerKind::LeadLag),
+            ),
+        ] {
+            assert!(parses_to(line, command), "{}", line);
+        }
+    }
+
+    #[test]
+    fn separates_tokens_by_any_whitespace() {
+        assert!(parses_to(
+            "  straight\t 250  ",
+            Command::Straight(250, ControllerKind::Pid)
+        ));
+        assert!(parse_command("") == Ok(None));
+        assert!(parse_command(" \t ") == Ok(None));
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert!(fails_with("fly 100", ShellError::UnknownCommand));
+        assert!(fails_with("Straight 100", ShellError::UnknownCommand));
+        assert!(fails_with("halt", ShellError::UnknownCommand));
+    }
+
+    #[test]
+    fn rejects_missing_arguments() {
+        for line in [
+            "straight", "turn", "pid", "pid kp", "get", "reset", "config", "log",
+        ] {
+            assert!(fails_with(line, ShellError::MissingArgument), "{}", line);
+        }
+    }
+
+    #[test]
+    fn rejects_extra_arguments() {
+        for line in [
+            "straight 500 pid fast",
+            "turn 90 leadlag 90",
+            "stop now",
+            "pid kp 18 1",
+            "get heading now",
+            "reset encoders all",
+            "config save now",
+            "log info now",
+            "help me",
+        ] {
+            assert!(fails_with(line, ShellError::ExtraArgument), "{}", line);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        for line in [
+            "straight -5",
+            "straight far",
+            "straight 4294967296",
+            "turn 361",
+            "turn -361",
+            "turn 40000",
+            "turn 1.5",
+            "straight 500 fast",
+            "turn 90 90",
+            "pid kx 18",
+            "pid kp fast",
+            "pid kp inf",
+            "pid kp NaN",
+            "get speed",
+            "reset heading",
+            "config load",
+            "log verbose",
+        ] {
+            assert!(fails_with(line, ShellError::InvalidArgument), "{}", line);
+        }
+    }
+
+    #[test]
+    fn reads_lines_ending_with_either_line_ending() {
+        let mut reader = LineReader::new();
+        assert!(reader.push(b't').is_none());
+        assert!(
+            read_line(&mut reader, b"urn 45\r") == Some(Ok(Command::Turn(45, ControllerKind::Pid)))
+        );
+        assert!(read_line(&mut reader, b"help\n") == Some(Ok(Command::Help)));
+        // the line feed of a CRLF line ending is a blank line
+        assert!(read_line(&mut reader, b"help\r\n") == Some(Ok(Command::Help)));
+        assert!(reader.push(b'\n').is_none());
+    }
+
+    #[test]
+    fn reads_a_stop_line_between_other_bytes() {
+        let mut reader = LineReader::new();
+        assert!(read_line(&mut reader, b"stop\r") == Some(Ok(Command::Stop)));
+        assert!(read_line(&mut reader, b"  stop \n") == Some(Ok(Command::Stop)));
+        assert!(read_line(&mut reader, b"stopp\x08\r") == Some(Ok(Command::Stop)));
+        assert!(read_line(&mut reader, b"stop 1\r") == Some(Err(ShellError::ExtraArgument)));
+    }
+
+    #[test]
+    fn backspace_removes_the_last_byte() {
+        let mut reader = LineReader::new();
+        let line = b"straight 5000\x08\x7f0\r";
+        assert!(
+            read_line(&mut reader, line) == Some(Ok(Command::Straight(500, ControllerKind::Pid)))
+        );
+        // backspace on an empty line does nothing
+        assert!(read_line(&mut reader, b"\x08help\r") == Some(Ok(Command::Help)));
+    }
+
+    #[test]
+    fn rejects_lines_that_are_too_long() {
+        let mut reader = LineReader::new();
+        let mut line = [b' '; MAX_LINE_LENGTH + 5];
+        line[..4].copy_from_slice(b"help");
+        assert!(read_line(&mut reader, &line[..MAX_LINE_LENGTH]).is_none());
+        assert!(reader.push(b'\r') == Some(Ok(Command::Help)));
+
+        assert!(read_line(&mut reader, &line).is_none());
+        assert!(reader.push(b'\r') == Some(Err(ShellError::LineTooLong)));
+        // the next line is read normally
+        assert!(read_line(&mut reader, b"get pid\r") == Some(Ok(Command::Get(Quantity::Pid))));
+    }
+
+    #[test]
+    fn rejects_lines_that_arent_utf8() {
+        let mut reader = LineReader::new();
+        let line = b"get \xff\r";
+        assert!(read_line(&mut reader, line) == Some(Err(ShellError::UnknownCommand)));
+    }
+}
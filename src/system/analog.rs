@@ -0,0 +1,33 @@
+use arduino_hal::{adc::Channel, Adc};
+use avr_device::interrupt;
+use core::cell::RefCell;
+
+/// The ADC is shared by all of the analog sensors, so it is kept in a global like the console.
+static ADC: interrupt::Mutex<RefCell<Option<Adc>>> = interrupt::Mutex::new(RefCell::new(None));
+
+pub fn put_adc(adc: Adc) {
+    interrupt::free(|cs| {
+        *ADC.borrow(cs).borrow_mut() = Some(adc);
+    })
+}
+
+/// Returns the raw 10-bit reading of an analog channel, or `None` if the ADC hasn't been set up.
+///
+/// A conversion takes about 100 microseconds. Interrupts are only disabled while the conversion
+/// is started and polled, not while waiting for it, so the millis clock and the serial
+/// interrupts aren't held off.
+pub fn read_analog(channel: &Channel) -> Option<u16> {
+    loop {
+        let reading = interrupt::free(|cs| {
+            ADC.borrow(cs)
+                .borrow_mut()
+                .as_mut()
+                .map(|adc| adc.read_nonblocking(channel))
+        });
+        match reading? {
+            Ok(counts) => return Some(counts),
+            Err(nb::Error::WouldBlock) => {}
+            Err(nb::Error::Other(error)) => match error {},
+        }
+    }
+}
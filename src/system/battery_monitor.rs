@@ -0,0 +1,61 @@
+use arduino_hal::adc::Channel;
+
+use super::analog::read_analog;
+use crate::model::battery::{Battery, NOMINAL_VOLTAGE};
+
+const ADC_REFERENCE_VOLTAGE: f32 = 5.0;
+const ADC_COUNTS: f32 = 1024.0;
+
+/// Monitors the battery voltage through a voltage divider on an analog input.
+pub struct BatteryMonitor {
+    channel: Channel,
+    divider_ratio: f32,
+    battery: Battery,
+}
+
+#[allow(dead_code)]
+impl BatteryMonitor {
+    /// `channel` is the analog input connected to the voltage divider and `divider_ratio` is the
+    /// ratio of the battery voltage to the voltage at the analog input, (R1 + R2) / R2.
+    pub fn new(channel: Channel, divider_ratio: f32) -> Self {
+        let voltage = read_voltage(&channel, divider_ratio).unwrap_or(NOMINAL_VOLTAGE);
+        Self {
+            channel,
+            divider_ratio,
+            battery: Battery::new(voltage),
+        }
+    }
+
+    /// Samples the battery voltage. The robot's scheduler calls this every 50 milliseconds.
+    pub fn update(&mut self) {
+        if let Some(voltage) = read_voltage(&self.channel, self.divider_ratio) {
+            self.battery.add_sample(voltage);
+        }
+    }
+
+    /// The filtered battery voltage in volts.
+    pub fn voltage(&self) -> f32 {
+        self.battery.voltage()
+    }
+
+    /// The estimated state of charge in percent.
+    pub fn state_of_charge(&self) -> u8 {
+        self.battery.state_of_charge()
+    }
+
+    /// Returns true while the battery voltage is too low to move.
+    pub fn is_below_cutoff(&self) -> bool {
+        self.battery.is_below_cutoff()
+    }
+
+    /// Scales a motor power level so that the motors get the same average voltage as they would
+    /// at the nominal battery voltage.
+    pub fn compensate_power(&self, power: f32) -> f32 {
+        self.battery.compensate_power(power)
+    }
+}
+
+fn read_voltage(channel: &Channel, divider_ratio: f32) -> Option<f32> {
+    read_analog(channel)
+        .map(|counts| counts as f32 * ADC_REFERENCE_VOLTAGE / ADC_COUNTS * divider_ratio)
+}
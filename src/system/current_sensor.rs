@@ -0,0 +1,43 @@
+use arduino_hal::adc::Channel;
+
+use super::analog::read_analog;
+use crate::model::motor_protection::CurrentSensor;
+
+const ADC_REFERENCE_MILLIVOLTS: f32 = 5000.0;
+const ADC_COUNTS: f32 = 1024.0;
+
+/// Measures the motor currents from the voltage across the shunt resistors on the L298N's
+/// current sense pins.
+pub struct AdcCurrentSensor {
+    channel_a: Channel,
+    channel_b: Channel,
+    milliamps_per_count: f32,
+}
+
+impl AdcCurrentSensor {
+    /// `channel_a` and `channel_b` are the analog inputs connected to the sense pins of motor A
+    /// and motor B. `shunt_resistance` is the shunt resistor value in ohms.
+    pub fn new(channel_a: Channel, channel_b: Channel, shunt_resistance: f32) -> Self {
+        Self {
+            channel_a,
+            channel_b,
+            milliamps_per_count: ADC_REFERENCE_MILLIVOLTS / ADC_COUNTS / shunt_resistance,
+        }
+    }
+
+    fn read_milliamps(&self, channel: &Channel) -> u16 {
+        match read_analog(channel) {
+            Some(counts) => (counts as f32 * self.milliamps_per_count) as u16,
+            None => 0,
+        }
+    }
+}
+
+impl CurrentSensor for AdcCurrentSensor {
+    fn read_currents(&mut self) -> (u16, u16) {
+        (
+            self.read_milliamps(&self.channel_a),
+            self.read_milliamps(&self.channel_b),
+        )
+    }
+}
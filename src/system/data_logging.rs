@@ -1,8 +1,10 @@
-use ufmt::{uWrite, uwrite};
+use ufmt::{uDisplay, uWrite, uwrite};
 
-pub fn log_csv_headers<W>(f: &mut W, headers: &[&str]) -> Result<(), W::Error>
+/// Writes a CSV header row of quoted column names. The headers can be `&str`s or flash strings.
+pub fn log_csv_headers<W, H>(f: &mut W, headers: &[H]) -> Result<(), W::Error>
 where
     W: uWrite + ?Sized,
+    H: uDisplay,
 {
     for i in 0..headers.len() {
         uwrite!(f, "\"{}\"", headers[i])?;
@@ -0,0 +1,121 @@
+use ufmt::{uDisplay, uWrite, Formatter};
+
+/// The number of bytes copied from flash to the stack at a time when a flash string is written.
+const CHUNK_SIZE: usize = 16;
+
+/// A string stored in program memory rather than in SRAM. Create one with the `F!` macro.
+///
+/// On the AVR, string literals are copied to the 8 KB of SRAM at start up. A flash string stays
+/// in flash and is read with LPM instructions, a small chunk at a time, when it is written. It
+/// implements `uDisplay`, so it can be printed with `println!("{}", F!("..."))`.
+#[derive(Copy, Clone)]
+pub struct FlashStr {
+    ptr: *const u8,
+    len: usize,
+}
+
+// SAFETY: a flash string is read only
+unsafe impl Sync for FlashStr {}
+
+#[allow(dead_code)]
+impl FlashStr {
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` bytes of valid UTF-8 in program memory.
+    pub const unsafe fn from_raw_parts(ptr: *const u8, len: usize) -> Self {
+        Self { ptr, len }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the string from byte `start` on. `start` must be on a character boundary.
+    pub const fn slice_from(self, start: usize) -> Self {
+        let start = if start < self.len { start } else { self.len };
+        Self {
+            // SAFETY: start is within the string
+            ptr: unsafe { self.ptr.add(start) },
+            len: self.len - start,
+        }
+    }
+
+    fn byte_at(&self, index: usize) -> u8 {
+        // SAFETY: the caller keeps index within the string, which is in program memory
+        unsafe { avr_progmem::raw::read_byte(self.ptr.add(index)) }
+    }
+
+    /// Passes the string to `write` in chunks that end on character boundaries.
+    fn for_each_chunk<E, F>(&self, mut write: F) -> Result<(), E>
+    where
+        F: FnMut(&str) -> Result<(), E>,
+    {
+        let mut chunk = [0u8; CHUNK_SIZE];
+        let mut start = 0;
+        while start < self.len {
+            let mut end = (start + CHUNK_SIZE).min(self.len);
+            // don't split a multi-byte character
+            while end < self.len && self.byte_at(end) & 0xC0 == 0x80 {
+                end -= 1;
+            }
+            for (i, byte) in chunk[..end - start].iter_mut().enumerate() {
+                *byte = self.byte_at(start + i);
+            }
+            // SAFETY: the string is valid UTF-8 and the chunk ends on a character boundary
+            write(unsafe { core::str::from_utf8_unchecked(&chunk[..end - start]) })?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    /// Writes the string to `f`.
+    pub fn write_to<W>(&self, f: &mut W) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        self.for_each_chunk(|chunk| f.write_str(chunk))
+    }
+}
+
+impl uDisplay for FlashStr {
+    fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        self.for_each_chunk(|chunk| f.write_str(chunk))
+    }
+}
+
+/// Copies a string into a byte array at compile time. Used by the `F!` macro.
+pub const fn to_bytes<const N: usize>(s: &str) -> [u8; N] {
+    let bytes = s.as_bytes();
+    let mut array = [0u8; N];
+    let mut i = 0;
+    while i < N {
+        array[i] = bytes[i];
+        i += 1;
+    }
+    array
+}
+
+/// Places a string constant in program memory and returns it as a `FlashStr`, like the Arduino
+/// `F()` macro. The string must be a constant expression, such as a literal.
+#[macro_export]
+macro_rules! F {
+    ($s:expr) => {{
+        const STRING: &str = $s;
+        #[link_section = ".progmem.data"]
+        static BYTES: [u8; STRING.len()] = $crate::system::flash_str::to_bytes(STRING);
+        // SAFETY: BYTES is in program memory and holds the UTF-8 bytes of STRING
+        unsafe {
+            $crate::system::flash_str::FlashStr::from_raw_parts(
+                core::ptr::addr_of!(BYTES).cast::<u8>(),
+                STRING.len(),
+            )
+        }
+    }};
+}
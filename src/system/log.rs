@@ -0,0 +1,149 @@
+use avr_device::interrupt::{self, Mutex};
+use core::cell::Cell;
+use ufmt::{uWrite, uwrite};
+
+pub use super::log_level::LogLevel;
+use super::{flash_str::FlashStr, millis::millis};
+
+/// The most verbose level that is compiled in, set by the `max_level_*` features. The log
+/// macros of more verbose levels compile to nothing, so their messages don't take up flash.
+#[cfg(feature = "max_level_off")]
+pub const MAX_LOG_LEVEL: LogLevel = LogLevel::Off;
+#[cfg(all(not(feature = "max_level_off"), feature = "max_level_error"))]
+pub const MAX_LOG_LEVEL: LogLevel = LogLevel::Error;
+#[cfg(all(
+    not(any(feature = "max_level_off", feature = "max_level_error")),
+    feature = "max_level_warn"
+))]
+pub const MAX_LOG_LEVEL: LogLevel = LogLevel::Warn;
+#[cfg(all(
+    not(any(
+        feature = "max_level_off",
+        feature = "max_level_error",
+        feature = "max_level_warn"
+    )),
+    feature = "max_level_debug"
+))]
+pub const MAX_LOG_LEVEL: LogLevel = LogLevel::Debug;
+#[cfg(all(
+    not(any(
+        feature = "max_level_off",
+        feature = "max_level_error",
+        feature = "max_level_warn",
+        feature = "max_level_debug"
+    )),
+    feature = "max_level_trace"
+))]
+pub const MAX_LOG_LEVEL: LogLevel = LogLevel::Trace;
+#[cfg(not(any(
+    feature = "max_level_off",
+    feature = "max_level_error",
+    feature = "max_level_warn",
+    feature = "max_level_debug",
+    feature = "max_level_trace"
+)))]
+pub const MAX_LOG_LEVEL: LogLevel = LogLevel::Info;
+
+static LOG_LEVEL: Mutex<Cell<LogLevel>> = Mutex::new(Cell::new(MAX_LOG_LEVEL));
+
+/// Sets the runtime log level. Levels more verbose than `MAX_LOG_LEVEL` stay compiled out.
+pub fn set_log_level(level: LogLevel) {
+    interrupt::free(|cs| LOG_LEVEL.borrow(cs).set(level.min(MAX_LOG_LEVEL)));
+}
+
+pub fn log_level() -> LogLevel {
+    interrupt::free(|cs| LOG_LEVEL.borrow(cs).get())
+}
+
+/// Returns true if messages of `level` are logged. The log macros check the compile time level
+/// first, so that disabled levels are removed as dead code.
+#[inline(always)]
+pub fn log_enabled(level: LogLevel) -> bool {
+    (level as u8) <= (MAX_LOG_LEVEL as u8) && level != LogLevel::Off && level <= log_level()
+}
+
+/// Returns where the module name starts in a module path, after the crate name. Evaluated at
+/// compile time by the log macros.
+pub const fn module_name_start(module_path: &str) -> usize {
+    let bytes = module_path.as_bytes();
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        if bytes[i] == b':' && bytes[i + 1] == b':' {
+            return i + 2;
+        }
+        i += 1;
+    }
+    0
+}
+
+/// Writes the `[<millis> <level> <module>] ` prefix of a log message.
+pub fn write_log_prefix<W>(f: &mut W, level: LogLevel, module: FlashStr) -> Result<(), W::Error>
+where
+    W: uWrite + ?Sized,
+{
+    uwrite!(f, "[{} {} {}] ", millis(), level, module)
+}
+
+/// Returns true if a message has no braces, so it can be printed as is rather than as a format
+/// string. Evaluated at compile time by the log macros.
+pub const fn is_plain_message(message: &str) -> bool {
+    let bytes = message.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'{' || bytes[i] == b'}' {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Logs a message at a level. A message that is only a string literal without braces is kept in
+/// flash, since it doesn't need to be formatted.
+#[macro_export]
+macro_rules! log {
+    (@write $level:expr, $($t:tt)*) => {{
+        let level = $level;
+        if $crate::system::log::log_enabled(level) {
+            const MODULE_START: usize = $crate::system::log::module_name_start(module_path!());
+            let module = $crate::F!(module_path!()).slice_from(MODULE_START);
+            let mut writer = $crate::system::serial_print::ConsoleWriter::blocking();
+            let _ = $crate::system::log::write_log_prefix(&mut writer, level, module);
+            let _ = ufmt::uwriteln!(writer, $($t)*);
+        }
+    }};
+    ($level:expr, $message:literal $(,)?) => {{
+        const PLAIN: bool = $crate::system::log::is_plain_message($message);
+        if PLAIN {
+            $crate::log!(@write $level, "{}", $crate::F!($message))
+        } else {
+            $crate::log!(@write $level, $message)
+        }
+    }};
+    ($level:expr, $($t:tt)*) => { $crate::log!(@write $level, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Error, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Warn, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Info, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Debug, $($t)*) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($t:tt)*) => { $crate::log!($crate::system::log::LogLevel::Trace, $($t)*) };
+}
@@ -1,45 +1,60 @@
 // This code taken from the example code in the avr-hal crate, which is licensed under the MIT license:
 //      https://github.com/Rahix/avr-hal/blob/main/examples/arduino-uno/src/bin/uno-millis.rs
 //
-//  modified for the Arduino Mega 2560
+//  modified for the Arduino Mega 2560, and extended with `micros()`
 
 /*!
- * A basic implementation of the `millis()` function from Arduino:
+ * A basic implementation of the `millis()` and `micros()` functions from Arduino:
  *
  *     https://www.arduino.cc/reference/en/language/functions/time/millis/
+ *     https://www.arduino.cc/reference/en/language/functions/time/micros/
  *
- * Uses timer TC0 and one of its interrupts to update a global millisecond
- * counter.  A walkthough of this code is available here:
+ * Uses timer TC0 and one of its interrupts to count timer overflows.  `millis()` is
+ * the overflow count scaled to milliseconds, and `micros()` adds the live timer count
+ * to it.  A walkthough of this code is available here:
  *
  *     https://blog.rahix.de/005-avr-hal-millis/
  */
 use core::cell;
 
-// Possible Values:
-//
-// ╔═══════════╦══════════════╦═══════════════════╗
-// ║ PRESCALER ║ TIMER_COUNTS ║ Overflow Interval ║
-// ╠═══════════╬══════════════╬═══════════════════╣
-// ║        64 ║          250 ║              1 ms ║
-// ║       256 ║          125 ║              2 ms ║
-// ║       256 ║          250 ║              4 ms ║
-// ║      1024 ║          125 ║              8 ms ║
-// ║      1024 ║          250 ║             16 ms ║
-// ╚═══════════╩══════════════╩═══════════════════╝
-const PRESCALER: u32 = 256;
-const TIMER_COUNTS: u32 = 125;
+/// The time between timer overflows, which is how often `millis()` steps, in milliseconds.
+/// Longer periods interrupt the CPU less often.
+///
+/// ╔════════════╦═══════════╦══════════════╦═══════════════════╗
+/// ║ TICK (ms)  ║ PRESCALER ║ TIMER_COUNTS ║ micros resolution ║
+/// ╠════════════╬═══════════╬══════════════╬═══════════════════╣
+/// ║          1 ║        64 ║          250 ║              4 us ║
+/// ║          2 ║       256 ║          125 ║             16 us ║
+/// ║          4 ║       256 ║          250 ║             16 us ║
+/// ║          8 ║      1024 ║          125 ║             64 us ║
+/// ║         16 ║      1024 ║          250 ║             64 us ║
+/// ╚════════════╩═══════════╩══════════════╩═══════════════════╝
+pub const MILLIS_TICK: u32 = 1;
+
+const PRESCALER: u32 = match MILLIS_TICK {
+    1 => 64,
+    2 | 4 => 256,
+    8 | 16 => 1024,
+    _ => panic!("MILLIS_TICK must be 1, 2, 4, 8 or 16 ms"),
+};
+const TIMER_COUNTS: u32 = match MILLIS_TICK {
+    2 | 8 => 125,
+    _ => 250,
+};
+const _: () = assert!(PRESCALER * TIMER_COUNTS / 16000 == MILLIS_TICK);
 
-const MILLIS_INCREMENT: u32 = PRESCALER * TIMER_COUNTS / 16000;
+const MICROS_PER_COUNT: u32 = PRESCALER / 16;
+const MICROS_PER_TICK: u32 = MILLIS_TICK * 1000;
 
-static MILLIS_COUNTER: avr_device::interrupt::Mutex<cell::Cell<u32>> =
+static OVERFLOW_COUNTER: avr_device::interrupt::Mutex<cell::Cell<u32>> =
     avr_device::interrupt::Mutex::new(cell::Cell::new(0));
 
-/// Initializes the timer and interrupt used by `millis()`.
+/// Initializes the timer and interrupt used by `millis()` and `micros()`.
 pub fn millis_init(tc0: arduino_hal::pac::TC0) {
     // Configure the timer for the above interval (in CTC mode)
-    // and enable its interrupt.
+    // and enable its interrupt. The timer counts from 0 to OCR0A inclusive.
     tc0.tccr0a.write(|w| w.wgm0().ctc());
-    tc0.ocr0a.write(|w| w.bits(TIMER_COUNTS as u8));
+    tc0.ocr0a.write(|w| w.bits((TIMER_COUNTS - 1) as u8));
     tc0.tccr0b.write(|w| match PRESCALER {
         8 => w.cs0().prescale_8(),
         64 => w.cs0().prescale_64(),
@@ -49,22 +64,42 @@ pub fn millis_init(tc0: arduino_hal::pac::TC0) {
     });
     tc0.timsk0.write(|w| w.ocie0a().set_bit());
 
-    // Reset the global millisecond counter
+    // Reset the global overflow counter
     avr_device::interrupt::free(|cs| {
-        MILLIS_COUNTER.borrow(cs).set(0);
+        OVERFLOW_COUNTER.borrow(cs).set(0);
     });
 }
 
 #[avr_device::interrupt(atmega2560)]
 fn TIMER0_COMPA() {
     avr_device::interrupt::free(|cs| {
-        let counter_cell = MILLIS_COUNTER.borrow(cs);
+        let counter_cell = OVERFLOW_COUNTER.borrow(cs);
         let counter = counter_cell.get();
-        counter_cell.set(counter + MILLIS_INCREMENT);
+        counter_cell.set(counter.wrapping_add(1));
     })
 }
 
-/// Returns the number of milliseconds since the program started.
+/// Returns the number of milliseconds since the program started. It steps by `MILLIS_TICK`.
 pub fn millis() -> u32 {
-    avr_device::interrupt::free(|cs| MILLIS_COUNTER.borrow(cs).get())
+    avr_device::interrupt::free(|cs| OVERFLOW_COUNTER.borrow(cs).get()).wrapping_mul(MILLIS_TICK)
+}
+
+/// Returns the number of microseconds since the program started. It wraps around after about
+/// 71.6 minutes, so only use it for differences between nearby readings.
+pub fn micros() -> u32 {
+    avr_device::interrupt::free(|cs| {
+        // SAFETY: only reads the counter and the interrupt flag, which `millis_init` configured
+        let tc0 = unsafe { &*arduino_hal::pac::TC0::ptr() };
+        let mut overflows = OVERFLOW_COUNTER.borrow(cs).get();
+        let mut counts = tc0.tcnt0.read().bits();
+        // with interrupts held off, the timer may have restarted without the overflow being
+        // counted yet. read the count again after the flag, so it's known to be after the restart.
+        if tc0.tifr0.read().ocf0a().bit_is_set() {
+            counts = tc0.tcnt0.read().bits();
+            overflows = overflows.wrapping_add(1);
+        }
+        overflows
+            .wrapping_mul(MICROS_PER_TICK)
+            .wrapping_add(counts as u32 * MICROS_PER_COUNT)
+    })
 }
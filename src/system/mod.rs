@@ -1,3 +1,15 @@
+pub mod analog;
+pub mod battery_monitor;
+pub mod current_sensor;
 pub mod data_logging;
+pub mod flash_str;
+pub mod log;
+pub mod log_level;
 pub mod millis;
+pub mod ring_buffer;
+pub mod run_files;
+pub mod scheduler;
+pub mod sd_logger;
 pub mod serial_print;
+pub mod settings;
+pub mod time;
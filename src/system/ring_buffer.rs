@@ -0,0 +1,135 @@
+/// A fixed capacity FIFO queue of bytes. Pushing and popping a byte are O(1), so a ring buffer
+/// can be filled in one context and emptied in another, such as an interrupt handler.
+pub struct RingBuffer<const N: usize> {
+    buffer: [u8; N],
+    head: usize,
+    length: usize,
+}
+
+#[allow(dead_code)]
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            head: 0,
+            length: 0,
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.length == N
+    }
+
+    /// The number of bytes that can be pushed before the buffer is full.
+    pub fn free(&self) -> usize {
+        N - self.length
+    }
+
+    /// Appends a byte to the end of the queue. Returns false if the buffer is full.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.buffer[(self.head + self.length) % N] = byte;
+        self.length += 1;
+        true
+    }
+
+    /// Removes and returns the byte at the front of the queue.
+    pub fn pop(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.head = (self.head + 1) % N;
+        self.length -= 1;
+        Some(byte)
+    }
+
+    /// Returns the byte at the front of the queue without removing it.
+    pub fn peek(&self) -> Option<u8> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.buffer[self.head])
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.length = 0;
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_bytes_in_the_order_they_were_pushed() {
+        let mut buffer: RingBuffer<4> = RingBuffer::new();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.push(1));
+        assert!(buffer.push(2));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.free(), 2);
+        assert_eq!(buffer.peek(), Some(1));
+        assert_eq!(buffer.pop(), Some(1));
+        assert_eq!(buffer.pop(), Some(2));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn refuses_bytes_when_full() {
+        let mut buffer: RingBuffer<3> = RingBuffer::new();
+        for byte in 0..3 {
+            assert!(buffer.push(byte));
+        }
+        assert!(buffer.is_full());
+        assert!(!buffer.push(3));
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop(), Some(0));
+    }
+
+    #[test]
+    fn wraps_around_the_end_of_the_storage() {
+        let mut buffer: RingBuffer<4> = RingBuffer::new();
+        // push and pop enough bytes that the head goes around the buffer several times
+        for byte in 0..10u8 {
+            assert!(buffer.push(byte));
+            assert!(buffer.push(byte + 100));
+            assert_eq!(buffer.pop(), Some(byte));
+            assert_eq!(buffer.pop(), Some(byte + 100));
+        }
+        for byte in 20..23 {
+            assert!(buffer.push(byte));
+        }
+        assert!(buffer.push(23));
+        assert!(buffer.is_full());
+        for byte in 20..24 {
+            assert_eq!(buffer.pop(), Some(byte));
+        }
+        assert!(buffer.is_empty());
+
+        buffer.push(1);
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.free(), 4);
+    }
+}
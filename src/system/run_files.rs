@@ -0,0 +1,434 @@
+use embedded_sdmmc::{
+    BlockDevice, Directory, Error, File, Mode, TimeSource, Volume, VolumeIdx, VolumeManager,
+};
+
+/// The size of an SD card block. Bytes are buffered and written a whole block at a time.
+pub const BLOCK_SIZE: usize = 512;
+const RUN_FILE_PREFIX: &[u8] = b"RUN";
+const RUN_NUMBER_DIGITS: usize = 5;
+const MAX_RUN_NUMBER: u32 = 99999;
+
+/// Why a run file operation failed.
+#[derive(Debug)]
+pub enum RunFileError<E: core::fmt::Debug> {
+    /// Every run number up to `MAX_RUN_NUMBER` is taken.
+    OutOfRunNumbers,
+    /// The file system or the block device failed.
+    FileSystem(Error<E>),
+}
+
+impl<E: core::fmt::Debug> From<Error<E>> for RunFileError<E> {
+    fn from(error: Error<E>) -> Self {
+        RunFileError::FileSystem(error)
+    }
+}
+
+/// Writes runs to numbered files, such as `RUN00001.CSV`, in the root directory of the first FAT
+/// volume of a block device. Each run gets the next unused number. The bytes of a run are
+/// buffered and written to the device a whole block at a time.
+pub struct RunFiles<D: BlockDevice, T: TimeSource> {
+    /// Only `None` while the volume manager is recreated, see `release_closed_files`.
+    volume_manager: Option<VolumeManager<D, T>>,
+    volume: Volume,
+    root_dir: Directory,
+    extension: &'static [u8],
+    file: Option<File>,
+    buffer: [u8; BLOCK_SIZE],
+    buffer_length: usize,
+    next_run_number: u32,
+}
+
+#[allow(dead_code)]
+impl<D: BlockDevice, T: TimeSource> RunFiles<D, T> {
+    /// Opens the first volume of `device` and finds the next unused run number among the files
+    /// with `extension`.
+    pub fn open(
+        device: D,
+        time_source: T,
+        extension: &'static [u8],
+    ) -> Result<Self, RunFileError<D::Error>> {
+        let mut volume_manager = VolumeManager::new(device, time_source);
+        let volume = volume_manager.get_volume(VolumeIdx(0))?;
+        let root_dir = volume_manager.open_root_dir(&volume)?;
+
+        let mut last_run_number = 0;
+        volume_manager.iterate_dir(&volume, &root_dir, |entry| {
+            let name = &entry.name;
+            if name.extension() == extension {
+                if let Some(run_number) = parse_run_number(name.base_name()) {
+                    last_run_number = last_run_number.max(run_number);
+                }
+            }
+        })?;
+
+        Ok(Self {
+            volume_manager: Some(volume_manager),
+            volume,
+            root_dir,
+            extension,
+            file: None,
+            buffer: [0; BLOCK_SIZE],
+            buffer_length: 0,
+            next_run_number: last_run_number + 1,
+        })
+    }
+
+    /// The block device, such as to re-clock an SD card's SPI bus once the card is initialized.
+    pub fn device(&mut self) -> Option<&mut D> {
+        self.volume_manager.as_mut().map(VolumeManager::device)
+    }
+
+    /// The number the next run's file gets.
+    pub fn next_run_number(&self) -> u32 {
+        self.next_run_number
+    }
+
+    /// Returns true while a run's file is open.
+    pub fn is_run_open(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Ends the current run, if any, and creates the next run's file. Returns the 8.3 file name.
+    pub fn start_run(&mut self) -> Result<[u8; 12], RunFileError<D::Error>> {
+        self.end_run()?;
+        if self.next_run_number > MAX_RUN_NUMBER {
+            return Err(RunFileError::OutOfRunNumbers);
+        }
+        let mut name = [0u8; 12];
+        run_file_name(self.next_run_number, self.extension, &mut name);
+        if let Some(volume_manager) = self.volume_manager.as_mut() {
+            let file = volume_manager.open_file_in_dir(
+                &mut self.volume,
+                &self.root_dir,
+                core::str::from_utf8(&name).unwrap_or_default(),
+                Mode::ReadWriteCreate,
+            )?;
+            self.file = Some(file);
+            self.next_run_number += 1;
+        }
+        Ok(name)
+    }
+
+    /// Appends bytes to the current run, writing the buffer out each time a block is full.
+    /// Nothing is written while no run is open.
+    pub fn append(&mut self, mut bytes: &[u8]) -> Result<(), RunFileError<D::Error>> {
+        while !bytes.is_empty() && self.file.is_some() {
+            let count = bytes.len().min(BLOCK_SIZE - self.buffer_length);
+            self.buffer[self.buffer_length..self.buffer_length + count]
+                .copy_from_slice(&bytes[..count]);
+            self.buffer_length += count;
+            bytes = &bytes[count..];
+            if self.buffer_length == BLOCK_SIZE {
+                self.write_buffer()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes out the rest of the current run and closes its file.
+    pub fn end_run(&mut self) -> Result<(), RunFileError<D::Error>> {
+        if self.file.is_none() {
+            return Ok(());
+        }
+        let written = self.write_buffer();
+        if let (Some(volume_manager), Some(file)) = (self.volume_manager.as_mut(), self.file.take())
+        {
+            volume_manager.close_file(&self.volume, file)?;
+        }
+        self.release_closed_files();
+        written
+    }
+
+    /// The volume manager remembers an open file by its first cluster, but a new file only gets
+    /// its first cluster when it's first written. So closing a run's file doesn't release its
+    /// open file slot, and the slots would run out after a few runs. Recreating the volume
+    /// manager releases them. The volume and the root directory stay valid.
+    fn release_closed_files(&mut self) {
+        if let Some(volume_manager) = self.volume_manager.take() {
+            let (device, time_source) = volume_manager.free();
+            self.volume_manager = Some(VolumeManager::new(device, time_source));
+        }
+    }
+
+    /// Writes the buffered bytes to the open file. The buffer is emptied even if the write
+    /// fails.
+    fn write_buffer(&mut self) -> Result<(), RunFileError<D::Error>> {
+        let length = core::mem::take(&mut self.buffer_length);
+        if let (Some(volume_manager), Some(file)) =
+            (self.volume_manager.as_mut(), self.file.as_mut())
+        {
+            if length > 0 {
+                volume_manager.write(&mut self.volume, file, &self.buffer[..length])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the number of a `RUNnnnnn` base name.
+fn parse_run_number(base_name: &[u8]) -> Option<u32> {
+    let digits = base_name.strip_prefix(RUN_FILE_PREFIX)?;
+    if digits.len() != RUN_NUMBER_DIGITS {
+        return None;
+    }
+    digits.iter().try_fold(0, |number, digit| {
+        digit
+            .is_ascii_digit()
+            .then(|| number * 10 + (digit - b'0') as u32)
+    })
+}
+
+/// Formats the 8.3 file name of a run, such as `RUN00001.CSV`.
+fn run_file_name(run_number: u32, extension: &[u8], name: &mut [u8; 12]) {
+    name[..RUN_FILE_PREFIX.len()].copy_from_slice(RUN_FILE_PREFIX);
+    let mut number = run_number;
+    for i in (RUN_FILE_PREFIX.len()..RUN_FILE_PREFIX.len() + RUN_NUMBER_DIGITS).rev() {
+        name[i] = b'0' + (number % 10) as u8;
+        number /= 10;
+    }
+    name[8] = b'.';
+    name[9..].copy_from_slice(extension);
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::{Cell, RefCell};
+
+    use embedded_sdmmc::{Block, BlockCount, BlockIdx, Timestamp};
+
+    use super::*;
+
+    /// The number of blocks a `RamDisk` can hold. Blocks that were never written read as zeros
+    /// and take no room, so a disk with a freshly formatted volume holds a few runs.
+    const RAM_DISK_SLOTS: usize = 48;
+    /// The FAT16 volume's clusters are one block each. FAT16 needs at least 4085 clusters.
+    const CLUSTER_COUNT: u32 = 4096;
+    const RESERVED_BLOCKS: u32 = 1;
+    const FAT_BLOCKS: u32 = ((CLUSTER_COUNT + 2) * 2).div_ceil(BLOCK_SIZE as u32);
+    const ROOT_ENTRIES: u32 = 512;
+    const ROOT_DIR_BLOCKS: u32 = ROOT_ENTRIES * 32 / BLOCK_SIZE as u32;
+    const VOLUME_BLOCKS: u32 = RESERVED_BLOCKS + FAT_BLOCKS + ROOT_DIR_BLOCKS + CLUSTER_COUNT;
+    /// The volume starts after the master boot record.
+    const VOLUME_START: u32 = 1;
+
+    #[derive(Debug)]
+    enum RamDiskError {
+        OutOfRange,
+        Full,
+    }
+
+    /// A block device in RAM with a FAT16 volume. Only the blocks that were written are stored.
+    struct RamDisk {
+        blocks: RefCell<[Option<(u32, Block)>; RAM_DISK_SLOTS]>,
+        write_count: Cell<usize>,
+    }
+
+    impl RamDisk {
+        fn formatted() -> Self {
+            let disk = Self {
+                blocks: RefCell::new(core::array::from_fn(|_| None)),
+                write_count: Cell::new(0),
+            };
+
+            let mut mbr = Block::new();
+            let partition = &mut mbr[446..462];
+            partition[4] = 0x06; // FAT16
+            partition[8..12].copy_from_slice(&VOLUME_START.to_le_bytes());
+            partition[12..16].copy_from_slice(&VOLUME_BLOCKS.to_le_bytes());
+            mbr[510..].copy_from_slice(&[0x55, 0xAA]);
+            disk.store(0, &mbr).unwrap();
+
+            let mut boot_block = Block::new();
+            boot_block[..11].copy_from_slice(b"\xEB\x3C\x90MSWIN4.1");
+            boot_block[11..13].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+            boot_block[13] = 1; // blocks per cluster
+            boot_block[14..16].copy_from_slice(&(RESERVED_BLOCKS as u16).to_le_bytes());
+            boot_block[16] = 1; // FATs
+            boot_block[17..19].copy_from_slice(&(ROOT_ENTRIES as u16).to_le_bytes());
+            boot_block[19..21].copy_from_slice(&(VOLUME_BLOCKS as u16).to_le_bytes());
+            boot_block[21] = 0xF8; // fixed disk
+            boot_block[22..24].copy_from_slice(&(FAT_BLOCKS as u16).to_le_bytes());
+            boot_block[38] = 0x29; // extended boot signature
+            boot_block[43..62].copy_from_slice(b"ROBOT      FAT16   ");
+            boot_block[510..].copy_from_slice(&[0x55, 0xAA]);
+            disk.store(VOLUME_START, &boot_block).unwrap();
+
+            // the first two FAT entries hold the media type and the end of chain marker
+            let mut fat = Block::new();
+            fat[..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+            disk.store(VOLUME_START + RESERVED_BLOCKS, &fat).unwrap();
+
+            disk.write_count.set(0);
+            disk
+        }
+
+        fn store(&self, index: u32, block: &Block) -> Result<(), RamDiskError> {
+            if index > VOLUME_START + VOLUME_BLOCKS {
+                return Err(RamDiskError::OutOfRange);
+            }
+            self.write_count.set(self.write_count.get() + 1);
+            let mut blocks = self.blocks.borrow_mut();
+            let position = blocks
+                .iter()
+                .position(|slot| matches!(slot, Some((stored, _)) if *stored == index))
+                .or_else(|| blocks.iter().position(Option::is_none))
+                .ok_or(RamDiskError::Full)?;
+            blocks[position] = Some((index, block.clone()));
+            Ok(())
+        }
+
+        fn load(&self, index: u32) -> Block {
+            self.blocks
+                .borrow()
+                .iter()
+                .flatten()
+                .find(|(stored, _)| *stored == index)
+                .map_or_else(Block::new, |(_, block)| block.clone())
+        }
+
+        /// The number of block writes so far, not counting the formatting.
+        fn write_count(&self) -> usize {
+            self.write_count.get()
+        }
+    }
+
+    impl BlockDevice for &RamDisk {
+        type Error = RamDiskError;
+
+        fn read(
+            &self,
+            blocks: &mut [Block],
+            start_block_idx: BlockIdx,
+            _reason: &str,
+        ) -> Result<(), Self::Error> {
+            for (offset, block) in blocks.iter_mut().enumerate() {
+                *block = self.load(start_block_idx.0 + offset as u32);
+            }
+            Ok(())
+        }
+
+        fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+            for (offset, block) in blocks.iter().enumerate() {
+                self.store(start_block_idx.0 + offset as u32, block)?;
+            }
+            Ok(())
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+            Ok(BlockCount(VOLUME_START + VOLUME_BLOCKS))
+        }
+    }
+
+    struct ZeroTimeSource;
+
+    impl TimeSource for ZeroTimeSource {
+        fn get_timestamp(&self) -> Timestamp {
+            Timestamp {
+                year_since_1970: 0,
+                zero_indexed_month: 0,
+                zero_indexed_day: 0,
+                hours: 0,
+                minutes: 0,
+                seconds: 0,
+            }
+        }
+    }
+
+    fn open<'a>(
+        disk: &'a RamDisk,
+        extension: &'static [u8],
+    ) -> RunFiles<&'a RamDisk, ZeroTimeSource> {
+        RunFiles::open(disk, ZeroTimeSource, extension).unwrap()
+    }
+
+    /// Reads a file's contents into `buffer` and returns its length.
+    fn read_file(disk: &RamDisk, name: &str, buffer: &mut [u8]) -> usize {
+        let mut volume_manager = VolumeManager::new(disk, ZeroTimeSource);
+        let mut volume = volume_manager.get_volume(VolumeIdx(0)).unwrap();
+        let root_dir = volume_manager.open_root_dir(&volume).unwrap();
+        let mut file = volume_manager
+            .open_file_in_dir(&mut volume, &root_dir, name, Mode::ReadOnly)
+            .unwrap();
+        let length = volume_manager.read(&volume, &mut file, buffer).unwrap();
+        assert_eq!(length, file.length() as usize);
+        length
+    }
+
+    #[test]
+    fn parses_run_numbers() {
+        assert_eq!(parse_run_number(b"RUN00042"), Some(42));
+        assert_eq!(parse_run_number(b"RUN99999"), Some(99999));
+        assert_eq!(parse_run_number(b"RUN0042"), None);
+        assert_eq!(parse_run_number(b"RUN0004A"), None);
+        assert_eq!(parse_run_number(b"LOG00042"), None);
+
+        let mut name = [0u8; 12];
+        run_file_name(42, b"CSV", &mut name);
+        assert_eq!(&name, b"RUN00042.CSV");
+    }
+
+    #[test]
+    fn runs_are_numbered_after_the_existing_runs() {
+        let disk = RamDisk::formatted();
+        let mut runs = open(&disk, b"CSV");
+        assert_eq!(runs.next_run_number(), 1);
+        assert_eq!(&runs.start_run().unwrap(), b"RUN00001.CSV");
+        // starting a run ends the previous one
+        assert_eq!(&runs.start_run().unwrap(), b"RUN00002.CSV");
+        runs.end_run().unwrap();
+
+        assert_eq!(open(&disk, b"CSV").next_run_number(), 3);
+        // the numbering is separate for each format
+        let mut binary_runs = open(&disk, b"BIN");
+        assert_eq!(&binary_runs.start_run().unwrap(), b"RUN00001.BIN");
+        binary_runs.end_run().unwrap();
+        assert_eq!(open(&disk, b"CSV").next_run_number(), 3);
+    }
+
+    #[test]
+    fn writes_whole_blocks() {
+        let disk = RamDisk::formatted();
+        let mut runs = open(&disk, b"CSV");
+        runs.start_run().unwrap();
+        let mut bytes = [0u8; BLOCK_SIZE + 100];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = b'a' + (i % 26) as u8;
+        }
+
+        let write_count = disk.write_count();
+        runs.append(&bytes[..300]).unwrap();
+        runs.append(&bytes[300..BLOCK_SIZE - 1]).unwrap();
+        assert_eq!(disk.write_count(), write_count);
+        runs.append(&bytes[BLOCK_SIZE - 1..]).unwrap();
+        assert!(disk.write_count() > write_count);
+
+        let write_count = disk.write_count();
+        runs.end_run().unwrap();
+        assert!(disk.write_count() > write_count);
+        let mut contents = [0u8; 2 * BLOCK_SIZE];
+        let length = read_file(&disk, "RUN00001.CSV", &mut contents);
+        assert_eq!(&contents[..length], &bytes[..]);
+    }
+
+    #[test]
+    fn end_run_closes_the_file() {
+        let disk = RamDisk::formatted();
+        let mut runs = open(&disk, b"BIN");
+        // the volume manager has room for four open files, so the runs must be closed
+        for _ in 0..6 {
+            runs.start_run().unwrap();
+            runs.append(b"row\n").unwrap();
+            runs.end_run().unwrap();
+            assert!(!runs.is_run_open());
+        }
+        let mut contents = [0u8; 8];
+        let length = read_file(&disk, "RUN00006.BIN", &mut contents);
+        assert_eq!(&contents[..length], b"row\n");
+
+        // nothing is written between runs
+        let write_count = disk.write_count();
+        runs.append(b"ignored").unwrap();
+        runs.end_run().unwrap();
+        assert_eq!(disk.write_count(), write_count);
+    }
+}
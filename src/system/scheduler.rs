@@ -0,0 +1,396 @@
+use ufmt::{uDisplay, uWrite, uwrite, Formatter};
+
+use super::{
+    millis::micros,
+    time::{Duration, Instant},
+};
+
+/// Whether a scheduled task runs repeatedly or once.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TaskKind {
+    Periodic,
+    OneShot,
+}
+
+/// Run statistics of a scheduled task.
+#[derive(Copy, Clone, Default)]
+pub struct TaskStats {
+    pub runs: u32,
+    /// The longest delay between when the task was due and when it started, in microseconds.
+    pub max_jitter: u32,
+    total_jitter: u32,
+    /// The longest run, in microseconds.
+    pub max_run_time: u32,
+    /// The number of runs of a periodic task that didn't finish before the task was next due.
+    pub overruns: u32,
+}
+
+#[allow(dead_code)]
+impl TaskStats {
+    /// The average delay between when the task was due and when it started, in microseconds.
+    pub fn mean_jitter(&self) -> u32 {
+        if self.runs == 0 {
+            return 0;
+        }
+        self.total_jitter / self.runs
+    }
+}
+
+impl uDisplay for TaskStats {
+    fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uwrite!(
+            f,
+            "runs: {}, jitter: mean {} us, max {} us, max run time: {} us, overruns: {}",
+            self.runs,
+            self.mean_jitter(),
+            self.max_jitter,
+            self.max_run_time,
+            self.overruns,
+        )
+    }
+}
+
+/// Returned when a task can't be added because the scheduler is full.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct SchedulerFull;
+
+#[derive(Copy, Clone)]
+struct Task<T> {
+    id: T,
+    kind: TaskKind,
+    period: Duration,
+    priority: u8,
+    due: Instant,
+    stats: TaskStats,
+}
+
+/// The task that was started last and hasn't finished yet.
+#[derive(Copy, Clone)]
+struct RunningTask {
+    index: usize,
+    start_micros: u32,
+    /// When the task is next due, in `micros()` time.
+    next_due_micros: u32,
+}
+
+/// A cooperative scheduler with room for `N` tasks.
+///
+/// Tasks are identified by `T`, usually an enum of the owner's tasks. The scheduler doesn't run
+/// the tasks itself. The owner calls `start_next` from its loop, runs the returned task, and then
+/// calls `finish`, which lets the scheduler record the task's run time. When several tasks are
+/// due, the one with the highest priority starts first.
+pub struct Scheduler<T: Copy + PartialEq, const N: usize> {
+    tasks: [Option<Task<T>>; N],
+    running: Option<RunningTask>,
+}
+
+#[allow(dead_code)]
+impl<T: Copy + PartialEq, const N: usize> Scheduler<T, N> {
+    pub fn new() -> Self {
+        Self {
+            tasks: [None; N],
+            running: None,
+        }
+    }
+
+    /// Adds a task that is due every `period`, starting one period from now. A task that is
+    /// already scheduled is replaced. The period is at least one millisecond.
+    pub fn add_periodic(
+        &mut self,
+        id: T,
+        period: Duration,
+        priority: u8,
+    ) -> Result<(), SchedulerFull> {
+        let period = period.max(Duration::from_millis(1));
+        self.add(id, TaskKind::Periodic, period, priority, Instant::after(period))
+    }
+
+    /// Adds a task that is due once, `delay` from now. A task that is already scheduled is
+    /// replaced.
+    pub fn add_one_shot(
+        &mut self,
+        id: T,
+        delay: Duration,
+        priority: u8,
+    ) -> Result<(), SchedulerFull> {
+        self.add(id, TaskKind::OneShot, delay, priority, Instant::after(delay))
+    }
+
+    fn add(
+        &mut self,
+        id: T,
+        kind: TaskKind,
+        period: Duration,
+        priority: u8,
+        due: Instant,
+    ) -> Result<(), SchedulerFull> {
+        let index = self
+            .index_of(id)
+            .or_else(|| self.tasks.iter().position(|task| task.is_none()))
+            .ok_or(SchedulerFull)?;
+        self.tasks[index] = Some(Task {
+            id,
+            kind,
+            period,
+            priority,
+            due,
+            stats: TaskStats::default(),
+        });
+        if matches!(self.running, Some(running) if running.index == index) {
+            self.running = None;
+        }
+        Ok(())
+    }
+
+    /// Removes a task. Does nothing if the task isn't scheduled.
+    pub fn remove(&mut self, id: T) {
+        if let Some(index) = self.index_of(id) {
+            self.tasks[index] = None;
+            if matches!(self.running, Some(running) if running.index == index) {
+                self.running = None;
+            }
+        }
+    }
+
+    pub fn contains(&self, id: T) -> bool {
+        self.index_of(id).is_some()
+    }
+
+    /// Changes a periodic task's period. The task's next due time doesn't change.
+    pub fn set_period(&mut self, id: T, period: Duration) {
+        if let Some(task) = self.task_mut(id) {
+            if task.kind == TaskKind::Periodic {
+                task.period = period.max(Duration::from_millis(1));
+            }
+        }
+    }
+
+    /// Makes a periodic task due one period from now, or a one-shot task due after its
+    /// original delay from now.
+    pub fn restart(&mut self, id: T) {
+        if let Some(task) = self.task_mut(id) {
+            task.due = Instant::after(task.period);
+        }
+    }
+
+    /// Returns a task's run statistics, or `None` if the task isn't scheduled.
+    pub fn stats(&self, id: T) -> Option<TaskStats> {
+        self.index_of(id)
+            .and_then(|index| self.tasks[index].map(|task| task.stats))
+    }
+
+    /// Iterates over the scheduled tasks and their run statistics.
+    pub fn task_stats(&self) -> impl Iterator<Item = (T, TaskStats)> + '_ {
+        self.tasks.iter().flatten().map(|task| (task.id, task.stats))
+    }
+
+    pub fn reset_stats(&mut self) {
+        for task in self.tasks.iter_mut().flatten() {
+            task.stats = TaskStats::default();
+        }
+    }
+
+    /// Starts the due task with the highest priority and returns it, or returns `None` if no
+    /// task is due. A periodic task's next due time is one period after this one, or one period
+    /// from now if the task has fallen a whole period behind. A one-shot task is removed.
+    pub fn start_next(&mut self) -> Option<T> {
+        let now = Instant::now();
+        let mut next: Option<(usize, u8, Instant)> = None;
+        for (index, task) in self.tasks.iter().enumerate() {
+            let task = match task {
+                Some(task) if !task.due.is_after(now) => task,
+                _ => continue,
+            };
+            let runs_first = match next {
+                None => true,
+                Some((_, priority, due)) => {
+                    task.priority > priority || (task.priority == priority && due.is_after(task.due))
+                }
+            };
+            if runs_first {
+                next = Some((index, task.priority, task.due));
+            }
+        }
+        let index = next?.0;
+
+        let slot = &mut self.tasks[index];
+        let task = slot.as_mut()?;
+        let id = task.id;
+        let start_micros = micros();
+        let jitter = start_micros.wrapping_sub(due_micros(task.due));
+        task.stats.runs = task.stats.runs.wrapping_add(1);
+        task.stats.total_jitter = task.stats.total_jitter.saturating_add(jitter);
+        task.stats.max_jitter = task.stats.max_jitter.max(jitter);
+        match task.kind {
+            TaskKind::Periodic => {
+                task.due += task.period;
+                if !task.due.is_after(now) {
+                    task.due = now + task.period;
+                }
+                self.running = Some(RunningTask {
+                    index,
+                    start_micros,
+                    next_due_micros: due_micros(task.due),
+                });
+            }
+            TaskKind::OneShot => {
+                *slot = None;
+                self.running = None;
+            }
+        }
+        Some(id)
+    }
+
+    /// Records the run time of a task returned by `start_next`. A run of a periodic task that
+    /// ends after the task's next due time counts as an overrun.
+    pub fn finish(&mut self, id: T) {
+        let running = match self.running.take() {
+            Some(running) => running,
+            None => return,
+        };
+        let end_micros = micros();
+        let run_time = end_micros.wrapping_sub(running.start_micros);
+        if let Some(task) = self.tasks[running.index].as_mut() {
+            if task.id != id {
+                return;
+            }
+            task.stats.max_run_time = task.stats.max_run_time.max(run_time);
+            if (end_micros.wrapping_sub(running.next_due_micros) as i32) > 0 {
+                task.stats.overruns = task.stats.overruns.wrapping_add(1);
+            }
+        }
+    }
+
+    fn index_of(&self, id: T) -> Option<usize> {
+        self.tasks
+            .iter()
+            .position(|task| matches!(task, Some(task) if task.id == id))
+    }
+
+    fn task_mut(&mut self, id: T) -> Option<&mut Task<T>> {
+        self.tasks
+            .iter_mut()
+            .flatten()
+            .find(|task| task.id == id)
+    }
+}
+
+/// Converts a due time to `micros()` time. Both counters start together, so the microsecond
+/// counter is the millisecond counter times 1000, wrapped around.
+fn due_micros(due: Instant) -> u32 {
+    due.as_millis().wrapping_mul(1000)
+}
+
+impl<T: Copy + PartialEq, const N: usize> Default for Scheduler<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::millis::{advance_micros, advance_millis, set_millis};
+
+    #[derive(Copy, Clone, PartialEq, Eq)]
+    enum TestTask {
+        Fast,
+        Slow,
+    }
+
+    /// Advances the clock to when `task` is due and starts it.
+    fn start_when_due(scheduler: &mut Scheduler<TestTask, 2>, task: TestTask) {
+        while scheduler.start_next() != Some(task) {
+            advance_millis(1);
+        }
+    }
+
+    #[test]
+    fn measures_jitter_in_micros() {
+        set_millis(1000);
+        let mut scheduler: Scheduler<TestTask, 2> = Scheduler::new();
+        scheduler
+            .add_periodic(TestTask::Fast, Duration::from_millis(10), 0)
+            .ok();
+
+        advance_micros(10_250);
+        assert!(scheduler.start_next() == Some(TestTask::Fast));
+        scheduler.finish(TestTask::Fast);
+        advance_micros(9_000);
+        assert!(scheduler.start_next().is_none());
+        advance_micros(1_750);
+        assert!(scheduler.start_next() == Some(TestTask::Fast));
+        scheduler.finish(TestTask::Fast);
+
+        let stats = scheduler.stats(TestTask::Fast).unwrap();
+        assert!(stats.runs == 2);
+        assert!(stats.max_jitter == 1000);
+        assert!(stats.mean_jitter() == 625);
+    }
+
+    #[test]
+    fn late_starts_are_not_overruns() {
+        set_millis(1000);
+        let mut scheduler: Scheduler<TestTask, 2> = Scheduler::new();
+        scheduler
+            .add_periodic(TestTask::Fast, Duration::from_millis(10), 0)
+            .ok();
+
+        // starts 15 ms late, so it's next due one period after it started, and runs for 5 ms
+        advance_millis(25);
+        assert!(scheduler.start_next() == Some(TestTask::Fast));
+        advance_millis(5);
+        scheduler.finish(TestTask::Fast);
+        assert!(scheduler.stats(TestTask::Fast).unwrap().overruns == 0);
+
+        advance_micros(500);
+        start_when_due(&mut scheduler, TestTask::Fast);
+        advance_millis(4);
+        scheduler.finish(TestTask::Fast);
+        assert!(scheduler.stats(TestTask::Fast).unwrap().overruns == 0);
+    }
+
+    #[test]
+    fn runs_past_the_next_due_time_are_overruns() {
+        set_millis(1000);
+        let mut scheduler: Scheduler<TestTask, 2> = Scheduler::new();
+        scheduler
+            .add_periodic(TestTask::Slow, Duration::from_millis(10), 0)
+            .ok();
+
+        start_when_due(&mut scheduler, TestTask::Slow);
+        advance_millis(11);
+        scheduler.finish(TestTask::Slow);
+        let stats = scheduler.stats(TestTask::Slow).unwrap();
+        assert!(stats.overruns == 1);
+        assert!(stats.max_run_time == 11_000);
+
+        // the task fell behind, so it's next due one period after it started
+        start_when_due(&mut scheduler, TestTask::Slow);
+        advance_millis(9);
+        scheduler.finish(TestTask::Slow);
+        assert!(scheduler.stats(TestTask::Slow).unwrap().overruns == 1);
+    }
+
+    #[test]
+    fn higher_priority_tasks_start_first() {
+        set_millis(1000);
+        let mut scheduler: Scheduler<TestTask, 2> = Scheduler::new();
+        scheduler
+            .add_periodic(TestTask::Slow, Duration::from_millis(10), 0)
+            .ok();
+        scheduler
+            .add_periodic(TestTask::Fast, Duration::from_millis(10), 1)
+            .ok();
+
+        advance_millis(10);
+        assert!(scheduler.start_next() == Some(TestTask::Fast));
+        scheduler.finish(TestTask::Fast);
+        assert!(scheduler.start_next() == Some(TestTask::Slow));
+        scheduler.finish(TestTask::Slow);
+        assert!(scheduler.start_next().is_none());
+    }
+}
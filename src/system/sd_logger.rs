@@ -0,0 +1,173 @@
+use core::convert::Infallible;
+
+use embedded_sdmmc::{BlockDevice, TimeSource, Timestamp};
+use ufmt::{uWrite, uwriteln};
+
+use super::run_files::{RunFileError, RunFiles};
+use crate::{
+    error, info,
+    telemetry::{
+        binary::{encode_frame, MAX_FRAME_SIZE},
+        recorder::{OverflowPolicy, RecordWriter, TelemetryRecorder, MAX_RECORD_SIZE},
+        telemetry_row::TelemetryRow,
+        write_telemetry_headers, TelemetryFormat, TelemetryLog, TelemetryStream,
+    },
+    warn,
+};
+
+/// The size of the queue of rows waiting to be written to the SD card, in bytes. It holds a few
+/// rows, which is enough for the rows logged between two runs of the write task.
+const SD_LOG_QUEUE_CAPACITY: usize = 384;
+
+/// The robot has no real time clock, so every file gets the same timestamp.
+pub struct FixedTimeSource;
+
+impl TimeSource for FixedTimeSource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 54,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// Logs telemetry tables to numbered files, `RUN00001.CSV` or `RUN00001.BIN`, in the root
+/// directory of a FAT formatted SD card. Each run gets the next unused number.
+///
+/// The files are written with `RunFiles`, which works with any `BlockDevice` and is tested with
+/// an in-memory one. If the device fails, the logger reports the error and stops logging.
+///
+/// Writing a block to the card takes a few milliseconds, so rows aren't written when they are
+/// logged. They are queued, like the console's telemetry, and written by `write_queued`, which
+/// the robot calls from a low priority task.
+pub struct SdLogger<D: BlockDevice, T: TimeSource> {
+    run_files: RunFiles<D, T>,
+    format: TelemetryFormat,
+    sequence: u16,
+    failed: bool,
+    queue: TelemetryRecorder<SD_LOG_QUEUE_CAPACITY>,
+}
+
+#[allow(dead_code)]
+impl<D: BlockDevice, T: TimeSource> SdLogger<D, T> {
+    /// Opens the first volume of `device` and finds the next unused run number. Returns `None`
+    /// if the device has no readable FAT volume, such as when there is no SD card.
+    pub fn new(device: D, time_source: T, format: TelemetryFormat) -> Option<Self> {
+        match RunFiles::open(device, time_source, Self::extension(format)) {
+            Ok(run_files) => Some(Self {
+                run_files,
+                format,
+                sequence: 0,
+                failed: false,
+                queue: TelemetryRecorder::new(OverflowPolicy::DropNewest),
+            }),
+            Err(_) => {
+                info!("No FAT volume found on the SD card");
+                None
+            }
+        }
+    }
+
+    /// The SD card, such as to re-clock its SPI bus once the card is initialized.
+    pub fn device(&mut self) -> Option<&mut D> {
+        self.run_files.device()
+    }
+
+    fn extension(format: TelemetryFormat) -> &'static [u8] {
+        match format {
+            TelemetryFormat::Csv => b"CSV",
+            TelemetryFormat::Binary => b"BIN",
+        }
+    }
+
+    fn fail(&mut self, operation: &str) {
+        error!("SD card {} failed, stopping the SD card log", operation);
+        self.failed = true;
+    }
+
+    fn append(&mut self, bytes: &[u8]) {
+        if !self.failed && self.run_files.append(bytes).is_err() {
+            self.fail("write");
+        }
+    }
+}
+
+impl<D: BlockDevice, T: TimeSource> uWrite for SdLogger<D, T> {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        self.append(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice, T: TimeSource> TelemetryLog for SdLogger<D, T> {
+    fn start_run(&mut self) {
+        if self.failed {
+            return;
+        }
+        match self.run_files.start_run() {
+            Ok(name) => {
+                let name = core::str::from_utf8(&name).unwrap_or_default();
+                info!("Logging the run to {} on the SD card", name);
+                self.sequence = 0;
+            }
+            Err(RunFileError::OutOfRunNumbers) => self.fail("run numbering"),
+            Err(RunFileError::FileSystem(_)) => self.fail("file creation"),
+        }
+    }
+
+    /// Writes the header row right away, after the rows still queued from the previous table.
+    fn log_headers<R: TelemetryRow>(&mut self, stream: TelemetryStream) {
+        self.write_queued();
+        let format = self.format;
+        let _ = write_telemetry_headers::<R, _>(self, stream, format);
+    }
+
+    fn log_row<R: TelemetryRow>(&mut self, stream: TelemetryStream, row: &R) {
+        if self.failed {
+            return;
+        }
+        match self.format {
+            TelemetryFormat::Csv => {
+                let mut record = RecordWriter::new();
+                if uwriteln!(record, "{}", row).is_ok() {
+                    self.queue.record(record.as_bytes());
+                }
+            }
+            TelemetryFormat::Binary => {
+                let mut frame = [0u8; MAX_FRAME_SIZE];
+                let length = encode_frame(stream as u8, self.sequence, row, &mut frame);
+                self.sequence = self.sequence.wrapping_add(1);
+                self.queue.record(&frame[..length]);
+            }
+        }
+    }
+
+    fn write_queued(&mut self) {
+        let mut record = [0u8; MAX_RECORD_SIZE];
+        while self.queue.next_record_length().is_some() {
+            let mut length = 0;
+            self.queue.pop_record(|byte| {
+                record[length] = byte;
+                length += 1;
+            });
+            self.append(&record[..length]);
+        }
+    }
+
+    fn end_run(&mut self) {
+        self.write_queued();
+        let dropped_rows = self.queue.take_dropped_records();
+        if dropped_rows > 0 {
+            warn!("SD card log dropped {} rows", dropped_rows);
+        }
+        if !self.failed && self.run_files.end_run().is_err() {
+            self.fail("file close");
+        }
+    }
+}
@@ -1,56 +1,209 @@
 // This code taken from the example code in the avr-hal crate, which is licensed under the MIT license:
 //      https://github.com/Rahix/avr-hal/blob/main/examples/arduino-uno/src/bin/uno-println.rs
 //
-use avr_device::interrupt;
-use core::cell::RefCell;
+// Modified so that output is queued in a transmit buffer and sent by the USART0 data register
+// empty interrupt, rather than written while holding off all interrupts. Received bytes are
+// queued in a receive buffer by the USART0 receive complete interrupt.
+//
+use arduino_hal::hal::usart::Event;
+use avr_device::interrupt::{self, CriticalSection};
+use core::{cell::RefCell, convert::Infallible};
+use ufmt::uWrite;
+
+use crate::system::ring_buffer::RingBuffer;
 
-type Console = arduino_hal::hal::usart::Usart0<arduino_hal::DefaultClock>;
+pub type Console = arduino_hal::hal::usart::Usart0<arduino_hal::DefaultClock>;
 pub static CONSOLE: interrupt::Mutex<RefCell<Option<Console>>> =
     interrupt::Mutex::new(RefCell::new(None));
 
+/// The size of the console's transmit buffer, in bytes. It must be able to hold the longest
+/// telemetry record.
+pub const TX_BUFFER_SIZE: usize = 256;
+static TX_BUFFER: interrupt::Mutex<RefCell<RingBuffer<TX_BUFFER_SIZE>>> =
+    interrupt::Mutex::new(RefCell::new(RingBuffer::new()));
+/// The size of the console's receive buffer, in bytes. Received bytes are dropped when it is
+/// full, such as while a long movement keeps the main loop from reading them.
+const RX_BUFFER_SIZE: usize = 64;
+static RX_BUFFER: interrupt::Mutex<RefCell<RingBuffer<RX_BUFFER_SIZE>>> =
+    interrupt::Mutex::new(RefCell::new(RingBuffer::new()));
+
 #[macro_export]
 macro_rules! print {
-    ($($t:tt)*) => {
-        avr_device::interrupt::free(
-            |cs| {
-                if let Some(console) = $crate::system::serial_print::CONSOLE.borrow(cs).borrow_mut().as_mut() {
-                    let _ = ufmt::uwrite!(console, $($t)*);
-                }
-            },
-        )
-    };
+    ($($t:tt)*) => {{
+        let mut writer = $crate::system::serial_print::ConsoleWriter::blocking();
+        let _ = ufmt::uwrite!(writer, $($t)*);
+    }};
 }
 
 #[macro_export]
 macro_rules! println {
-    ($($t:tt)*) => {
-        avr_device::interrupt::free(
-            |cs| {
-                if let Some(console) = $crate::system::serial_print::CONSOLE.borrow(cs).borrow_mut().as_mut() {
-                    let _ = ufmt::uwriteln!(console, $($t)*);
-                }
-            },
-        )
-    };
+    ($($t:tt)*) => {{
+        let mut writer = $crate::system::serial_print::ConsoleWriter::blocking();
+        let _ = ufmt::uwriteln!(writer, $($t)*);
+    }};
+}
+
+/// Like `print!`, but never waits for room in the transmit buffer. Returns the number of bytes
+/// that were dropped because the buffer was full.
+#[macro_export]
+macro_rules! try_print {
+    ($($t:tt)*) => {{
+        let mut writer = $crate::system::serial_print::ConsoleWriter::non_blocking();
+        let _ = ufmt::uwrite!(writer, $($t)*);
+        writer.dropped_bytes()
+    }};
+}
+
+/// Like `println!`, but never waits for room in the transmit buffer. Returns the number of bytes
+/// that were dropped because the buffer was full.
+#[macro_export]
+macro_rules! try_println {
+    ($($t:tt)*) => {{
+        let mut writer = $crate::system::serial_print::ConsoleWriter::non_blocking();
+        let _ = ufmt::uwriteln!(writer, $($t)*);
+        writer.dropped_bytes()
+    }};
 }
 
 #[macro_export]
 macro_rules! print_with_fn {
-    ($print_fn:expr) => {
-        avr_device::interrupt::free(|cs| {
-            if let Some(console) = $crate::system::serial_print::CONSOLE
-                .borrow(cs)
-                .borrow_mut()
-                .as_mut()
-            {
-                let _ = $print_fn(console);
-            }
-        })
-    };
+    ($print_fn:expr) => {{
+        let mut writer = $crate::system::serial_print::ConsoleWriter::blocking();
+        let _ = $print_fn(&mut writer);
+    }};
 }
 
-pub fn put_console(console: Console) {
+pub fn put_console(mut console: Console) {
+    console.listen(Event::RxComplete);
     interrupt::free(|cs| {
         *CONSOLE.borrow(cs).borrow_mut() = Some(console);
     })
 }
+
+/// Returns the next byte received by the console, if any.
+pub fn read_console_byte() -> Option<u8> {
+    interrupt::free(|cs| RX_BUFFER.borrow(cs).borrow_mut().pop())
+}
+
+/// Writes to the console through its transmit buffer.
+///
+/// A blocking writer waits for room in the buffer when it is full. If interrupts are disabled,
+/// such as before they are first enabled or inside a critical section, the interrupt can't empty
+/// the buffer, so the writer sends the buffered bytes itself. A non-blocking writer drops the
+/// bytes that don't fit and counts them instead.
+pub struct ConsoleWriter {
+    blocking: bool,
+    dropped_bytes: usize,
+}
+
+impl ConsoleWriter {
+    pub fn blocking() -> Self {
+        Self {
+            blocking: true,
+            dropped_bytes: 0,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn non_blocking() -> Self {
+        Self {
+            blocking: false,
+            dropped_bytes: 0,
+        }
+    }
+
+    /// The number of bytes dropped because the transmit buffer was full.
+    #[allow(dead_code)]
+    pub fn dropped_bytes(&self) -> usize {
+        self.dropped_bytes
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        loop {
+            if interrupt::free(|cs| queue_byte(cs, byte)) {
+                return;
+            }
+            if !self.blocking {
+                self.dropped_bytes += 1;
+                return;
+            }
+            if !interrupts_enabled() {
+                interrupt::free(send_buffered_byte);
+            }
+        }
+    }
+}
+
+impl uWrite for ConsoleWriter {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        for byte in s.as_bytes() {
+            self.write_byte(*byte);
+        }
+        Ok(())
+    }
+}
+
+/// Queues a byte to send. Returns false if the transmit buffer is full. Without a console, the
+/// byte is discarded.
+fn queue_byte(cs: CriticalSection, byte: u8) -> bool {
+    let mut queued = true;
+    fill_console_buffer(cs, |buffer| queued = buffer.push(byte));
+    queued
+}
+
+/// Gives `fill` the console's transmit buffer to queue bytes in, then starts sending them.
+/// `fill` isn't called if there is no console.
+pub fn fill_console_buffer<F>(cs: CriticalSection, fill: F)
+where
+    F: FnOnce(&mut RingBuffer<TX_BUFFER_SIZE>),
+{
+    if let Some(console) = CONSOLE.borrow(cs).borrow_mut().as_mut() {
+        let mut buffer = TX_BUFFER.borrow(cs).borrow_mut();
+        fill(&mut buffer);
+        if !buffer.is_empty() {
+            console.listen(Event::DataRegisterEmpty);
+        }
+    }
+}
+
+/// Waits until everything in the transmit buffer has been handed to the UART.
+pub fn flush_console() {
+    while !interrupt::free(|cs| TX_BUFFER.borrow(cs).borrow().is_empty()) {
+        if !interrupts_enabled() {
+            interrupt::free(send_buffered_byte);
+        }
+    }
+}
+
+/// Sends the next buffered byte, or stops the data register empty interrupt once the buffer is
+/// empty.
+fn send_buffered_byte(cs: CriticalSection) {
+    if let Some(console) = CONSOLE.borrow(cs).borrow_mut().as_mut() {
+        match TX_BUFFER.borrow(cs).borrow_mut().pop() {
+            Some(byte) => console.write_byte(byte),
+            None => console.unlisten(Event::DataRegisterEmpty),
+        }
+    }
+}
+
+fn interrupts_enabled() -> bool {
+    // SAFETY: reading the status register has no side effects
+    unsafe { (*arduino_hal::pac::CPU::ptr()).sreg.read().i().bit_is_set() }
+}
+
+#[avr_device::interrupt(atmega2560)]
+fn USART0_UDRE() {
+    interrupt::free(send_buffered_byte);
+}
+
+#[avr_device::interrupt(atmega2560)]
+fn USART0_RX() {
+    interrupt::free(|cs| {
+        if let Some(console) = CONSOLE.borrow(cs).borrow_mut().as_mut() {
+            let byte = console.read_byte();
+            RX_BUFFER.borrow(cs).borrow_mut().push(byte);
+        }
+    })
+}
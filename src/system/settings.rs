@@ -0,0 +1,191 @@
+use arduino_hal::Eeprom;
+
+use crate::model::{
+    motor_calibration::{MotorPowerRatios, COUNT_MOTOR_LR_POWER_RATIOS},
+    motor_characterization::{MotorCharacterization, Wheel, COUNT_CHARACTERIZATION_DUTIES},
+    pid_controller::PidGains,
+};
+
+// EEPROM layout
+//
+// The settings are stored in fixed slots. Each slot starts with a marker byte that indicates
+// whether the slot has been written. An erased EEPROM byte reads as 0xFF, so a fresh board
+// has no valid slots and the firmware defaults are used.
+//
+// ╔════════╦════════╦═══════════════════════╗
+// ║ OFFSET ║ LENGTH ║ CONTENTS              ║
+// ╠════════╬════════╬═══════════════════════╣
+// ║      0 ║      2 ║ settings magic        ║
+// ║      2 ║      1 ║ settings version      ║
+// ║     16 ║     13 ║ heading PID gains     ║
+// ║     32 ║     13 ║ (unused)              ║
+// ║     48 ║     61 ║ L/R power ratio table ║
+// ║    112 ║     69 ║ motor speed curves    ║
+// ╚════════╩════════╩═══════════════════════╝
+const SETTINGS_MAGIC: u16 = 0x5252;
+const SETTINGS_VERSION: u8 = 1;
+const SETTINGS_MAGIC_OFFSET: u16 = 0;
+const SETTINGS_VERSION_OFFSET: u16 = 2;
+
+const SLOT_VALID_MARKER: u8 = 0xA5;
+
+const HEADING_PID_GAINS_OFFSET: u16 = 16;
+const MOTOR_POWER_RATIOS_OFFSET: u16 = 48;
+const MOTOR_CHARACTERIZATION_OFFSET: u16 = 112;
+
+const PID_GAINS_SIZE: usize = 12;
+const MOTOR_POWER_RATIO_SIZE: usize = 5;
+const MOTOR_POWER_RATIOS_SIZE: usize = COUNT_MOTOR_LR_POWER_RATIOS * MOTOR_POWER_RATIO_SIZE;
+const MOTOR_SPEED_CURVE_SIZE: usize = 2 + 4 * COUNT_CHARACTERIZATION_DUTIES;
+const MOTOR_CHARACTERIZATION_SIZE: usize = 2 * MOTOR_SPEED_CURVE_SIZE;
+
+/// Settings that persist across power cycles. The settings are stored in the Mega 2560's EEPROM.
+pub struct PersistentSettings {
+    eeprom: Eeprom,
+}
+
+#[allow(dead_code)]
+impl PersistentSettings {
+    pub fn new(eeprom: Eeprom) -> Self {
+        let mut settings = Self { eeprom };
+        if !settings.is_initialized() {
+            settings.initialize();
+        }
+        settings
+    }
+
+    /// Returns true if the EEPROM contains settings written by this firmware.
+    pub fn is_initialized(&self) -> bool {
+        let mut magic = [0u8; 2];
+        if self.eeprom.read(SETTINGS_MAGIC_OFFSET, &mut magic).is_err() {
+            return false;
+        }
+        u16::from_le_bytes(magic) == SETTINGS_MAGIC
+            && self.eeprom.read_byte(SETTINGS_VERSION_OFFSET) == SETTINGS_VERSION
+    }
+
+    /// Clears all settings slots and writes a fresh settings header.
+    pub fn initialize(&mut self) {
+        self.invalidate_slot(HEADING_PID_GAINS_OFFSET);
+        self.invalidate_slot(MOTOR_POWER_RATIOS_OFFSET);
+        self.invalidate_slot(MOTOR_CHARACTERIZATION_OFFSET);
+        self.eeprom
+            .write(SETTINGS_MAGIC_OFFSET, &SETTINGS_MAGIC.to_le_bytes())
+            .ok();
+        self.eeprom.write_byte(SETTINGS_VERSION_OFFSET, SETTINGS_VERSION);
+    }
+
+    /// Returns the saved heading PID gains, if any have been saved.
+    pub fn heading_pid_gains(&self) -> Option<PidGains> {
+        self.read_pid_gains(HEADING_PID_GAINS_OFFSET)
+    }
+
+    pub fn save_heading_pid_gains(&mut self, gains: &PidGains) {
+        self.write_pid_gains(HEADING_PID_GAINS_OFFSET, gains);
+    }
+
+    /// Returns the saved L/R motor power ratio table, if one has been saved.
+    pub fn motor_power_ratios(&self) -> Option<MotorPowerRatios> {
+        let mut data = [0u8; MOTOR_POWER_RATIOS_SIZE];
+        self.read_slot(MOTOR_POWER_RATIOS_OFFSET, &mut data)?;
+        let mut ratios = [(0u8, 0f32); COUNT_MOTOR_LR_POWER_RATIOS];
+        for (i, ratio) in ratios.iter_mut().enumerate() {
+            let index = i * MOTOR_POWER_RATIO_SIZE;
+            *ratio = (data[index], read_f32(&data, index + 1));
+        }
+        Some(MotorPowerRatios::new(ratios))
+    }
+
+    pub fn save_motor_power_ratios(&mut self, ratios: &MotorPowerRatios) {
+        let mut data = [0u8; MOTOR_POWER_RATIOS_SIZE];
+        for (i, ratio) in ratios.ratios().iter().enumerate() {
+            let index = i * MOTOR_POWER_RATIO_SIZE;
+            data[index] = ratio.0;
+            write_f32(&mut data, index + 1, ratio.1);
+        }
+        self.write_slot(MOTOR_POWER_RATIOS_OFFSET, &data);
+    }
+
+    /// Returns the saved motor duty-to-speed characterization, if one has been saved.
+    pub fn motor_characterization(&self) -> Option<MotorCharacterization> {
+        let mut data = [0u8; MOTOR_CHARACTERIZATION_SIZE];
+        self.read_slot(MOTOR_CHARACTERIZATION_OFFSET, &mut data)?;
+        let mut characterization = MotorCharacterization::default();
+        for (i, wheel) in [Wheel::Left, Wheel::Right].iter().enumerate() {
+            let index = i * MOTOR_SPEED_CURVE_SIZE;
+            let curve = characterization.curve_mut(*wheel);
+            curve.start_duty = data[index];
+            curve.stall_duty = data[index + 1];
+            for (j, speed) in curve.speeds.iter_mut().enumerate() {
+                *speed = read_f32(&data, index + 2 + 4 * j);
+            }
+        }
+        Some(characterization)
+    }
+
+    pub fn save_motor_characterization(&mut self, characterization: &MotorCharacterization) {
+        let mut data = [0u8; MOTOR_CHARACTERIZATION_SIZE];
+        for (i, wheel) in [Wheel::Left, Wheel::Right].iter().enumerate() {
+            let index = i * MOTOR_SPEED_CURVE_SIZE;
+            let curve = characterization.curve(*wheel);
+            data[index] = curve.start_duty;
+            data[index + 1] = curve.stall_duty;
+            for (j, speed) in curve.speeds.iter().enumerate() {
+                write_f32(&mut data, index + 2 + 4 * j, *speed);
+            }
+        }
+        self.write_slot(MOTOR_CHARACTERIZATION_OFFSET, &data);
+    }
+
+    fn read_pid_gains(&self, offset: u16) -> Option<PidGains> {
+        let mut data = [0u8; PID_GAINS_SIZE];
+        self.read_slot(offset, &mut data)?;
+        Some(PidGains::new(
+            read_f32(&data, 0),
+            read_f32(&data, 4),
+            read_f32(&data, 8),
+        ))
+    }
+
+    fn write_pid_gains(&mut self, offset: u16, gains: &PidGains) {
+        let mut data = [0u8; PID_GAINS_SIZE];
+        write_f32(&mut data, 0, gains.kp);
+        write_f32(&mut data, 4, gains.ki);
+        write_f32(&mut data, 8, gains.kd);
+        self.write_slot(offset, &data);
+    }
+
+    /// Reads a slot's data into `data`. Returns `None` if the slot has never been written.
+    fn read_slot(&self, offset: u16, data: &mut [u8]) -> Option<()> {
+        if self.eeprom.read_byte(offset) != SLOT_VALID_MARKER {
+            return None;
+        }
+        self.eeprom.read(offset + 1, data).ok()
+    }
+
+    fn write_slot(&mut self, offset: u16, data: &[u8]) {
+        // invalidate the slot first so that a power loss part way through the write
+        // doesn't leave a valid marker in front of partially written data
+        self.invalidate_slot(offset);
+        if self.eeprom.write(offset + 1, data).is_ok() {
+            self.eeprom.write_byte(offset, SLOT_VALID_MARKER);
+        }
+    }
+
+    fn invalidate_slot(&mut self, offset: u16) {
+        self.eeprom.erase_byte(offset);
+    }
+}
+
+fn read_f32(data: &[u8], index: usize) -> f32 {
+    f32::from_le_bytes([
+        data[index],
+        data[index + 1],
+        data[index + 2],
+        data[index + 3],
+    ])
+}
+
+fn write_f32(data: &mut [u8], index: usize, value: f32) {
+    data[index..index + 4].copy_from_slice(&value.to_le_bytes());
+}
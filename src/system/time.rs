@@ -0,0 +1,277 @@
+/*!
+ * Time types on top of `millis()`.
+ *
+ * The millisecond counter is a `u32`, so it wraps around after about 49.7 days. An `Instant` is
+ * a reading of the counter and a `Duration` is the number of milliseconds between two readings.
+ * The difference between two instants is computed with wrapping arithmetic, so it is correct
+ * across a wraparound as long as the instants are less than about 24.8 days apart. Arithmetic
+ * on durations saturates instead, so a duration that overflows is clamped to the longest one.
+ */
+use core::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
+use ufmt::{uDisplay, uWrite, uwrite, Formatter};
+
+use super::millis::millis;
+
+/// A span of time in milliseconds.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration(u32);
+
+#[allow(dead_code)]
+impl Duration {
+    pub const ZERO: Duration = Duration(0);
+
+    pub const fn from_millis(millis: u32) -> Self {
+        Self(millis)
+    }
+
+    pub const fn from_secs(secs: u32) -> Self {
+        Self(secs.saturating_mul(1000))
+    }
+
+    pub const fn as_millis(&self) -> u32 {
+        self.0
+    }
+
+    /// The duration in microseconds, for comparing with differences between `micros()` readings.
+    pub const fn as_micros(&self) -> u32 {
+        self.0.saturating_mul(1000)
+    }
+
+    pub fn as_secs_f32(&self) -> f32 {
+        self.0 as f32 / 1000.0
+    }
+
+    pub const fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration(self.0.saturating_add(other.0))
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, other: Duration) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, other: Duration) -> Duration {
+        Duration(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Mul<u32> for Duration {
+    type Output = Duration;
+
+    fn mul(self, factor: u32) -> Duration {
+        Duration(self.0.saturating_mul(factor))
+    }
+}
+
+impl Div<u32> for Duration {
+    type Output = Duration;
+
+    fn div(self, divisor: u32) -> Duration {
+        Duration(self.0 / divisor)
+    }
+}
+
+impl uDisplay for Duration {
+    fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uwrite!(f, "{} ms", self.0)
+    }
+}
+
+/// A point in time, as read from the millisecond counter.
+///
+/// Instants are compared by the sign of their wrapping difference rather than by their counter
+/// values, so an instant taken just after the counter wraps is still later than one taken just
+/// before it.
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub struct Instant(u32);
+
+#[allow(dead_code)]
+impl Instant {
+    /// The current time.
+    pub fn now() -> Self {
+        Self(millis())
+    }
+
+    pub const fn from_millis(millis: u32) -> Self {
+        Self(millis)
+    }
+
+    /// The raw counter value in milliseconds. Use it for timestamps in logs and telemetry.
+    pub const fn as_millis(&self) -> u32 {
+        self.0
+    }
+
+    /// The time from `earlier` to this instant. It is zero if `earlier` is actually later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        if earlier.is_after(*self) {
+            Duration::ZERO
+        } else {
+            Duration(self.0.wrapping_sub(earlier.0))
+        }
+    }
+
+    /// The time since this instant.
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// Returns true once at least `duration` has passed since this instant.
+    pub fn has_elapsed(&self, duration: Duration) -> bool {
+        self.elapsed() >= duration
+    }
+
+    /// Returns true if this instant is later than `other`.
+    pub fn is_after(&self, other: Instant) -> bool {
+        (self.0.wrapping_sub(other.0) as i32) > 0
+    }
+
+    /// Returns the deadline `duration` from now.
+    pub fn after(duration: Duration) -> Instant {
+        Instant::now() + duration
+    }
+
+    /// Returns true once this instant, used as a deadline, has been reached.
+    pub fn has_passed(&self) -> bool {
+        !self.is_after(Instant::now())
+    }
+
+    /// The time left until this instant, used as a deadline. It is zero once the deadline has
+    /// passed.
+    pub fn remaining(&self) -> Duration {
+        self.duration_since(Instant::now())
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(self.0.wrapping_add(duration.0))
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        Instant(self.0.wrapping_sub(duration.0))
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+impl uDisplay for Instant {
+    fn fmt<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uwrite!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::millis::{advance_millis, set_millis};
+
+    /// The counter value 10 ms before it wraps around.
+    const BEFORE_WRAP: u32 = u32::MAX - 9;
+
+    #[test]
+    fn duration_since_spans_the_wraparound() {
+        let earlier = Instant::from_millis(BEFORE_WRAP);
+        let later = Instant::from_millis(20);
+        assert!(later.duration_since(earlier) == Duration::from_millis(30));
+        assert!(later - earlier == Duration::from_millis(30));
+        // an earlier instant is zero time after a later one
+        assert!(earlier.duration_since(later).is_zero());
+    }
+
+    #[test]
+    fn is_after_spans_the_wraparound() {
+        let earlier = Instant::from_millis(BEFORE_WRAP);
+        let later = earlier + Duration::from_millis(30);
+        assert!(later.as_millis() == 20);
+        assert!(later.is_after(earlier));
+        assert!(!earlier.is_after(later));
+        assert!(!later.is_after(later));
+    }
+
+    #[test]
+    fn has_elapsed_spans_the_wraparound() {
+        set_millis(BEFORE_WRAP);
+        let start = Instant::now();
+        advance_millis(25);
+        assert!(start.elapsed() == Duration::from_millis(25));
+        assert!(start.has_elapsed(Duration::from_millis(25)));
+        assert!(!start.has_elapsed(Duration::from_millis(26)));
+    }
+
+    #[test]
+    fn deadlines_span_the_wraparound() {
+        set_millis(BEFORE_WRAP);
+        let deadline = Instant::after(Duration::from_millis(30));
+        assert!(!deadline.has_passed());
+        assert!(deadline.remaining() == Duration::from_millis(30));
+
+        advance_millis(20);
+        assert!(!deadline.has_passed());
+        assert!(deadline.remaining() == Duration::from_millis(10));
+
+        advance_millis(10);
+        assert!(deadline.has_passed());
+        assert!(deadline.remaining().is_zero());
+
+        advance_millis(10);
+        assert!(deadline.has_passed());
+        assert!(deadline.remaining().is_zero());
+    }
+
+    #[test]
+    fn duration_arithmetic_saturates() {
+        let max = Duration::from_millis(u32::MAX);
+        assert!(max + Duration::from_millis(1) == max);
+        assert!(Duration::from_millis(5) - Duration::from_millis(10) == Duration::ZERO);
+        assert!(Duration::from_millis(u32::MAX / 2) * 3 == max);
+        assert!(Duration::from_secs(u32::MAX) == max);
+        assert!(Duration::from_secs(2) * 3 / 4 == Duration::from_millis(1500));
+
+        let mut total = Duration::from_millis(u32::MAX - 1);
+        total += Duration::from_millis(2);
+        assert!(total == max);
+    }
+}
@@ -0,0 +1 @@
+pub mod motor_controller;
@@ -0,0 +1,156 @@
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::PwmPin;
+
+use crate::motor_driver::{DirectionPwmPins, DualMotorDriver, MotorChannel};
+
+/// Controls two motors through a TB6612FNG dual H-bridge. Each motor has two direction inputs and
+/// a PWM input, and the driver has a standby pin shared by both motors. The driver is taken out of
+/// standby when the controller is created.
+pub struct MotorController<AIN1, AIN2, BIN1, BIN2, PWMA, PWMB, STBY> {
+    channel_a: MotorChannel<DirectionPwmPins<AIN1, AIN2, PWMA>>,
+    channel_b: MotorChannel<DirectionPwmPins<BIN1, BIN2, PWMB>>,
+    stby: STBY,
+}
+
+#[allow(dead_code)]
+impl<AIN1, AIN2, BIN1, BIN2, PWMA, PWMB, STBY>
+    MotorController<AIN1, AIN2, BIN1, BIN2, PWMA, PWMB, STBY>
+where
+    AIN1: OutputPin,
+    AIN2: OutputPin,
+    BIN1: OutputPin,
+    BIN2: OutputPin,
+    PWMA: PwmPin<Duty = u8>,
+    PWMB: PwmPin<Duty = u8>,
+    STBY: OutputPin,
+{
+    pub fn new(
+        ain1: AIN1,
+        ain2: AIN2,
+        bin1: BIN1,
+        bin2: BIN2,
+        pwma: PWMA,
+        pwmb: PWMB,
+        stby: STBY,
+    ) -> Self {
+        let mut controller = Self {
+            channel_a: MotorChannel::new(DirectionPwmPins::new(ain1, ain2, pwma)),
+            channel_b: MotorChannel::new(DirectionPwmPins::new(bin1, bin2, pwmb)),
+            stby,
+        };
+        controller.wake();
+        controller
+    }
+
+    /// Puts the driver in its low power standby mode. Both motors coast.
+    pub fn standby(&mut self) {
+        self.stby.set_low().ok();
+    }
+
+    /// Takes the driver out of standby mode.
+    pub fn wake(&mut self) {
+        self.stby.set_high().ok();
+    }
+}
+
+impl<AIN1, AIN2, BIN1, BIN2, PWMA, PWMB, STBY> DualMotorDriver
+    for MotorController<AIN1, AIN2, BIN1, BIN2, PWMA, PWMB, STBY>
+where
+    AIN1: OutputPin,
+    AIN2: OutputPin,
+    BIN1: OutputPin,
+    BIN2: OutputPin,
+    PWMA: PwmPin<Duty = u8>,
+    PWMB: PwmPin<Duty = u8>,
+    STBY: OutputPin,
+{
+    type PinsA = DirectionPwmPins<AIN1, AIN2, PWMA>;
+    type PinsB = DirectionPwmPins<BIN1, BIN2, PWMB>;
+
+    fn channel_a(&self) -> &MotorChannel<Self::PinsA> {
+        &self.channel_a
+    }
+
+    fn channel_a_mut(&mut self) -> &mut MotorChannel<Self::PinsA> {
+        &mut self.channel_a
+    }
+
+    fn channel_b(&self) -> &MotorChannel<Self::PinsB> {
+        &self.channel_b
+    }
+
+    fn channel_b_mut(&mut self) -> &mut MotorChannel<Self::PinsB> {
+        &mut self.channel_b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motor_driver::mock::{MockOutputPin, MockPwmPin, PinTransaction as T, State};
+    use crate::system::time::Instant;
+
+    #[test]
+    fn standby_pin_is_released_on_creation() {
+        let mut stby =
+            MockOutputPin::new(&[T::set(State::High), T::set(State::Low), T::set(State::High)]);
+        let mut inputs = [
+            MockOutputPin::new(&[]),
+            MockOutputPin::new(&[]),
+            MockOutputPin::new(&[]),
+            MockOutputPin::new(&[]),
+        ];
+        let mut pwm_inputs = [MockPwmPin::new(&[]), MockPwmPin::new(&[])];
+        let mut motors = MotorController::new(
+            inputs[0].clone(),
+            inputs[1].clone(),
+            inputs[2].clone(),
+            inputs[3].clone(),
+            pwm_inputs[0].clone(),
+            pwm_inputs[1].clone(),
+            stby.clone(),
+        );
+        motors.standby();
+        motors.wake();
+
+        stby.done();
+        inputs.iter_mut().for_each(|pin| pin.done());
+        pwm_inputs.iter_mut().for_each(|pin| pin.done());
+    }
+
+    #[test]
+    fn signed_speed_drives_the_inputs_and_pwm_input() {
+        let mut ain1 = MockOutputPin::new(&[T::set(State::Low), T::set(State::High)]);
+        let mut ain2 = MockOutputPin::new(&[T::set(State::High), T::set(State::High)]);
+        let mut pwma = MockPwmPin::new(&[
+            T::get_max_duty(255),
+            T::set_duty(255),
+            T::enable(),
+            T::get_max_duty(255),
+            T::set_duty(255),
+            T::enable(),
+        ]);
+        let mut bin1 = MockOutputPin::new(&[]);
+        let mut bin2 = MockOutputPin::new(&[]);
+        let mut pwmb = MockPwmPin::new(&[]);
+        let mut stby = MockOutputPin::new(&[T::set(State::High)]);
+        let mut motors = MotorController::new(
+            ain1.clone(),
+            ain2.clone(),
+            bin1.clone(),
+            bin2.clone(),
+            pwma.clone(),
+            pwmb.clone(),
+            stby.clone(),
+        );
+        // the speed saturates at the max duty
+        motors.set_speed_a(-300);
+        motors.brake_a(Instant::from_millis(20));
+
+        for pin in [&mut ain1, &mut ain2, &mut bin1, &mut bin2, &mut stby] {
+            pin.done();
+        }
+        pwma.done();
+        pwmb.done();
+    }
+}
@@ -0,0 +1,214 @@
+use super::telemetry_row::TelemetryRow;
+
+/// The largest binary row record that can be framed, in bytes.
+pub const MAX_ROW_SIZE: usize = 64;
+/// The size of a record's header: the stream ID and the sequence number.
+const RECORD_HEADER_SIZE: usize = 3;
+const CRC_SIZE: usize = 2;
+const MAX_RECORD_SIZE: usize = RECORD_HEADER_SIZE + MAX_ROW_SIZE + CRC_SIZE;
+/// The largest encoded frame: the record plus the COBS overhead byte and the zero delimiter.
+/// Records are shorter than 254 bytes, so COBS adds exactly one byte.
+pub const MAX_FRAME_SIZE: usize = MAX_RECORD_SIZE + 2;
+
+/// Calculates the CRC-16/CCITT-FALSE checksum of `data`.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+/// Encodes `input` with Consistent Overhead Byte Stuffing into `output`, so that the encoded data
+/// contains no zero bytes, and returns the encoded length. The zero frame delimiter is not
+/// written. `output` must be at least `input.len() + input.len() / 254 + 1` bytes long.
+pub fn cobs_encode(input: &[u8], output: &mut [u8]) -> usize {
+    let mut code_index = 0;
+    let mut code: u8 = 1;
+    let mut write_index = 1;
+    for byte in input {
+        if *byte == 0 {
+            output[code_index] = code;
+            code_index = write_index;
+            write_index += 1;
+            code = 1;
+        } else {
+            output[write_index] = *byte;
+            write_index += 1;
+            code += 1;
+            if code == 0xFF {
+                output[code_index] = code;
+                code_index = write_index;
+                write_index += 1;
+                code = 1;
+            }
+        }
+    }
+    output[code_index] = code;
+    write_index
+}
+
+/// Encodes `row` as a binary telemetry frame into `frame` and returns the frame length.
+///
+/// The record is the stream ID, the little-endian sequence number, the row's binary record and
+/// the little-endian CRC16 of everything before it. The record is COBS encoded and terminated
+/// by a zero byte. Panics if the row's binary record is larger than `MAX_ROW_SIZE`.
+pub fn encode_frame<R: TelemetryRow>(
+    stream_id: u8,
+    sequence: u16,
+    row: &R,
+    frame: &mut [u8; MAX_FRAME_SIZE],
+) -> usize {
+    let mut record = [0u8; MAX_RECORD_SIZE];
+    record[0] = stream_id;
+    record[1..RECORD_HEADER_SIZE].copy_from_slice(&sequence.to_le_bytes());
+    let mut length = RECORD_HEADER_SIZE;
+    length += row.write_binary(&mut record[length..length + MAX_ROW_SIZE]);
+    let crc = crc16(&record[..length]);
+    record[length..length + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+    length += CRC_SIZE;
+
+    let frame_length = cobs_encode(&record[..length], frame);
+    frame[frame_length] = 0;
+    frame_length + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row with a timestamp, a heading and a duty, so its record has zero bytes to stuff.
+    struct TestRow {
+        timestamp: u32,
+        heading: f32,
+        duty: u8,
+    }
+
+    impl TelemetryRow for TestRow {
+        const BINARY_SIZE: usize = 9;
+
+        fn write_binary(&self, buf: &mut [u8]) -> usize {
+            buf[0..4].copy_from_slice(&self.timestamp.to_le_bytes());
+            buf[4..8].copy_from_slice(&self.heading.to_le_bytes());
+            buf[8] = self.duty;
+            Self::BINARY_SIZE
+        }
+    }
+
+    /// The frame of `TestRow { timestamp: 1000, heading: 1.5, duty: 0 }` as stream 1 with
+    /// sequence number 258. The telemetry decoder's tests decode the same frame.
+    const TEST_FRAME: [u8; 16] = [
+        0x06, 0x01, 0x02, 0x01, 0xE8, 0x03, 0x01, 0x01, 0x01, 0x03, 0xC0, 0x3F, 0x03, 0x3B, 0xDD,
+        0x00,
+    ];
+
+    /// Decodes COBS encoded `input`, without the zero delimiter, into `output` and returns the
+    /// decoded length.
+    fn cobs_decode(input: &[u8], output: &mut [u8]) -> usize {
+        let mut read_index = 0;
+        let mut write_index = 0;
+        while read_index < input.len() {
+            let code = input[read_index] as usize;
+            assert!(code != 0 && read_index + code <= input.len());
+            for byte in &input[read_index + 1..read_index + code] {
+                output[write_index] = *byte;
+                write_index += 1;
+            }
+            read_index += code;
+            if code < 0xFF && read_index < input.len() {
+                output[write_index] = 0;
+                write_index += 1;
+            }
+        }
+        write_index
+    }
+
+    fn encode(input: &[u8], output: &mut [u8]) -> usize {
+        let length = cobs_encode(input, output);
+        assert!(!output[..length].contains(&0));
+        length
+    }
+
+    #[test]
+    fn crc16_matches_the_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn cobs_replaces_zero_bytes() {
+        let mut output = [0u8; 8];
+        assert_eq!(encode(&[], &mut output), 1);
+        assert_eq!(output[..1], [0x01]);
+        assert_eq!(encode(&[0x00], &mut output), 2);
+        assert_eq!(output[..2], [0x01, 0x01]);
+        assert_eq!(encode(&[0x00, 0x00], &mut output), 3);
+        assert_eq!(output[..3], [0x01, 0x01, 0x01]);
+        assert_eq!(encode(&[0x11, 0x22, 0x00, 0x33], &mut output), 5);
+        assert_eq!(output[..5], [0x03, 0x11, 0x22, 0x02, 0x33]);
+        assert_eq!(encode(&[0x11, 0x00], &mut output), 3);
+        assert_eq!(output[..3], [0x02, 0x11, 0x01]);
+    }
+
+    #[test]
+    fn cobs_splits_runs_of_254_non_zero_bytes() {
+        let mut input = [0u8; 255];
+        for (i, byte) in input.iter_mut().enumerate() {
+            *byte = (i % 255) as u8 + 1;
+        }
+        let mut output = [0u8; 258];
+        let mut decoded = [0u8; 255];
+
+        // a full run ends with a 0xFF code, which is followed by an empty block
+        let length = encode(&input[..254], &mut output);
+        assert_eq!(length, 256);
+        assert_eq!(output[0], 0xFF);
+        assert_eq!(output[1..255], input[..254]);
+        assert_eq!(output[255], 0x01);
+        assert_eq!(cobs_decode(&output[..length], &mut decoded), 254);
+        assert_eq!(decoded[..254], input[..254]);
+
+        let length = encode(&input, &mut output);
+        assert_eq!(length, 257);
+        assert_eq!(output[0], 0xFF);
+        assert_eq!(output[255..257], [0x02, 0xFF]);
+        assert_eq!(cobs_decode(&output[..length], &mut decoded), 255);
+        assert_eq!(decoded, input);
+    }
+
+    #[test]
+    fn encode_frame_round_trips() {
+        let row = TestRow {
+            timestamp: 1000,
+            heading: 1.5,
+            duty: 0,
+        };
+        let mut frame = [0xAAu8; MAX_FRAME_SIZE];
+        let length = encode_frame(1, 258, &row, &mut frame);
+        assert_eq!(frame[..length], TEST_FRAME);
+
+        // only the delimiter is zero
+        assert!(!frame[..length - 1].contains(&0));
+        assert_eq!(frame[length - 1], 0);
+        let mut record = [0u8; MAX_FRAME_SIZE];
+        let record_length = cobs_decode(&frame[..length - 1], &mut record);
+        assert_eq!(
+            record_length,
+            RECORD_HEADER_SIZE + TestRow::BINARY_SIZE + CRC_SIZE
+        );
+        let (body, crc) = record[..record_length].split_at(record_length - CRC_SIZE);
+        assert_eq!(crc16(body), u16::from_le_bytes([crc[0], crc[1]]));
+        assert_eq!(body[0], 1);
+        assert_eq!(u16::from_le_bytes([body[1], body[2]]), 258);
+        assert_eq!(body[3..7], 1000u32.to_le_bytes());
+        assert_eq!(body[7..11], 1.5f32.to_le_bytes());
+        assert_eq!(body[11], 0);
+    }
+}
@@ -0,0 +1,278 @@
+pub mod binary;
+pub mod recorder;
+pub mod telemetry_row;
+
+use avr_device::interrupt::{self, CriticalSection, Mutex};
+use core::cell::{Cell, RefCell};
+use ufmt::{uWrite, uwrite, uwriteln};
+
+use self::{
+    binary::{encode_frame, MAX_FRAME_SIZE},
+    recorder::{OverflowPolicy, RecordWriter, TelemetryRecorder, MAX_RECORD_SIZE},
+    telemetry_row::TelemetryRow,
+};
+use crate::{
+    model::motion_monitor::MotionError,
+    print_with_fn,
+    system::{
+        data_logging::log_csv_headers,
+        serial_print::{fill_console_buffer, flush_console, TX_BUFFER_SIZE},
+    },
+};
+
+// a record is moved to the console's transmit buffer in one piece, so it must fit
+const _: () = assert!(MAX_RECORD_SIZE <= TX_BUFFER_SIZE);
+/// The size of the console's telemetry ring buffer, in bytes. It holds a few rows, which is
+/// enough to smooth out the bursts of a control loop iteration.
+const TELEMETRY_RECORDER_CAPACITY: usize = 512;
+const TELEMETRY_OVERFLOW_POLICY: OverflowPolicy = OverflowPolicy::DropNewest;
+
+/// How telemetry rows are written to the console.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TelemetryFormat {
+    /// Each row is a line of comma separated values.
+    Csv,
+    /// Each row is a COBS encoded binary frame. See `binary::encode_frame`.
+    Binary,
+}
+
+/// Identifies the table a binary telemetry frame belongs to.
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TelemetryStream {
+    ForwardMovement = 1,
+    RelayAutoTune = 2,
+    MotionDiagnostic = 3,
+    MotorCalibration = 4,
+    MotorCharacterization = 5,
+}
+
+#[cfg(not(feature = "binary_telemetry"))]
+const DEFAULT_TELEMETRY_FORMAT: TelemetryFormat = TelemetryFormat::Csv;
+#[cfg(feature = "binary_telemetry")]
+const DEFAULT_TELEMETRY_FORMAT: TelemetryFormat = TelemetryFormat::Binary;
+
+static TELEMETRY_FORMAT: Mutex<Cell<TelemetryFormat>> =
+    Mutex::new(Cell::new(DEFAULT_TELEMETRY_FORMAT));
+static TELEMETRY_SEQUENCE: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
+static TELEMETRY_RECORDER: Mutex<RefCell<TelemetryRecorder<TELEMETRY_RECORDER_CAPACITY>>> =
+    Mutex::new(RefCell::new(TelemetryRecorder::new(TELEMETRY_OVERFLOW_POLICY)));
+
+#[allow(dead_code)]
+pub fn set_telemetry_format(format: TelemetryFormat) {
+    interrupt::free(|cs| TELEMETRY_FORMAT.borrow(cs).set(format));
+}
+
+pub fn telemetry_format() -> TelemetryFormat {
+    interrupt::free(|cs| TELEMETRY_FORMAT.borrow(cs).get())
+}
+
+/// A destination for telemetry tables besides the console, such as an SD card. Each run, such as
+/// a movement, is logged separately.
+pub trait TelemetryLog {
+    /// Starts logging a new run.
+    fn start_run(&mut self);
+
+    fn log_headers<R: TelemetryRow>(&mut self, stream: TelemetryStream);
+
+    /// Queues a row to be written by `write_queued`.
+    fn log_row<R: TelemetryRow>(&mut self, stream: TelemetryStream, row: &R);
+
+    /// Writes the queued rows. Called from a low priority task, so that slow writes don't
+    /// delay the control loop.
+    fn write_queued(&mut self);
+
+    /// Writes out everything logged for the run and closes it.
+    fn end_run(&mut self);
+}
+
+/// `None` logs nothing, such as when there is no SD card.
+impl<L: TelemetryLog> TelemetryLog for Option<L> {
+    fn start_run(&mut self) {
+        if let Some(log) = self {
+            log.start_run();
+        }
+    }
+
+    fn log_headers<R: TelemetryRow>(&mut self, stream: TelemetryStream) {
+        if let Some(log) = self {
+            log.log_headers::<R>(stream);
+        }
+    }
+
+    fn log_row<R: TelemetryRow>(&mut self, stream: TelemetryStream, row: &R) {
+        if let Some(log) = self {
+            log.log_row(stream, row);
+        }
+    }
+
+    fn write_queued(&mut self) {
+        if let Some(log) = self {
+            log.write_queued();
+        }
+    }
+
+    fn end_run(&mut self) {
+        if let Some(log) = self {
+            log.end_run();
+        }
+    }
+}
+
+/// Queues a telemetry record to be sent to the console. Returns false if it was dropped.
+pub fn record_telemetry(record: &[u8]) -> bool {
+    interrupt::free(|cs| TELEMETRY_RECORDER.borrow(cs).borrow_mut().record(record))
+}
+
+#[allow(dead_code)]
+pub fn set_telemetry_overflow_policy(policy: OverflowPolicy) {
+    interrupt::free(|cs| TELEMETRY_RECORDER.borrow(cs).borrow_mut().set_policy(policy));
+}
+
+/// Returns the number of telemetry records dropped since the last call.
+pub fn take_dropped_telemetry_records() -> u16 {
+    interrupt::free(|cs| TELEMETRY_RECORDER.borrow(cs).borrow_mut().take_dropped_records())
+}
+
+/// Moves queued records to the console's transmit buffer while they fit. Returns true if there
+/// is nothing left to move, or no console to move it to.
+fn drain(cs: CriticalSection) -> bool {
+    let mut recorder = TELEMETRY_RECORDER.borrow(cs).borrow_mut();
+    let mut drained = true;
+    fill_console_buffer(cs, |tx_buffer| drained = recorder.drain_into(tx_buffer));
+    drained
+}
+
+/// Moves queued telemetry to the console's transmit buffer without waiting for room in it.
+/// Called from the main loop.
+pub fn drain_telemetry() {
+    interrupt::free(drain);
+}
+
+/// Moves all queued telemetry to the console's transmit buffer, waiting for room in it.
+/// Interrupts are enabled while waiting.
+pub fn flush_telemetry() {
+    while !interrupt::free(drain) {
+        flush_console();
+    }
+}
+
+/// Writes the header row of a telemetry table. In the binary format, the header row is preceded
+/// by a `#stream <stream ID> <field types>` line so that a decoder can unpack the table's frames.
+pub fn write_telemetry_headers<R, W>(
+    f: &mut W,
+    stream: TelemetryStream,
+    format: TelemetryFormat,
+) -> Result<(), W::Error>
+where
+    R: TelemetryRow,
+    W: uWrite + ?Sized,
+{
+    if format == TelemetryFormat::Binary {
+        uwrite!(f, "#stream {} ", stream as u8)?;
+        R::write_binary_format(f)?;
+        uwrite!(f, "\n")?;
+    }
+    log_csv_headers(f, R::headers())
+}
+
+/// Logs the header row of a telemetry table to the console in the current telemetry format.
+/// The rows still queued from the previous table are sent first.
+pub fn log_telemetry_headers<R: TelemetryRow>(stream: TelemetryStream) {
+    flush_telemetry();
+    let format = telemetry_format();
+    print_with_fn!(|f| { write_telemetry_headers::<R, _>(f, stream, format) });
+}
+
+/// Logs a telemetry row in the current telemetry format. The row is queued in the telemetry
+/// recorder and sent to the console from the main loop.
+pub fn log_telemetry_row<R: TelemetryRow>(stream: TelemetryStream, row: &R) {
+    match telemetry_format() {
+        TelemetryFormat::Csv => {
+            let mut record = RecordWriter::new();
+            if uwriteln!(record, "{}", row).is_ok() {
+                record_telemetry(record.as_bytes());
+            }
+        }
+        TelemetryFormat::Binary => {
+            let sequence = interrupt::free(|cs| {
+                let sequence = TELEMETRY_SEQUENCE.borrow(cs);
+                let current = sequence.get();
+                sequence.set(current.wrapping_add(1));
+                current
+            });
+            let mut frame = [0u8; MAX_FRAME_SIZE];
+            let length = encode_frame(stream as u8, sequence, row, &mut frame);
+            record_telemetry(&frame[..length]);
+        }
+    }
+}
+
+crate::telemetry_row! {
+    #[derive(Copy, Clone, Default)]
+    pub struct ForwardMovementTelemetryRow {
+        timestamp: u32 => "millis",
+        left_encoder: u32 => "Left Wheel Counter",
+        right_encoder: u32 => "Right Wheel Counter",
+        distance: f32 => "Distance",
+        delta_heading: f32 => "Delta Heading",
+        current_heading: f32 => "Current Heading",
+        gyro_heading: f32 => "Gyro Heading",
+        control_signal: f32 => "Control Signal",
+        control_error_integral: f32 => "Control Error Integral",
+        updated_left_power: u8 => "Updated Left Power",
+        updated_right_power: u8 => "Updated Right Power",
+        left_current: u16 => "Left Current",
+        right_current: u16 => "Right Current",
+        motor_fault: u8 => "Motor Fault",
+    }
+}
+
+crate::telemetry_row! {
+    #[derive(Copy, Clone, Default)]
+    pub struct RelayAutoTuneTelemetryRow {
+        timestamp: u32 => "millis",
+        setpoint: f32 => "Setpoint",
+        measurement: f32 => "Measurement",
+        relay_output: f32 => "Relay Output",
+        updated_left_power: u8 => "Updated Left Power",
+        updated_right_power: u8 => "Updated Right Power",
+    }
+}
+
+crate::telemetry_row! {
+    /// The state of the robot when a movement was aborted.
+    #[derive(Copy, Clone)]
+    pub struct MotionDiagnosticRow {
+        timestamp: u32 => "millis",
+        error: MotionError => "Error",
+        left_duty: u8 => "Left Duty",
+        right_duty: u8 => "Right Duty",
+        left_encoder: u32 => "Left Wheel Counter",
+        right_encoder: u32 => "Right Wheel Counter",
+        encoder_heading: f32 => "Encoder Heading",
+        gyro_heading: f32 => "Gyro Heading",
+    }
+}
+
+#[cfg(feature = "calibrate_motors")]
+crate::telemetry_row! {
+    #[derive(Copy, Clone, Default)]
+    pub struct MotorCalibrationRow {
+        test_id: u16 => "test_id",
+        power: u8 => "power",
+        left_ticks: u32 => "left_ticks",
+        right_ticks: u32 => "right_ticks",
+        lr_ratio: f32 => "lr_ratio",
+    }
+}
+
+#[cfg(feature = "characterize_motors")]
+crate::telemetry_row! {
+    #[derive(Copy, Clone, Default)]
+    pub struct MotorCharacterizationRow {
+        duty: u8 => "Duty",
+        left_speed: f32 => "Left Speed",
+        right_speed: f32 => "Right Speed",
+    }
+}
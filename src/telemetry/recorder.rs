@@ -0,0 +1,280 @@
+use ufmt::uWrite;
+
+use crate::system::ring_buffer::RingBuffer;
+
+/// The longest record that can be recorded, in bytes. A record's length is stored in one byte.
+pub const MAX_RECORD_SIZE: usize = 255;
+
+/// What the recorder does with a record that doesn't fit in the buffer.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drops the oldest records until the new record fits, so the most recent rows are kept.
+    DropOldest,
+    /// Drops the new record, so the rows that are kept are an unbroken run up to the overflow.
+    DropNewest,
+}
+
+/// Queues telemetry records, such as CSV lines or binary frames, so that logging a row doesn't
+/// wait for the UART or the SD card. The records are later moved whole to their destination,
+/// such as to the console's transmit buffer whenever it has room, so other console output never
+/// splits a record.
+///
+/// Each record is stored as its length byte followed by its bytes, so that whole records can be
+/// dropped when the buffer overflows.
+pub struct TelemetryRecorder<const N: usize> {
+    buffer: RingBuffer<N>,
+    policy: OverflowPolicy,
+    dropped_records: u16,
+}
+
+#[allow(dead_code)]
+impl<const N: usize> TelemetryRecorder<N> {
+    pub const fn new(policy: OverflowPolicy) -> Self {
+        Self {
+            buffer: RingBuffer::new(),
+            policy,
+            dropped_records: 0,
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    /// The number of records dropped because the buffer was full.
+    pub fn dropped_records(&self) -> u16 {
+        self.dropped_records
+    }
+
+    /// Returns the number of dropped records and resets the count.
+    pub fn take_dropped_records(&mut self) -> u16 {
+        core::mem::take(&mut self.dropped_records)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Queues a record. Returns false if the record was dropped because it didn't fit.
+    pub fn record(&mut self, record: &[u8]) -> bool {
+        let length = record.len();
+        if length == 0 {
+            return true;
+        }
+        if length > MAX_RECORD_SIZE || length + 1 > N {
+            self.dropped_records = self.dropped_records.saturating_add(1);
+            return false;
+        }
+        while self.buffer.free() < length + 1 {
+            match self.policy {
+                OverflowPolicy::DropNewest => {
+                    self.dropped_records = self.dropped_records.saturating_add(1);
+                    return false;
+                }
+                OverflowPolicy::DropOldest => self.drop_oldest(),
+            }
+        }
+        self.buffer.push(length as u8);
+        for byte in record {
+            self.buffer.push(*byte);
+        }
+        true
+    }
+
+    fn drop_oldest(&mut self) {
+        self.pop_record(|_| {});
+        self.dropped_records = self.dropped_records.saturating_add(1);
+    }
+
+    /// The length of the oldest record.
+    pub fn next_record_length(&self) -> Option<usize> {
+        self.buffer.peek().map(usize::from)
+    }
+
+    /// Moves the queued records to `buffer` while they fit. A record is only moved whole. Returns
+    /// true if every record was moved.
+    pub fn drain_into<const M: usize>(&mut self, buffer: &mut RingBuffer<M>) -> bool {
+        while let Some(length) = self.next_record_length() {
+            if buffer.free() < length {
+                return false;
+            }
+            self.pop_record(|byte| {
+                buffer.push(byte);
+            });
+        }
+        true
+    }
+
+    /// Removes the oldest record, passing each of its bytes to `f`.
+    pub fn pop_record<F: FnMut(u8)>(&mut self, mut f: F) {
+        if let Some(length) = self.buffer.pop() {
+            for _ in 0..length {
+                if let Some(byte) = self.buffer.pop() {
+                    f(byte);
+                }
+            }
+        }
+    }
+}
+
+/// Formats a record in memory before it is queued.
+pub struct RecordWriter {
+    bytes: [u8; MAX_RECORD_SIZE],
+    length: usize,
+}
+
+impl RecordWriter {
+    pub fn new() -> Self {
+        Self {
+            bytes: [0; MAX_RECORD_SIZE],
+            length: 0,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.length]
+    }
+}
+
+impl Default for RecordWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl uWrite for RecordWriter {
+    /// The record is longer than `MAX_RECORD_SIZE`.
+    type Error = ();
+
+    fn write_str(&mut self, s: &str) -> Result<(), Self::Error> {
+        let end = self.length + s.len();
+        if end > MAX_RECORD_SIZE {
+            return Err(());
+        }
+        self.bytes[self.length..end].copy_from_slice(s.as_bytes());
+        self.length = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pops every queued record and returns their first bytes.
+    fn first_bytes<const N: usize>(recorder: &mut TelemetryRecorder<N>) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        let mut count = 0;
+        while recorder.next_record_length().is_some() {
+            let mut first = None;
+            recorder.pop_record(|byte| {
+                first.get_or_insert(byte);
+            });
+            bytes[count] = first.unwrap();
+            count += 1;
+        }
+        bytes
+    }
+
+    #[test]
+    fn records_are_popped_whole_and_in_order() {
+        let mut recorder = TelemetryRecorder::<16>::new(OverflowPolicy::DropNewest);
+        assert!(recorder.record(&[1, 2, 3]));
+        assert!(recorder.record(&[4]));
+        // an empty record isn't queued
+        assert!(recorder.record(&[]));
+        assert_eq!(recorder.next_record_length(), Some(3));
+
+        let mut popped = [0u8; 3];
+        let mut length = 0;
+        recorder.pop_record(|byte| {
+            popped[length] = byte;
+            length += 1;
+        });
+        assert_eq!(popped[..length], [1, 2, 3]);
+        assert_eq!(recorder.next_record_length(), Some(1));
+        recorder.pop_record(|byte| assert_eq!(byte, 4));
+        assert!(recorder.is_empty());
+    }
+
+    #[test]
+    fn records_wrap_around_the_buffer() {
+        let mut recorder = TelemetryRecorder::<8>::new(OverflowPolicy::DropNewest);
+        for value in 0..20u8 {
+            assert!(recorder.record(&[value, value, value]));
+            assert!(recorder.record(&[value + 100, value]));
+            assert_eq!(first_bytes(&mut recorder)[..2], [value, value + 100]);
+        }
+        assert_eq!(recorder.dropped_records(), 0);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_queued_records() {
+        // each 3 byte record takes 4 bytes of the buffer
+        let mut recorder = TelemetryRecorder::<10>::new(OverflowPolicy::DropNewest);
+        assert!(recorder.record(&[1, 0, 0]));
+        assert!(recorder.record(&[2, 0, 0]));
+        assert!(!recorder.record(&[3, 0, 0]));
+        assert!(!recorder.record(&[4, 0, 0]));
+        // a shorter record still fits
+        assert!(recorder.record(&[5]));
+        assert_eq!(recorder.dropped_records(), 2);
+        assert_eq!(first_bytes(&mut recorder)[..3], [1, 2, 5]);
+
+        assert_eq!(recorder.take_dropped_records(), 2);
+        assert_eq!(recorder.dropped_records(), 0);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_records() {
+        let mut recorder = TelemetryRecorder::<10>::new(OverflowPolicy::DropOldest);
+        assert!(recorder.record(&[1, 0, 0]));
+        assert!(recorder.record(&[2, 0, 0]));
+        assert!(recorder.record(&[3, 0, 0]));
+        assert_eq!(recorder.dropped_records(), 1);
+        // a longer record drops as many old records as it needs
+        assert!(recorder.record(&[4, 0, 0, 0, 0, 0]));
+        assert_eq!(recorder.dropped_records(), 3);
+        assert_eq!(first_bytes(&mut recorder)[..1], [4]);
+    }
+
+    #[test]
+    fn records_that_can_never_fit_are_dropped() {
+        let mut recorder = TelemetryRecorder::<4>::new(OverflowPolicy::DropOldest);
+        assert!(recorder.record(&[1]));
+        assert!(!recorder.record(&[2, 2, 2, 2]));
+        assert_eq!(recorder.dropped_records(), 1);
+        // the queued record is kept
+        assert_eq!(first_bytes(&mut recorder)[..1], [1]);
+
+        let mut recorder = TelemetryRecorder::<300>::new(OverflowPolicy::DropNewest);
+        assert!(!recorder.record(&[1; MAX_RECORD_SIZE + 1]));
+        assert!(recorder.record(&[1; MAX_RECORD_SIZE]));
+        assert_eq!(recorder.dropped_records(), 1);
+    }
+
+    #[test]
+    fn drain_never_splits_a_record() {
+        let mut recorder = TelemetryRecorder::<16>::new(OverflowPolicy::DropNewest);
+        recorder.record(&[1, 1, 1]);
+        recorder.record(&[2, 2, 2, 2]);
+        let mut buffer: RingBuffer<6> = RingBuffer::new();
+
+        // the second record doesn't fit after the first, so it stays queued
+        assert!(!recorder.drain_into(&mut buffer));
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(recorder.next_record_length(), Some(4));
+        assert!(!recorder.drain_into(&mut buffer));
+        assert_eq!(buffer.len(), 3);
+
+        for _ in 0..3 {
+            assert_eq!(buffer.pop(), Some(1));
+        }
+        assert!(recorder.drain_into(&mut buffer));
+        assert_eq!(buffer.len(), 4);
+        assert!(recorder.is_empty());
+        for _ in 0..4 {
+            assert_eq!(buffer.pop(), Some(2));
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use ufmt::{uDisplay, uWrite, Formatter};
+
+use crate::{model::motion_monitor::MotionError, system::flash_str::FlashStr};
+
+/// A row of a telemetry table. Implement it with the `telemetry_row!` macro, which generates the
+/// row struct, the headers and the formatters from a single field list.
+pub trait TelemetryRow: uDisplay {
+    const COLUMN_COUNT: usize;
+    /// The size of the row's binary record in bytes.
+    const BINARY_SIZE: usize;
+
+    /// The column headers, one per field. They are kept in program memory.
+    fn headers() -> &'static [FlashStr];
+
+    /// Writes the row as comma separated values, without a line ending.
+    fn write_csv<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized;
+
+    /// Writes the row's fields to `buf` as little-endian values in column order and returns the
+    /// number of bytes written, which is `BINARY_SIZE`. Panics if `buf` is shorter than that.
+    fn write_binary(&self, buf: &mut [u8]) -> usize;
+
+    /// Writes the type codes of the row's binary record fields in column order, so that a
+    /// decoder can unpack the record.
+    fn write_binary_format<W>(f: &mut W) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized;
+}
+
+/// A value that can be a telemetry row field.
+pub trait TelemetryValue: uDisplay {
+    /// The size of the value's binary encoding in bytes.
+    const SIZE: usize;
+    /// The code for the value's binary encoding, using the Python `struct` module's format
+    /// characters.
+    const TYPE_CODE: &'static str;
+
+    /// Writes the value to the start of `buf` as little-endian bytes.
+    fn write_le(&self, buf: &mut [u8]);
+
+    /// Writes the value as a CSV field. The value's text must not contain the `", "` field
+    /// separator.
+    fn write_csv<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uDisplay::fmt(self, f)
+    }
+}
+
+macro_rules! impl_telemetry_value {
+    ($($ty:ty => $type_code:literal),+ $(,)?) => {
+        $(
+            impl TelemetryValue for $ty {
+                const SIZE: usize = core::mem::size_of::<$ty>();
+                const TYPE_CODE: &'static str = $type_code;
+
+                fn write_le(&self, buf: &mut [u8]) {
+                    buf[..Self::SIZE].copy_from_slice(&self.to_le_bytes());
+                }
+            }
+        )+
+    };
+}
+
+impl_telemetry_value!(
+    u8 => "B",
+    u16 => "H",
+    u32 => "I",
+    i8 => "b",
+    i16 => "h",
+    i32 => "i",
+    f32 => "f",
+);
+
+/// Motion errors are recorded as their numeric code, since the text of some errors contains
+/// commas.
+impl TelemetryValue for MotionError {
+    const SIZE: usize = 1;
+    const TYPE_CODE: &'static str = "B";
+
+    fn write_le(&self, buf: &mut [u8]) {
+        buf[0] = self.code();
+    }
+
+    fn write_csv<W>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error>
+    where
+        W: uWrite + ?Sized,
+    {
+        uDisplay::fmt(&self.code(), f)
+    }
+}
+
+/// Generates a telemetry row struct with a public field per column, along with its
+/// `TelemetryRow`, `uDisplay` and `uDebug` implementations. Each field names its column header:
+///
+/// ```ignore
+/// telemetry_row! {
+///     #[derive(Copy, Clone, Default)]
+///     pub struct TurnTelemetryRow {
+///         timestamp: u32 => "millis",
+///         heading: f32 => "Heading",
+///     }
+/// }
+/// ```
+///
+/// Every field type must implement `TelemetryValue`.
+#[macro_export]
+macro_rules! telemetry_row {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident: $ty:ty => $header:literal
+            ),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                pub $field: $ty,
+            )+
+        }
+
+        impl $crate::telemetry::telemetry_row::TelemetryRow for $name {
+            const COLUMN_COUNT: usize = [$($header),+].len();
+            const BINARY_SIZE: usize =
+                0 $(+ <$ty as $crate::telemetry::telemetry_row::TelemetryValue>::SIZE)+;
+
+            fn headers() -> &'static [$crate::system::flash_str::FlashStr] {
+                static HEADERS: [$crate::system::flash_str::FlashStr; [$($header),+].len()] =
+                    [$($crate::F!($header)),+];
+                &HEADERS
+            }
+
+            // the separator assigned after the last field is never read
+            #[allow(unused_assignments)]
+            fn write_csv<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+            where
+                W: ufmt::uWrite + ?Sized,
+            {
+                let mut separator = "";
+                $(
+                    f.write_str(separator)?;
+                    $crate::telemetry::telemetry_row::TelemetryValue::write_csv(&self.$field, f)?;
+                    separator = ", ";
+                )+
+                Ok(())
+            }
+
+            fn write_binary(&self, buf: &mut [u8]) -> usize {
+                let mut offset = 0;
+                $(
+                    $crate::telemetry::telemetry_row::TelemetryValue::write_le(
+                        &self.$field,
+                        &mut buf[offset..],
+                    );
+                    offset += <$ty as $crate::telemetry::telemetry_row::TelemetryValue>::SIZE;
+                )+
+                offset
+            }
+
+            fn write_binary_format<W>(f: &mut W) -> Result<(), W::Error>
+            where
+                W: ufmt::uWrite + ?Sized,
+            {
+                $(
+                    f.write_str(
+                        <$ty as $crate::telemetry::telemetry_row::TelemetryValue>::TYPE_CODE,
+                    )?;
+                )+
+                Ok(())
+            }
+        }
+
+        impl ufmt::uDisplay for $name {
+            fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+            where
+                W: ufmt::uWrite + ?Sized,
+            {
+                $crate::telemetry::telemetry_row::TelemetryRow::write_csv(self, f)
+            }
+        }
+
+        impl ufmt::uDebug for $name {
+            #[allow(unused_assignments)]
+            fn fmt<W>(&self, f: &mut ufmt::Formatter<'_, W>) -> Result<(), W::Error>
+            where
+                W: ufmt::uWrite + ?Sized,
+            {
+                f.write_str(stringify!($name))?;
+                f.write_str("<")?;
+                let mut separator = "";
+                $(
+                    f.write_str(separator)?;
+                    f.write_str(concat!(stringify!($field), ": "))?;
+                    ufmt::uDisplay::fmt(&self.$field, f)?;
+                    separator = ", ";
+                )+
+                f.write_str(">")
+            }
+        }
+    };
+}
@@ -0,0 +1,206 @@
+//! Decoding of the firmware's binary telemetry frames. A frame is a COBS encoded record of the
+//! stream ID, the little-endian sequence number, the row's little-endian fields and the
+//! little-endian CRC-16/CCITT-FALSE of everything before it. See `src/telemetry/binary.rs` in the
+//! firmware.
+
+const RECORD_HEADER_SIZE: usize = 3;
+const CRC_SIZE: usize = 2;
+
+/// A decoded binary telemetry record.
+pub struct Record {
+    pub stream_id: u8,
+    pub sequence: u16,
+    pub payload: Vec<u8>,
+}
+
+/// Calculates the CRC-16/CCITT-FALSE checksum of `data`.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+/// Decodes COBS encoded `input`, without the zero delimiter. Returns `None` if `input` isn't
+/// valid COBS data.
+pub fn cobs_decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut index = 0;
+    while index < input.len() {
+        let code = input[index] as usize;
+        if code == 0 || index + code > input.len() {
+            return None;
+        }
+        output.extend_from_slice(&input[index + 1..index + code]);
+        index += code;
+        if code < 0xFF && index < input.len() {
+            output.push(0);
+        }
+    }
+    Some(output)
+}
+
+/// Decodes a frame without its zero delimiter. Returns `None` if the frame is malformed or its
+/// CRC doesn't match.
+pub fn decode_frame(frame: &[u8]) -> Option<Record> {
+    let record = cobs_decode(frame)?;
+    if record.len() < RECORD_HEADER_SIZE + CRC_SIZE {
+        return None;
+    }
+    let (body, crc) = record.split_at(record.len() - CRC_SIZE);
+    if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+        return None;
+    }
+    Some(Record {
+        stream_id: body[0],
+        sequence: u16::from_le_bytes([body[1], body[2]]),
+        payload: body[RECORD_HEADER_SIZE..].to_vec(),
+    })
+}
+
+/// The size in bytes of a field with the given `struct` module style type code.
+fn field_size(type_code: char) -> Option<usize> {
+    match type_code {
+        'B' | 'b' => Some(1),
+        'H' | 'h' => Some(2),
+        'I' | 'i' | 'f' => Some(4),
+        _ => None,
+    }
+}
+
+/// Unpacks a record payload into one string per field according to `format`, a string of
+/// `struct` module style type codes. Returns `None` if the payload doesn't match the format.
+pub fn unpack_fields(format: &str, payload: &[u8]) -> Option<Vec<String>> {
+    let mut fields = Vec::with_capacity(format.len());
+    let mut offset = 0;
+    for type_code in format.chars() {
+        let size = field_size(type_code)?;
+        let bytes = payload.get(offset..offset + size)?;
+        let field = match type_code {
+            'B' => bytes[0].to_string(),
+            'b' => (bytes[0] as i8).to_string(),
+            'H' => u16::from_le_bytes([bytes[0], bytes[1]]).to_string(),
+            'h' => i16::from_le_bytes([bytes[0], bytes[1]]).to_string(),
+            'I' => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_string(),
+            'i' => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_string(),
+            'f' => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_string(),
+            _ => return None,
+        };
+        fields.push(field);
+        offset += size;
+    }
+    if offset != payload.len() {
+        return None;
+    }
+    Some(fields)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A frame produced by the firmware's `encode_frame` for a row of a `u32` timestamp of 1000, an
+    /// `f32` heading of 1.5 and a `u8` duty of 0, as stream 1 with sequence number 258. The
+    /// firmware's `binary` module tests encode the same frame.
+    pub const FIRMWARE_FRAME: [u8; 16] = [
+        0x06, 0x01, 0x02, 0x01, 0xE8, 0x03, 0x01, 0x01, 0x01, 0x03, 0xC0, 0x3F, 0x03, 0x3B, 0xDD,
+        0x00,
+    ];
+
+    #[test]
+    fn crc16_matches_the_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn cobs_decode_restores_zero_bytes() {
+        assert_eq!(cobs_decode(&[0x01]), Some(vec![]));
+        assert_eq!(cobs_decode(&[0x01, 0x01]), Some(vec![0x00]));
+        assert_eq!(cobs_decode(&[0x01, 0x01, 0x01]), Some(vec![0x00, 0x00]));
+        assert_eq!(
+            cobs_decode(&[0x03, 0x11, 0x22, 0x02, 0x33]),
+            Some(vec![0x11, 0x22, 0x00, 0x33])
+        );
+        assert_eq!(cobs_decode(&[0x02, 0x11, 0x01]), Some(vec![0x11, 0x00]));
+    }
+
+    #[test]
+    fn cobs_decode_joins_runs_of_254_non_zero_bytes() {
+        let data: Vec<u8> = (1..=255).collect();
+        // a full run isn't followed by a zero byte, even when an empty block ends the data
+        let mut encoded = vec![0xFF];
+        encoded.extend_from_slice(&data[..254]);
+        encoded.push(0x01);
+        assert_eq!(cobs_decode(&encoded), Some(data[..254].to_vec()));
+
+        encoded.pop();
+        encoded.extend_from_slice(&[0x02, 0xFF]);
+        assert_eq!(cobs_decode(&encoded), Some(data));
+    }
+
+    #[test]
+    fn cobs_decode_rejects_invalid_data() {
+        assert_eq!(cobs_decode(&[0x00, 0x11]), None);
+        assert_eq!(cobs_decode(&[0x04, 0x11, 0x22]), None);
+    }
+
+    #[test]
+    fn decodes_a_firmware_frame() {
+        let frame = &FIRMWARE_FRAME[..FIRMWARE_FRAME.len() - 1];
+        let record = decode_frame(frame).unwrap();
+        assert_eq!(record.stream_id, 1);
+        assert_eq!(record.sequence, 258);
+        assert_eq!(
+            unpack_fields("IfB", &record.payload),
+            Some(vec!["1000".to_string(), "1.5".to_string(), "0".to_string()])
+        );
+    }
+
+    #[test]
+    fn rejects_frames_with_a_bad_crc_or_too_short() {
+        let mut frame = FIRMWARE_FRAME[..FIRMWARE_FRAME.len() - 1].to_vec();
+        frame[4] ^= 0x01;
+        assert!(decode_frame(&frame).is_none());
+        // a stream ID and sequence number without a CRC
+        assert!(decode_frame(&[0x04, 0x01, 0x02, 0x01]).is_none());
+    }
+
+    #[test]
+    fn unpacks_every_field_type() {
+        let mut payload = vec![200, 0xFE];
+        payload.extend_from_slice(&60000u16.to_le_bytes());
+        payload.extend_from_slice(&(-300i16).to_le_bytes());
+        payload.extend_from_slice(&4_000_000_000u32.to_le_bytes());
+        payload.extend_from_slice(&(-70000i32).to_le_bytes());
+        payload.extend_from_slice(&(-0.25f32).to_le_bytes());
+        let fields = unpack_fields("BbHhIif", &payload).unwrap();
+        assert_eq!(
+            fields,
+            [
+                "200",
+                "-2",
+                "60000",
+                "-300",
+                "4000000000",
+                "-70000",
+                "-0.25"
+            ]
+        );
+    }
+
+    #[test]
+    fn unpack_rejects_payloads_that_dont_match_the_format() {
+        assert_eq!(unpack_fields("H", &[1]), None);
+        assert_eq!(unpack_fields("B", &[1, 2]), None);
+        assert_eq!(unpack_fields("d", &[0; 8]), None);
+        assert_eq!(unpack_fields("", &[]), Some(vec![]));
+    }
+}
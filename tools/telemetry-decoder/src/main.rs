@@ -0,0 +1,285 @@
+//! Reads the robot's serial log, such as a file captured from the `ravedude` console or a serial
+//! device, splits it into telemetry tables by their header rows and binary frames, and writes
+//! one CSV file per table along with summary statistics.
+
+mod frame;
+mod splitter;
+mod table;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::mem;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use frame::{unpack_fields, Record};
+use splitter::{LogItem, LogSplitter};
+use table::{StreamInfo, Table};
+
+const DEFAULT_OUT_DIR: &str = "telemetry";
+const USAGE: &str = "usage: telemetry-decoder [--out-dir <dir>] [<log file or serial device>]
+
+Reads the robot's serial log from the file or device, or from stdin if none is given, and writes
+one CSV file per telemetry table to the output directory (default: ./telemetry).";
+
+struct Options {
+    input: Option<PathBuf>,
+    out_dir: PathBuf,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut input = None;
+    let mut out_dir = PathBuf::from(DEFAULT_OUT_DIR);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--out-dir" => {
+                out_dir = args
+                    .next()
+                    .map(PathBuf::from)
+                    .ok_or("--out-dir needs a directory")?;
+            }
+            "-h" | "--help" => return Err(String::new()),
+            "-" => input = None,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => input = Some(PathBuf::from(arg)),
+        }
+    }
+    Ok(Options { input, out_dir })
+}
+
+/// Assembles the log's lines and frames into telemetry tables.
+struct LogDecoder {
+    source: String,
+    out_dir: PathBuf,
+    table: Option<Table>,
+    table_count: usize,
+    pending_lines: Vec<String>,
+    pending_stream: Option<StreamInfo>,
+    bad_frames: u32,
+}
+
+impl LogDecoder {
+    fn new(source: String, out_dir: PathBuf) -> Self {
+        Self {
+            source,
+            out_dir,
+            table: None,
+            table_count: 0,
+            pending_lines: Vec::new(),
+            pending_stream: None,
+            bad_frames: 0,
+        }
+    }
+
+    fn handle_item(&mut self, item: LogItem, splitter: &mut LogSplitter) -> io::Result<()> {
+        match item {
+            LogItem::Line(line) => self.handle_line(line, splitter),
+            LogItem::Frame(record) => {
+                self.handle_frame(record);
+                Ok(())
+            }
+            LogItem::Garbage(length) => {
+                eprintln!("skipped {} bytes that are not a valid frame", length);
+                self.bad_frames += 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn handle_line(&mut self, line: String, splitter: &mut LogSplitter) -> io::Result<()> {
+        if let Some(stream) = parse_stream_line(&line) {
+            splitter.expect_frames();
+            self.pending_stream = Some(stream);
+            return Ok(());
+        }
+        if let Some(headers) = parse_header_line(&line) {
+            self.finish_table()?;
+            self.table_count += 1;
+            self.table = Some(Table::new(
+                self.table_count,
+                headers,
+                mem::take(&mut self.pending_lines),
+                self.pending_stream.take(),
+            ));
+            return Ok(());
+        }
+        if let Some(table) = self.table.as_mut().filter(|table| table.stream.is_none()) {
+            let fields: Vec<String> = line.split(',').map(|f| f.trim().to_string()).collect();
+            let is_row = fields.len() == table.headers.len()
+                && fields[0].parse::<f64>().is_ok();
+            if is_row {
+                table.notes.append(&mut self.pending_lines);
+                table.rows.push(fields);
+                return Ok(());
+            }
+        }
+        if !line.trim().is_empty() {
+            self.pending_lines.push(line);
+        }
+        Ok(())
+    }
+
+    fn handle_frame(&mut self, record: Record) {
+        let table = match self.table.as_mut() {
+            Some(table) => table,
+            None => {
+                eprintln!("skipped a frame of stream {} before any table", record.stream_id);
+                self.bad_frames += 1;
+                return;
+            }
+        };
+        let format = match &table.stream {
+            Some(stream) if stream.id == record.stream_id => stream.format.clone(),
+            _ => {
+                eprintln!(
+                    "skipped a frame of stream {} in table {}",
+                    record.stream_id, table.index
+                );
+                self.bad_frames += 1;
+                return;
+            }
+        };
+        table.record_sequence(record.sequence);
+        match unpack_fields(&format, &record.payload) {
+            Some(fields) => {
+                table.notes.append(&mut self.pending_lines);
+                table.rows.push(fields);
+            }
+            None => {
+                eprintln!(
+                    "skipped frame {} that doesn't match the format {}",
+                    record.sequence, format
+                );
+                self.bad_frames += 1;
+            }
+        }
+    }
+
+    /// Writes the current table's CSV file and prints its summary.
+    fn finish_table(&mut self) -> io::Result<()> {
+        if let Some(table) = self.table.take() {
+            let path = table.write_csv(&self.out_dir, &self.source)?;
+            println!("{}:", path.display());
+            for line in table.summary() {
+                println!("    {}", line);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(table) = self.table.as_mut() {
+            table.notes.append(&mut self.pending_lines);
+        }
+        self.finish_table()?;
+        println!(
+            "{} tables decoded from {}, {} bad frames",
+            self.table_count, self.source, self.bad_frames
+        );
+        Ok(())
+    }
+}
+
+/// Parses a `#stream <stream ID> <field types>` line.
+fn parse_stream_line(line: &str) -> Option<StreamInfo> {
+    let mut parts = line.trim().strip_prefix("#stream ")?.split_whitespace();
+    let id = parts.next()?.parse().ok()?;
+    let format = parts.next()?.to_string();
+    Some(StreamInfo { id, format })
+}
+
+/// Parses a header row, which is a list of quoted column names.
+fn parse_header_line(line: &str) -> Option<Vec<String>> {
+    let headers: Option<Vec<String>> = line
+        .split(',')
+        .map(|h| {
+            let h = h.trim();
+            h.strip_prefix('"')?.strip_suffix('"').map(str::to_string)
+        })
+        .collect();
+    headers.filter(|headers| !headers.is_empty() && line.contains('"'))
+}
+
+fn run(options: Options) -> io::Result<()> {
+    let (mut input, source): (Box<dyn Read>, String) = match &options.input {
+        Some(path) => (Box::new(File::open(path)?), path.display().to_string()),
+        None => (Box::new(io::stdin()), "stdin".to_string()),
+    };
+    fs::create_dir_all(&options.out_dir)?;
+
+    let mut decoder = LogDecoder::new(source, options.out_dir);
+    let mut splitter = LogSplitter::default();
+    let mut buffer = [0u8; 4096];
+    loop {
+        let count = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => {
+                // a serial device that goes away ends the log
+                eprintln!("stopped reading: {}", error);
+                break;
+            }
+        };
+        splitter.push(&buffer[..count]);
+        while let Some(item) = splitter.next_item(false) {
+            decoder.handle_item(item, &mut splitter)?;
+        }
+    }
+    while let Some(item) = splitter.next_item(true) {
+        decoder.handle_item(item, &mut splitter)?;
+    }
+    decoder.finish()
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}\n", message);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_header_lines() {
+        assert_eq!(
+            parse_header_line("\"millis\", \"Gyro Heading\",\"Control Signal\"\r"),
+            Some(vec![
+                "millis".to_string(),
+                "Gyro Heading".to_string(),
+                "Control Signal".to_string()
+            ])
+        );
+        assert_eq!(parse_header_line("1000, 0.5, 3"), None);
+        assert_eq!(parse_header_line("\"millis\", Heading"), None);
+        assert_eq!(parse_header_line(""), None);
+    }
+
+    #[test]
+    fn parses_stream_lines() {
+        let stream = parse_stream_line("#stream 2 IffhB\r").unwrap();
+        assert_eq!(stream.id, 2);
+        assert_eq!(stream.format, "IffhB");
+        assert!(parse_stream_line("#stream x If").is_none());
+        assert!(parse_stream_line("#stream 2").is_none());
+        assert!(parse_stream_line("stream 2 If").is_none());
+    }
+}
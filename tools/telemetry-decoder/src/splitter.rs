@@ -0,0 +1,204 @@
+use crate::frame::{decode_frame, Record};
+
+/// An item of the robot's serial log.
+pub enum LogItem {
+    /// A line of text, without its line ending.
+    Line(String),
+    /// A binary telemetry frame.
+    Frame(Record),
+    /// Bytes that were neither text nor a valid frame, such as a frame with a bad CRC.
+    Garbage(usize),
+}
+
+/// Splits the serial log byte stream into text lines and binary telemetry frames.
+///
+/// Text lines end with a line feed and frames end with a zero byte. Until the log announces a
+/// binary stream, everything is text. After that, a COBS encoded frame may contain line feed
+/// bytes, so a line is only split off once the next zero byte shows whether the bytes before
+/// it are a valid frame.
+#[derive(Default)]
+pub struct LogSplitter {
+    buffer: Vec<u8>,
+    binary: bool,
+}
+
+impl LogSplitter {
+    /// Appends bytes read from the log.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Marks that binary frames may follow, which the log announces with a `#stream` line.
+    pub fn expect_frames(&mut self) {
+        self.binary = true;
+    }
+
+    /// Returns the next complete item in the buffered log. With `end_of_log` set, the remaining
+    /// bytes are returned as text.
+    pub fn next_item(&mut self, end_of_log: bool) -> Option<LogItem> {
+        let newline = self.buffer.iter().position(|b| *b == b'\n');
+        if !self.binary {
+            return match newline {
+                Some(newline) => Some(self.take_line(newline + 1)),
+                None => self.take_remainder(end_of_log),
+            };
+        }
+
+        match self.buffer.iter().position(|b| *b == 0) {
+            Some(zero) => {
+                if let Some(record) = decode_frame(&self.buffer[..zero]) {
+                    self.buffer.drain(..=zero);
+                    Some(LogItem::Frame(record))
+                } else if let Some(newline) = newline.filter(|n| *n < zero) {
+                    Some(self.take_line(newline + 1))
+                } else {
+                    self.buffer.drain(..=zero);
+                    Some(LogItem::Garbage(zero + 1))
+                }
+            }
+            None if end_of_log => match newline {
+                Some(newline) => Some(self.take_line(newline + 1)),
+                None => self.take_remainder(true),
+            },
+            None => None,
+        }
+    }
+
+    fn take_line(&mut self, length: usize) -> LogItem {
+        let bytes: Vec<u8> = self.buffer.drain(..length).collect();
+        let line = String::from_utf8_lossy(&bytes);
+        LogItem::Line(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    fn take_remainder(&mut self, end_of_log: bool) -> Option<LogItem> {
+        if end_of_log && !self.buffer.is_empty() {
+            let length = self.buffer.len();
+            Some(self.take_line(length))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::tests::FIRMWARE_FRAME;
+
+    /// A frame of stream 2 with sequence number 10 and a `u16` field of 0x0A0A, so the frame
+    /// contains line feed bytes.
+    const FRAME_WITH_LINE_FEEDS: [u8; 9] = [0x03, 0x02, 0x0A, 0x05, 0x0A, 0x0A, 0xA5, 0x73, 0x00];
+
+    fn line(item: Option<LogItem>) -> String {
+        match item {
+            Some(LogItem::Line(line)) => line,
+            _ => panic!("expected a line"),
+        }
+    }
+
+    fn frame(item: Option<LogItem>) -> Record {
+        match item {
+            Some(LogItem::Frame(record)) => record,
+            _ => panic!("expected a frame"),
+        }
+    }
+
+    fn garbage(item: Option<LogItem>) -> usize {
+        match item {
+            Some(LogItem::Garbage(length)) => length,
+            _ => panic!("expected garbage"),
+        }
+    }
+
+    #[test]
+    fn splits_text_into_lines() {
+        let mut splitter = LogSplitter::default();
+        splitter.push(b"Robot initialized\r\nStarting");
+        assert_eq!(line(splitter.next_item(false)), "Robot initialized");
+        // the line isn't complete yet
+        assert!(splitter.next_item(false).is_none());
+        splitter.push(b" movement\n");
+        assert_eq!(line(splitter.next_item(false)), "Starting movement");
+        assert!(splitter.next_item(true).is_none());
+    }
+
+    #[test]
+    fn zero_bytes_are_text_until_frames_are_expected() {
+        let mut splitter = LogSplitter::default();
+        splitter.push(&FIRMWARE_FRAME);
+        splitter.push(b"\n");
+        assert!(matches!(splitter.next_item(false), Some(LogItem::Line(_))));
+    }
+
+    #[test]
+    fn splits_frames_from_text() {
+        let mut splitter = LogSplitter::default();
+        splitter.expect_frames();
+        splitter.push(b"\"millis\", \"Heading\"\n");
+        splitter.push(&FIRMWARE_FRAME);
+        splitter.push(b"Done\n");
+        assert_eq!(line(splitter.next_item(false)), "\"millis\", \"Heading\"");
+        assert_eq!(frame(splitter.next_item(false)).sequence, 258);
+        // a line is only split off once a zero byte or the end of the log shows it isn't a frame
+        assert!(splitter.next_item(false).is_none());
+        assert_eq!(line(splitter.next_item(true)), "Done");
+    }
+
+    #[test]
+    fn frames_may_contain_line_feeds() {
+        let mut splitter = LogSplitter::default();
+        splitter.expect_frames();
+        splitter.push(&FRAME_WITH_LINE_FEEDS);
+        let record = frame(splitter.next_item(false));
+        assert_eq!(record.stream_id, 2);
+        assert_eq!(record.sequence, 10);
+        assert_eq!(record.payload, [0x0A, 0x0A]);
+        assert!(splitter.next_item(true).is_none());
+    }
+
+    #[test]
+    fn lines_before_a_frame_are_split_off() {
+        let mut splitter = LogSplitter::default();
+        splitter.expect_frames();
+        splitter.push(b"Movement aborted\n");
+        splitter.push(&FRAME_WITH_LINE_FEEDS);
+        assert_eq!(line(splitter.next_item(false)), "Movement aborted");
+        assert_eq!(frame(splitter.next_item(false)).sequence, 10);
+    }
+
+    #[test]
+    fn skips_garbage_up_to_the_next_zero_byte() {
+        let mut splitter = LogSplitter::default();
+        splitter.expect_frames();
+        // a frame with a bad CRC, then a truncated frame after a line
+        let mut bad_frame = FIRMWARE_FRAME;
+        bad_frame[4] ^= 0x01;
+        splitter.push(&bad_frame);
+        splitter.push(b"text\n\x04\x01\x00");
+        splitter.push(&FIRMWARE_FRAME);
+        assert_eq!(garbage(splitter.next_item(false)), FIRMWARE_FRAME.len());
+        assert_eq!(line(splitter.next_item(false)), "text");
+        assert_eq!(garbage(splitter.next_item(false)), 3);
+        assert_eq!(frame(splitter.next_item(false)).sequence, 258);
+        assert!(splitter.next_item(true).is_none());
+    }
+
+    #[test]
+    fn returns_the_remainder_at_the_end_of_the_log() {
+        let mut splitter = LogSplitter::default();
+        splitter.expect_frames();
+        splitter.push(b"last line\npartial");
+        assert!(splitter.next_item(false).is_none());
+        assert_eq!(line(splitter.next_item(true)), "last line");
+        assert_eq!(line(splitter.next_item(true)), "partial");
+        assert!(splitter.next_item(true).is_none());
+
+        // a frame cut off at the end of the log comes out as text rather than being lost
+        splitter.push(&FIRMWARE_FRAME[..4]);
+        assert!(splitter.next_item(false).is_none());
+        assert_eq!(
+            line(splitter.next_item(true)).as_bytes(),
+            &FIRMWARE_FRAME[..4]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// A binary telemetry stream announced by a `#stream <stream ID> <field types>` line.
+#[derive(Clone)]
+pub struct StreamInfo {
+    pub id: u8,
+    pub format: String,
+}
+
+/// A telemetry table from the log, which is one movement or experiment.
+pub struct Table {
+    pub index: usize,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// The log lines leading up to the table's header row.
+    pub metadata: Vec<String>,
+    /// Log lines printed while the table's rows were being logged.
+    pub notes: Vec<String>,
+    pub stream: Option<StreamInfo>,
+    pub last_sequence: Option<u16>,
+    pub dropped_frames: u32,
+}
+
+impl Table {
+    pub fn new(
+        index: usize,
+        headers: Vec<String>,
+        metadata: Vec<String>,
+        stream: Option<StreamInfo>,
+    ) -> Self {
+        Self {
+            index,
+            headers,
+            rows: Vec::new(),
+            metadata,
+            notes: Vec::new(),
+            stream,
+            last_sequence: None,
+            dropped_frames: 0,
+        }
+    }
+
+    /// Records a binary frame's sequence number and counts the frames missing since the
+    /// previous one. The sequence number is shared by all streams, so gaps include frames of
+    /// other streams that were lost.
+    pub fn record_sequence(&mut self, sequence: u16) {
+        if let Some(last_sequence) = self.last_sequence {
+            let gap = sequence.wrapping_sub(last_sequence);
+            if gap > 1 {
+                self.dropped_frames += (gap - 1) as u32;
+            }
+        }
+        self.last_sequence = Some(sequence);
+    }
+
+    /// A short name for the kind of table, recognized from its headers.
+    pub fn kind(&self) -> &'static str {
+        let has = |header: &str| self.headers.iter().any(|h| h == header);
+        if has("Control Signal") {
+            "forward_movement"
+        } else if has("Relay Output") {
+            "relay_autotune"
+        } else if has("Error") && has("Encoder Heading") {
+            "motion_diagnostic"
+        } else if has("lr_ratio") {
+            "motor_calibration"
+        } else if has("Left Speed") {
+            "motor_characterization"
+        } else {
+            "table"
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{:03}_{}.csv", self.index, self.kind())
+    }
+
+    /// Writes the table as a CSV file in `out_dir`, with the metadata and notes as leading `#`
+    /// comment lines, and returns the file's path.
+    pub fn write_csv(&self, out_dir: &Path, source: &str) -> io::Result<PathBuf> {
+        let path = out_dir.join(self.file_name());
+        let mut out = BufWriter::new(File::create(&path)?);
+        writeln!(out, "# source: {}", source)?;
+        writeln!(out, "# table: {}", self.index)?;
+        if let Some(stream) = &self.stream {
+            writeln!(out, "# stream: {} ({})", stream.id, stream.format)?;
+            writeln!(out, "# dropped frames: {}", self.dropped_frames)?;
+        }
+        for line in &self.metadata {
+            writeln!(out, "# log: {}", line)?;
+        }
+        for line in &self.notes {
+            writeln!(out, "# note: {}", line)?;
+        }
+        let headers: Vec<String> = self.headers.iter().map(|h| csv_field(h)).collect();
+        writeln!(out, "{}", headers.join(","))?;
+        for row in &self.rows {
+            let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+            writeln!(out, "{}", fields.join(","))?;
+        }
+        out.flush()?;
+        Ok(path)
+    }
+
+    fn column(&self, header: &str) -> Option<Vec<f64>> {
+        let index = self.headers.iter().position(|h| h == header)?;
+        Some(
+            self.rows
+                .iter()
+                .filter_map(|row| row.get(index)?.trim().parse::<f64>().ok())
+                .collect(),
+        )
+    }
+
+    /// Finds a `<label> = <value>` number in the metadata, such as the target wheel tick count.
+    fn metadata_value(&self, label: &str) -> Option<f64> {
+        self.metadata.iter().rev().find_map(|line| {
+            let start = line.find(label)? + label.len();
+            let rest = line[start..].trim_start().strip_prefix('=')?.trim_start();
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+                .unwrap_or(rest.len());
+            rest[..end].parse().ok()
+        })
+    }
+
+    /// Summary statistics of the table, one line each.
+    pub fn summary(&self) -> Vec<String> {
+        let mut summary = Vec::new();
+        match self.column("millis").as_deref() {
+            Some([first, .., last]) => summary.push(format!(
+                "{} rows over {} ms",
+                self.rows.len(),
+                last - first
+            )),
+            _ => summary.push(format!("{} rows", self.rows.len())),
+        }
+
+        // the heading setpoint is straight ahead, so the gyro heading is the heading error
+        if let Some(headings) = self.column("Gyro Heading") {
+            if let Some(last) = headings.last() {
+                summary.push(format!(
+                    "final heading error: {:.4} rad ({:.2} deg)",
+                    last,
+                    last.to_degrees()
+                ));
+            }
+            if let Some(peak) = headings.iter().map(|h| h.abs()).reduce(f64::max) {
+                summary.push(format!(
+                    "peak heading error: {:.4} rad ({:.2} deg)",
+                    peak,
+                    peak.to_degrees()
+                ));
+            }
+        }
+
+        if let (Some(target), Some(left), Some(right)) = (
+            self.metadata_value("Target wheel tick count"),
+            self.column("Left Wheel Counter"),
+            self.column("Right Wheel Counter"),
+        ) {
+            if let (Some(left), Some(right)) = (left.last(), right.last()) {
+                let overshoot = (left + right) / 2.0 - target;
+                summary.push(format!(
+                    "overshoot: {:.1} ticks past the target of {} ticks",
+                    overshoot, target
+                ));
+            }
+        }
+
+        if let Some(signals) = self.column("Control Signal") {
+            if !signals.is_empty() {
+                let effort = signals.iter().map(|s| s.abs()).sum::<f64>() / signals.len() as f64;
+                summary.push(format!("mean control effort: {:.3}", effort));
+            }
+        }
+
+        if self.stream.is_some() {
+            summary.push(format!("dropped frames: {}", self.dropped_frames));
+        }
+        summary
+    }
+}
+
+/// Quotes a CSV field if it contains a comma, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn forward_movement_table() -> Table {
+        let headers = strings(&[
+            "millis",
+            "Gyro Heading",
+            "Control Signal",
+            "Left Wheel Counter",
+            "Right Wheel Counter",
+        ]);
+        let metadata = strings(&["Starting movement", "Target wheel tick count = 100"]);
+        let mut table = Table::new(3, headers, metadata, None);
+        table.rows = vec![
+            strings(&["1000", "0.0", "-2.0", "0", "0"]),
+            strings(&["1075", "-0.05", "4.0", "55", "52"]),
+            strings(&["1150", "0.02", "0.0", "104", "102"]),
+        ];
+        table
+    }
+
+    #[test]
+    fn counts_dropped_frames_from_sequence_gaps() {
+        let stream = StreamInfo {
+            id: 1,
+            format: "If".to_string(),
+        };
+        let mut table = Table::new(0, strings(&["millis"]), Vec::new(), Some(stream));
+        table.record_sequence(10);
+        table.record_sequence(11);
+        assert_eq!(table.dropped_frames, 0);
+        table.record_sequence(14);
+        assert_eq!(table.dropped_frames, 2);
+        // the sequence number wraps around
+        table.record_sequence(65534);
+        table.dropped_frames = 0;
+        table.record_sequence(1);
+        assert_eq!(table.dropped_frames, 2);
+    }
+
+    #[test]
+    fn recognizes_the_table_kind() {
+        let table = forward_movement_table();
+        assert_eq!(table.kind(), "forward_movement");
+        assert_eq!(table.file_name(), "003_forward_movement.csv");
+
+        let headers = strings(&["millis", "Error", "Encoder Heading", "Gyro Heading"]);
+        let table = Table::new(0, headers, Vec::new(), None);
+        assert_eq!(table.kind(), "motion_diagnostic");
+        let table = Table::new(0, strings(&["millis", "Voltage"]), Vec::new(), None);
+        assert_eq!(table.kind(), "table");
+    }
+
+    #[test]
+    fn summarizes_a_movement() {
+        let summary = forward_movement_table().summary();
+        assert_eq!(
+            summary,
+            [
+                "3 rows over 150 ms",
+                "final heading error: 0.0200 rad (1.15 deg)",
+                "peak heading error: 0.0500 rad (2.86 deg)",
+                "overshoot: 3.0 ticks past the target of 100 ticks",
+                "mean control effort: 2.000",
+            ]
+        );
+    }
+
+    #[test]
+    fn summarizes_a_binary_table_without_rows() {
+        let stream = StreamInfo {
+            id: 2,
+            format: "Iff".to_string(),
+        };
+        let mut table = Table::new(0, strings(&["millis"]), Vec::new(), Some(stream));
+        table.dropped_frames = 4;
+        assert_eq!(table.summary(), ["0 rows", "dropped frames: 4"]);
+    }
+
+    #[test]
+    fn quotes_csv_fields_only_when_needed() {
+        assert_eq!(csv_field("1.5"), "1.5");
+        assert_eq!(csv_field("Left Speed"), "Left Speed");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}